@@ -1,14 +1,25 @@
-use eframe::egui::{Color32, ColorImage, Key, TextureFilter, TextureOptions};
+use eframe::egui::{Color32, ColorImage, Key, TextureFilter, TextureHandle, TextureOptions};
 use eframe::{egui, CreationContext, Storage};
 use gilrs::{Event, EventType, Gilrs};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::fs::File;
-use std::{fs, iter};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
-use crate::emulator::Emulator;
+use crate::emulator::{AudioSink, Emulator, VideoSink, FRAME_HEIGHT, FRAME_WIDTH};
+use crate::labels::{Label, LabelAddress, Labels};
+use crate::nes::breakpoints::expression::{Expression, LogMessage};
+use crate::nes::breakpoints::{Breakpoint, BreakpointKind};
+use crate::nes::cartridge::LoadError;
+pub use crate::nes::controller::NesButtonState;
+use crate::nes::ppu::palette::{BuiltInPalette, Palette, PaletteError};
+use crate::nes::Nes;
+use crate::save_state;
+use crate::saves::{self, BatterySave, SAVE_STATE_SLOTS};
 use crate::setup;
+use crate::ui::{ChrViewer, EventViewer, MemoryViewer, NametableViewer, OamViewer};
 use crate::widgets::input_select::Input;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +29,7 @@ pub struct ControllerConfig {
 }
 
 #[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct InputMapping {
     pub up: Input,
     pub down: Input,
@@ -30,17 +42,11 @@ pub struct InputMapping {
     pub pause: Input,
     pub rewind: Input,
     pub fast_forward: Input,
-}
-
-pub struct NesButtonState {
-    pub up: bool,
-    pub down: bool,
-    pub left: bool,
-    pub right: bool,
-    pub b: bool,
-    pub a: bool,
-    pub start: bool,
-    pub select: bool,
+    pub save_state: Input,
+    pub load_state: Input,
+    pub next_save_slot: Input,
+    pub reset: Input,
+    pub power_cycle: Input,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -49,6 +55,15 @@ pub struct PersistentData {
     pub keyboard_input_mapping: (InputMapping, InputMapping),
     pub controllers_input_mapping: HashMap<Uuid, ControllerConfig>,
     pub selected_controllers: (Option<Uuid>, Option<Uuid>),
+    #[serde(default)]
+    pub saves_directory: Option<PathBuf>,
+    // Left as None to use the emulator's defaults
+    #[serde(default)]
+    pub rewind_seconds: Option<f64>,
+    #[serde(default)]
+    pub rewind_megabytes: Option<f64>,
+    #[serde(default)]
+    pub palette: PaletteChoice,
 }
 
 impl Default for PersistentData {
@@ -58,18 +73,281 @@ impl Default for PersistentData {
             keyboard_input_mapping: (InputMapping::default(), InputMapping::default()),
             controllers_input_mapping: HashMap::new(),
             selected_controllers: (None, None),
+            saves_directory: None,
+            rewind_seconds: None,
+            rewind_megabytes: None,
+            palette: PaletteChoice::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PaletteChoice {
+    BuiltIn(BuiltInPalette),
+    File(PathBuf),
+}
+
+impl Default for PaletteChoice {
+    fn default() -> Self {
+        PaletteChoice::BuiltIn(BuiltInPalette::default())
+    }
+}
+
+impl PaletteChoice {
+    pub fn load(&self) -> Result<Palette, PaletteError> {
+        match self {
+            PaletteChoice::BuiltIn(built_in) => Ok(built_in.palette()),
+            PaletteChoice::File(path) => Palette::load(path),
+        }
+    }
+}
+
+pub struct SaveSlotPreview {
+    pub timestamp: u64,
+    pub thumbnail: TextureHandle,
+}
+
+#[derive(Copy, Clone, Default, PartialEq)]
+pub enum BreakpointType {
+    #[default]
+    Execute,
+    CpuRead,
+    CpuWrite,
+    PpuRegisterRead,
+    PpuRegisterWrite,
+    VramRead,
+    VramWrite,
+    Nmi,
+    Irq,
+    Scanline,
+}
+
+impl BreakpointType {
+    pub const ALL: [BreakpointType; 10] = [
+        BreakpointType::Execute,
+        BreakpointType::CpuRead,
+        BreakpointType::CpuWrite,
+        BreakpointType::PpuRegisterRead,
+        BreakpointType::PpuRegisterWrite,
+        BreakpointType::VramRead,
+        BreakpointType::VramWrite,
+        BreakpointType::Nmi,
+        BreakpointType::Irq,
+        BreakpointType::Scanline,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BreakpointType::Execute => "Execute",
+            BreakpointType::CpuRead => "CPU read",
+            BreakpointType::CpuWrite => "CPU write",
+            BreakpointType::PpuRegisterRead => "PPU register read",
+            BreakpointType::PpuRegisterWrite => "PPU register write",
+            BreakpointType::VramRead => "VRAM read",
+            BreakpointType::VramWrite => "VRAM write",
+            BreakpointType::Nmi => "NMI",
+            BreakpointType::Irq => "IRQ",
+            BreakpointType::Scanline => "Scanline",
         }
     }
+
+    pub fn has_range(&self) -> bool {
+        matches!(
+            self,
+            BreakpointType::CpuRead
+                | BreakpointType::CpuWrite
+                | BreakpointType::VramRead
+                | BreakpointType::VramWrite
+        )
+    }
+
+    pub fn has_address(&self) -> bool {
+        !matches!(self, BreakpointType::Nmi | BreakpointType::Irq)
+    }
+}
+
+// What's been typed into the "add breakpoint" row of the CPU debugger
+#[derive(Default)]
+pub struct BreakpointEditor {
+    pub breakpoint_type: BreakpointType,
+    pub start: String,
+    pub end: String,
+    pub condition: String,
+    pub break_on_hit: String,
+    pub log_message: String,
+    pub error: Option<String>,
+}
+
+// Hex, with or without a leading $
+fn parse_hex_addr(text: &str) -> Result<u16, String> {
+    let text = text.trim();
+    u16::from_str_radix(text.strip_prefix('$').unwrap_or(text), 16)
+        .map_err(|_| format!("\"{text}\" isn't a hex address or label"))
+}
+
+impl BreakpointEditor {
+    // Addresses are hex or, for CPU addresses, a label. Scanlines are decimal.
+    pub fn breakpoint_kind(&self, labels: &Labels, nes: &Nes) -> Result<BreakpointKind, String> {
+        let parse_addr = |text: &str| match labels.address_of(nes, text.trim()) {
+            Some(addr) => Ok(addr),
+            None => parse_hex_addr(text),
+        };
+        let range = |parse_addr: &dyn Fn(&str) -> Result<u16, String>| {
+            let start = parse_addr(&self.start)?;
+            let end = match self.end.trim() {
+                "" => start,
+                end => parse_addr(end)?,
+            };
+            if start <= end {
+                Ok((start, end))
+            } else {
+                Err("The end of the range is before the start".to_owned())
+            }
+        };
+        let ppu_register = || match parse_addr(&self.start)? {
+            reg @ 0x2000..=0x2007 => Ok(reg),
+            _ => Err("PPU registers are $2000-$2007".to_owned()),
+        };
+
+        Ok(match self.breakpoint_type {
+            BreakpointType::Execute => BreakpointKind::Execute(parse_addr(&self.start)?),
+            BreakpointType::CpuRead => {
+                let (start, end) = range(&parse_addr)?;
+                BreakpointKind::CpuRead { start, end }
+            }
+            BreakpointType::CpuWrite => {
+                let (start, end) = range(&parse_addr)?;
+                BreakpointKind::CpuWrite { start, end }
+            }
+            BreakpointType::PpuRegisterRead => BreakpointKind::PpuRegisterRead(ppu_register()?),
+            BreakpointType::PpuRegisterWrite => BreakpointKind::PpuRegisterWrite(ppu_register()?),
+            BreakpointType::VramRead => {
+                let (start, end) = range(&parse_hex_addr)?;
+                BreakpointKind::VramRead { start, end }
+            }
+            BreakpointType::VramWrite => {
+                let (start, end) = range(&parse_hex_addr)?;
+                BreakpointKind::VramWrite { start, end }
+            }
+            BreakpointType::Nmi => BreakpointKind::Nmi,
+            BreakpointType::Irq => BreakpointKind::Irq,
+            BreakpointType::Scanline => match self.start.trim().parse() {
+                Ok(scanline @ -1..=260) => BreakpointKind::Scanline(scanline),
+                _ => return Err("Scanlines are -1 (pre-render) to 260".to_owned()),
+            },
+        })
+    }
+
+    // Empty fields are left off, a log message makes it a log point
+    pub fn breakpoint(&self, labels: &Labels, nes: &Nes) -> Result<Breakpoint, String> {
+        let mut breakpoint = Breakpoint::new(self.breakpoint_kind(labels, nes)?);
+        if !self.condition.trim().is_empty() {
+            breakpoint.condition = Some(
+                Expression::parse(&self.condition).map_err(|err| format!("Condition: {err}"))?,
+            );
+        }
+        breakpoint.break_on_hit = match self.break_on_hit.trim() {
+            "" => 0,
+            hit => hit
+                .parse()
+                .map_err(|_| format!("\"{hit}\" isn't a hit count"))?,
+        };
+        if !self.log_message.is_empty() {
+            breakpoint.log_message = Some(
+                LogMessage::parse(&self.log_message)
+                    .map_err(|err| format!("Log message: {err}"))?,
+            );
+        }
+        Ok(breakpoint)
+    }
+}
+
+// What's been typed into the "add label" row of the CPU debugger
+#[derive(Default)]
+pub struct LabelEditor {
+    pub address: String,
+    pub name: String,
+    pub comment: String,
+    pub error: Option<String>,
+}
+
+impl LabelEditor {
+    pub fn label(&self, nes: &Nes) -> Result<(LabelAddress, Label), String> {
+        let addr = parse_hex_addr(&self.address)?;
+        let name = self.name.trim();
+        let valid_name = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+            && !name.starts_with(|c: char| c.is_ascii_digit());
+        if !valid_name {
+            return Err(
+                "Labels are letters, numbers, _ and @, and can't start with a number".into(),
+            );
+        }
+        if name.is_empty() && self.comment.is_empty() {
+            return Err("A label needs a name or a comment".to_owned());
+        }
+        let label = Label {
+            name: name.to_owned(),
+            comment: self.comment.clone(),
+        };
+        Ok((LabelAddress::from_cpu(nes, addr), label))
+    }
+}
+
+impl VideoSink for TextureHandle {
+    fn present_frame(&mut self, frame: &[u8]) {
+        self.set(
+            ColorImage::from_rgba_unmultiplied([FRAME_WIDTH, FRAME_HEIGHT], frame),
+            TextureOptions {
+                magnification: TextureFilter::Nearest,
+                minification: TextureFilter::Nearest,
+                wrap_mode: Default::default(),
+            },
+        );
+    }
 }
 
 pub struct App {
     pub emulator: Emulator,
+    pub screen_texture: TextureHandle,
+    pub rom_path: Option<PathBuf>,
     pub show_cpu_debugger: bool,
+    pub show_memory_viewer: bool,
+    pub show_nametable_viewer: bool,
+    pub show_chr_viewer: bool,
+    pub show_palette_viewer: bool,
+    pub show_oam_viewer: bool,
+    pub show_event_viewer: bool,
     pub show_controller_config: bool,
+    pub load_error: Option<LoadError>,
+    pub battery_save: Option<BatterySave>,
+    pub saves_directory: Option<PathBuf>,
+    pub palette: PaletteChoice,
+    pub save_state_slot: usize,
+    pub save_state_error: Option<String>,
+    pub save_slot_previews: HashMap<usize, Option<SaveSlotPreview>>,
+    pub status_message: Option<(String, f64)>,
+    pub breakpoint_editor: BreakpointEditor,
+    pub labels: Labels,
+    pub label_editor: LabelEditor,
+    pub memory_viewer: MemoryViewer,
+    pub nametable_viewer: NametableViewer,
+    pub chr_viewer: ChrViewer,
+    pub oam_viewer: OamViewer,
+    pub event_viewer: EventViewer,
+    // Sprite to outline on the screen, while the OAM viewer has the mouse over it
+    pub highlighted_sprite: Option<usize>,
+    // Index of the breakpoint that last paused the emulator
+    pub last_breakpoint_hit: Option<usize>,
+    pub scroll_debugger_to_pc: bool,
+    // Disassembly row picked for "Run to Cursor"
+    pub debugger_cursor: Option<u16>,
     pub controllers_input_mapping: HashMap<Uuid, ControllerConfig>,
     pub keyboard_input_mapping: (InputMapping, InputMapping),
     pub selected_controllers: (Option<Uuid>, Option<Uuid>),
     pub held_input: HashSet<Input>,
+    pub gamepad_held_input: HashMap<Uuid, HashSet<Input>>,
     pub pressed_input: HashSet<Input>,
     pub is_paused: bool,
     pub scrubbing_rate: f32,
@@ -83,7 +361,7 @@ impl App {
     pub fn new(eframe_creation_ctx: &CreationContext) -> Self {
         let screen_texture = eframe_creation_ctx.egui_ctx.load_texture(
             "emu",
-            ColorImage::new([256, 240], Color32::BLACK),
+            ColorImage::new([FRAME_WIDTH, FRAME_HEIGHT], Color32::BLACK),
             TextureOptions {
                 magnification: TextureFilter::Nearest,
                 minification: TextureFilter::Nearest,
@@ -92,7 +370,7 @@ impl App {
         );
 
         let audio_stream = match setup::create_audio_stream() {
-            Ok(stream) => Some(stream),
+            Ok(stream) => Some(Box::new(stream) as Box<dyn AudioSink>),
             Err(e) => {
                 eprintln!("Failed to create stream, emulator will have no audio output: {e}");
                 None
@@ -103,18 +381,61 @@ impl App {
 
         let persistent_state = Self::read_from_config_file_or_default();
 
-        let mut emulator = Emulator::new(screen_texture, audio_stream);
+        // Texture handles are reference counted, so the emulator draws into the texture the UI shows
+        let mut emulator = Emulator::new(Some(Box::new(screen_texture.clone())), audio_stream);
         emulator.get_set_volume(Some(persistent_state.volume));
+        emulator.get_set_rewind_seconds(persistent_state.rewind_seconds);
+        emulator.get_set_rewind_megabytes(persistent_state.rewind_megabytes);
+        // A palette file that's been moved or deleted shouldn't stop the emulator from starting
+        let palette = match persistent_state.palette.load() {
+            Ok(palette) => {
+                emulator.set_palette(palette);
+                persistent_state.palette
+            }
+            Err(err) => {
+                eprintln!("Failed to load palette, using the default: {err}");
+                PaletteChoice::default()
+            }
+        };
 
         Self {
             emulator,
+            screen_texture,
+            rom_path: None,
             show_cpu_debugger: false,
+            show_memory_viewer: false,
+            show_nametable_viewer: false,
+            show_chr_viewer: false,
+            show_palette_viewer: false,
+            show_oam_viewer: false,
+            show_event_viewer: false,
             show_controller_config: false,
+            load_error: None,
+            battery_save: None,
+            saves_directory: persistent_state.saves_directory,
+            palette,
+            save_state_slot: 0,
+            save_state_error: None,
+            save_slot_previews: HashMap::new(),
+            status_message: None,
+            breakpoint_editor: BreakpointEditor::default(),
+            labels: Labels::default(),
+            label_editor: LabelEditor::default(),
+            memory_viewer: MemoryViewer::default(),
+            nametable_viewer: NametableViewer::default(),
+            chr_viewer: ChrViewer::default(),
+            oam_viewer: OamViewer::default(),
+            event_viewer: EventViewer::default(),
+            highlighted_sprite: None,
+            last_breakpoint_hit: None,
+            debugger_cursor: None,
+            scroll_debugger_to_pc: false,
             gilrs: Gilrs::new().unwrap(),
             keyboard_input_mapping: persistent_state.keyboard_input_mapping,
             controllers_input_mapping: persistent_state.controllers_input_mapping,
             selected_controllers: persistent_state.selected_controllers,
             held_input: HashSet::with_capacity(32),
+            gamepad_held_input: HashMap::new(),
             pressed_input: HashSet::with_capacity(32),
             is_paused: false,
             scrubbing_rate: 0.0,
@@ -140,40 +461,219 @@ impl App {
         Ok(())
     }
 
+    pub fn load_rom(&mut self, path: &Path) {
+        // Make sure the current game's progress is on disk before it gets replaced
+        self.flush_battery_save();
+
+        let loaded = setup::get_rom_from_file(path)
+            .map_err(|err| LoadError::InvalidRom(err.to_string()))
+            .and_then(|rom| self.emulator.load_game(rom));
+
+        match loaded {
+            Ok(()) => {
+                let mut battery_save = BatterySave::new(path, self.saves_directory.as_deref());
+                if self.emulator.battery_ram().is_some() {
+                    if let Some(data) = battery_save.read() {
+                        self.emulator.load_battery_ram(&data);
+                    }
+                }
+                self.battery_save = Some(battery_save);
+                self.rom_path = Some(path.to_owned());
+                self.save_slot_previews.clear();
+                self.load_labels();
+            }
+            Err(err) => self.load_error = Some(err),
+        }
+    }
+
+    pub fn flush_battery_save(&mut self) {
+        if let (Some(save), Some(ram)) = (self.battery_save.as_mut(), self.emulator.battery_ram()) {
+            if let Err(err) = save.flush(ram) {
+                eprintln!("Couldn't write save file {}: {err}", save.path().display());
+            }
+        }
+    }
+
+    fn labels_path(&self) -> Option<PathBuf> {
+        let rom_path = self.rom_path.as_ref()?;
+        Some(saves::labels_path(
+            rom_path,
+            self.saves_directory.as_deref(),
+        ))
+    }
+
+    // The game's own labels replace the last game's, there may not be any yet
+    fn load_labels(&mut self) {
+        self.labels = Labels::default();
+        let Some(path) = self.labels_path().filter(|path| path.exists()) else {
+            return;
+        };
+        if let Err(err) = self.labels.import(&path) {
+            eprintln!("Couldn't read labels {}: {err}", path.display());
+        }
+    }
+
+    pub fn set_palette(&mut self, choice: PaletteChoice, time: f64) {
+        match choice.load() {
+            Ok(palette) => {
+                self.emulator.set_palette(palette);
+                self.palette = choice;
+            }
+            Err(err) => self.status_message = Some((err.to_string(), time)),
+        }
+    }
+
+    // Called whenever the labels are changed
+    pub fn save_labels(&mut self, time: f64) {
+        let Some(path) = self.labels_path() else {
+            return;
+        };
+        if let Err(err) = self.labels.save(&path) {
+            let message = format!("Couldn't write labels {}: {err}", path.display());
+            self.status_message = Some((message, time));
+        }
+    }
+
+    pub fn save_state_path(&self, slot: usize) -> Option<PathBuf> {
+        let rom_path = self.rom_path.as_ref()?;
+        Some(saves::save_state_path(
+            rom_path,
+            self.saves_directory.as_deref(),
+            slot,
+        ))
+    }
+
+    pub fn save_state_to_slot(&mut self, slot: usize, time: f64) {
+        let (Some(path), Some(data)) = (self.save_state_path(slot), self.emulator.save_state())
+        else {
+            return;
+        };
+        match saves::write_save_state(&path, &data) {
+            Ok(()) => self.status_message = Some((format!("Saved state to slot {slot}"), time)),
+            Err(err) => {
+                self.save_state_error = Some(format!("Couldn't write {}: {err}", path.display()))
+            }
+        }
+        self.save_slot_previews.remove(&slot);
+    }
+
+    pub fn load_state_from_slot(&mut self, slot: usize, time: f64) {
+        let Some(path) = self.save_state_path(slot) else {
+            return;
+        };
+        let loaded = fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|data| {
+                self.emulator
+                    .load_state(&data)
+                    .map_err(|err| err.to_string())
+            });
+        match loaded {
+            Ok(()) => self.status_message = Some((format!("Loaded state from slot {slot}"), time)),
+            Err(err) => self.save_state_error = Some(format!("Couldn't load slot {slot}: {err}")),
+        }
+    }
+
+    // Reads the header of a slot's save state, cached so the file isn't read every frame
+    pub fn save_slot_preview(
+        &mut self,
+        ctx: &egui::Context,
+        slot: usize,
+    ) -> Option<&SaveSlotPreview> {
+        let path = self.save_state_path(slot);
+        self.save_slot_previews
+            .entry(slot)
+            .or_insert_with(|| {
+                let data = fs::read(path?).ok()?;
+                let header = save_state::decode_header(&data).ok()?;
+                Some(SaveSlotPreview {
+                    timestamp: header.timestamp,
+                    thumbnail: ctx.load_texture(
+                        format!("save-slot-{slot}"),
+                        Self::decode_thumbnail(&header.thumbnail),
+                        TextureOptions::LINEAR,
+                    ),
+                })
+            })
+            .as_ref()
+    }
+
+    fn decode_thumbnail(png: &[u8]) -> ColorImage {
+        match image::load_from_memory(png) {
+            Ok(image) => {
+                let image = image.to_rgba8();
+                ColorImage::from_rgba_unmultiplied(
+                    [image.width() as usize, image.height() as usize],
+                    image.as_raw(),
+                )
+            }
+            Err(_) => ColorImage::new(
+                [
+                    save_state::THUMBNAIL_WIDTH as usize,
+                    save_state::THUMBNAIL_HEIGHT as usize,
+                ],
+                Color32::BLACK,
+            ),
+        }
+    }
+
+    fn nes_button_state(&self, keyboard: &InputMapping, gamepad: Option<Uuid>) -> NesButtonState {
+        let gamepad = gamepad.and_then(|id| {
+            Some((
+                &self.controllers_input_mapping.get(&id)?.input_mapping,
+                self.gamepad_held_input.get(&id)?,
+            ))
+        });
+        let held = |button: fn(&InputMapping) -> Input| {
+            button(keyboard).specified_and(|i| self.held_input.contains(&i))
+                || gamepad.is_some_and(|(mapping, held)| held.contains(&button(mapping)))
+        };
+        NesButtonState {
+            up: held(|m| m.up),
+            down: held(|m| m.down),
+            left: held(|m| m.left),
+            right: held(|m| m.right),
+            b: held(|m| m.b),
+            a: held(|m| m.a),
+            start: held(|m| m.start),
+            select: held(|m| m.select),
+        }
+    }
+
     pub fn get_pressed_input(&mut self, ctx: &egui::Context) {
-        // TODO: Only process selected controller with UUID
         self.pressed_input.clear();
-        for event in iter::from_fn(|| self.gilrs.next_event()) {
-            match event {
-                Event {
-                    event: EventType::ButtonPressed(button, _),
-                    ..
-                } => {
-                    self.held_input.insert(Input::ControllerButton(button));
-                    self.pressed_input.insert(Input::ControllerButton(button));
-                }
-                Event {
-                    event: EventType::ButtonReleased(button, _),
-                    ..
-                } => {
-                    self.held_input.remove(&Input::ControllerButton(button));
-                }
-                Event {
-                    event: EventType::AxisChanged(axis, position, _),
-                    ..
-                } => {
-                    if (-1.0..=-AXIS_DEADZONE).contains(&position) {
-                        self.held_input.remove(&Input::ControllerAxis(axis, true));
-                        self.held_input.insert(Input::ControllerAxis(axis, false));
-                    } else if (AXIS_DEADZONE..=1.0).contains(&position) {
-                        self.held_input.remove(&Input::ControllerAxis(axis, false));
-                        self.held_input.insert(Input::ControllerAxis(axis, true));
-                    } else if (-AXIS_DEADZONE..=AXIS_DEADZONE).contains(&position) {
-                        self.held_input.remove(&Input::ControllerAxis(axis, false));
-                        self.held_input.remove(&Input::ControllerAxis(axis, true));
+        while let Some(Event { id, event, .. }) = self.gilrs.next_event() {
+            if let EventType::ButtonPressed(button, _) = event {
+                self.pressed_input.insert(Input::ControllerButton(button));
+            }
+
+            // Each gamepad's input is also tracked separately so two players can use gamepads
+            // with the same bindings without pressing each other's buttons
+            let uuid = Uuid::from_slice(&self.gilrs.gamepad(id).uuid()).unwrap();
+            let gamepad_held_input = self.gamepad_held_input.entry(uuid).or_default();
+
+            for held_input in [&mut self.held_input, gamepad_held_input] {
+                match event {
+                    EventType::ButtonPressed(button, _) => {
+                        held_input.insert(Input::ControllerButton(button));
+                    }
+                    EventType::ButtonReleased(button, _) => {
+                        held_input.remove(&Input::ControllerButton(button));
                     }
+                    EventType::AxisChanged(axis, position, _) => {
+                        if (-1.0..=-AXIS_DEADZONE).contains(&position) {
+                            held_input.remove(&Input::ControllerAxis(axis, true));
+                            held_input.insert(Input::ControllerAxis(axis, false));
+                        } else if (AXIS_DEADZONE..=1.0).contains(&position) {
+                            held_input.remove(&Input::ControllerAxis(axis, false));
+                            held_input.insert(Input::ControllerAxis(axis, true));
+                        } else if (-AXIS_DEADZONE..=AXIS_DEADZONE).contains(&position) {
+                            held_input.remove(&Input::ControllerAxis(axis, false));
+                            held_input.remove(&Input::ControllerAxis(axis, true));
+                        }
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
         }
 
@@ -255,26 +755,36 @@ impl eframe::App for App {
             self.scrubbing_rate = 1.0;
         }
 
-        let nes_button_state = NesButtonState {
-            up: k_con1.up.specified_and(|i| self.held_input.contains(&i))
-                || c_con1.is_some_and(|c| self.held_input.contains(&c.input_mapping.up)),
-            down: k_con1.down.specified_and(|i| self.held_input.contains(&i))
-                || c_con1.is_some_and(|c| self.held_input.contains(&c.input_mapping.down)),
-            left: k_con1.left.specified_and(|i| self.held_input.contains(&i))
-                || c_con1.is_some_and(|c| self.held_input.contains(&c.input_mapping.left)),
-            right: k_con1.right.specified_and(|i| self.held_input.contains(&i))
-                || c_con1.is_some_and(|c| self.held_input.contains(&c.input_mapping.right)),
-            b: k_con1.b.specified_and(|i| self.held_input.contains(&i))
-                || c_con1.is_some_and(|c| self.held_input.contains(&c.input_mapping.b)),
-            a: k_con1.a.specified_and(|i| self.held_input.contains(&i))
-                || c_con1.is_some_and(|c| self.held_input.contains(&c.input_mapping.a)),
-            start: k_con1.start.specified_and(|i| self.held_input.contains(&i))
-                || c_con1.is_some_and(|c| self.held_input.contains(&c.input_mapping.start)),
-            select: k_con1
-                .select
-                .specified_and(|i| self.held_input.contains(&i))
-                || c_con1.is_some_and(|c| self.held_input.contains(&c.input_mapping.select)),
+        let time = ctx.input(|input| input.time);
+        let hotkey_pressed = |select: fn(&InputMapping) -> Input| {
+            select(&k_con1).specified_and(|i| self.pressed_input.contains(&i))
+                || c_con1.is_some_and(|c| self.pressed_input.contains(&select(&c.input_mapping)))
         };
+        let save_pressed = hotkey_pressed(|m| m.save_state);
+        let load_pressed = hotkey_pressed(|m| m.load_state);
+        let next_slot_pressed = hotkey_pressed(|m| m.next_save_slot);
+        let reset_pressed = hotkey_pressed(|m| m.reset);
+        let power_cycle_pressed = hotkey_pressed(|m| m.power_cycle);
+        let con1_button_state = self.nes_button_state(&k_con1, self.selected_controllers.0);
+        let con2_button_state =
+            self.nes_button_state(&self.keyboard_input_mapping.1, self.selected_controllers.1);
+
+        if next_slot_pressed {
+            self.save_state_slot = (self.save_state_slot + 1) % SAVE_STATE_SLOTS;
+            self.status_message = Some((format!("Save slot {}", self.save_state_slot), time));
+        }
+        if save_pressed {
+            self.save_state_to_slot(self.save_state_slot, time);
+        }
+        if load_pressed {
+            self.load_state_from_slot(self.save_state_slot, time);
+        }
+        if reset_pressed {
+            self.emulator.reset();
+        }
+        if power_cycle_pressed {
+            self.emulator.power_cycle();
+        }
 
         self.define_main_top_panel(ctx);
         self.define_main_bottom_panel(ctx);
@@ -283,23 +793,80 @@ impl eframe::App for App {
         self.emulator.get_set_pause(Some(self.is_paused));
         self.emulator.scrub_by(self.scrubbing_rate);
 
-        self.emulator.update_controller(1, nes_button_state);
+        self.emulator.update_controller(1, con1_button_state);
+        self.emulator.update_controller(2, con2_button_state);
         self.emulator.update(ctx.input(|input| input.time));
 
+        if self.emulator.take_run_target_reached().is_some() {
+            self.is_paused = true;
+            self.scroll_debugger_to_pc = true;
+        }
+        if let Some(index) = self.emulator.take_breakpoint_hit() {
+            self.is_paused = true;
+            self.last_breakpoint_hit = Some(index);
+            self.scroll_debugger_to_pc = true;
+            if let Some(breakpoint) = self
+                .emulator
+                .breakpoints()
+                .and_then(|bps| bps.list.get(index))
+            {
+                self.status_message = Some((format!("Hit breakpoint: {}", breakpoint.kind), time));
+            }
+        }
+
+        if let (Some(save), Some(ram)) = (self.battery_save.as_mut(), self.emulator.battery_ram()) {
+            if let Err(err) = save.flush_if_due(ram, ctx.input(|input| input.time)) {
+                eprintln!("Couldn't write save file {}: {err}", save.path().display());
+            }
+        }
+
         if self.show_cpu_debugger {
             self.define_cpu_debugger(ctx);
         }
+        if self.show_memory_viewer {
+            self.define_memory_viewer(ctx);
+        }
+        if self.show_nametable_viewer {
+            self.define_nametable_viewer(ctx);
+        }
+        if self.show_chr_viewer {
+            self.define_chr_viewer(ctx);
+        }
+        if self.show_palette_viewer {
+            self.define_palette_viewer(ctx);
+        }
+        if self.show_oam_viewer {
+            self.define_oam_viewer(ctx);
+        }
+        if self.show_event_viewer {
+            self.define_event_viewer(ctx);
+        } else if self.emulator.event_log().is_some() {
+            // Logging every event slows emulation down, so it only runs while the viewer is open
+            self.emulator.stop_event_log();
+        }
         if self.show_controller_config {
             self.define_controller_config(ctx);
         }
+        if self.load_error.is_some() {
+            self.define_load_error_dialog(ctx);
+        }
+        if self.save_state_error.is_some() {
+            self.define_save_state_error_dialog(ctx);
+        }
     }
 
     fn save(&mut self, _storage: &mut dyn Storage) {
+        self.flush_battery_save();
+
         let new_config = PersistentData {
             controllers_input_mapping: self.controllers_input_mapping.clone(),
             keyboard_input_mapping: self.keyboard_input_mapping,
             volume: self.emulator.get_set_volume(None),
             selected_controllers: self.selected_controllers,
+            saves_directory: self.saves_directory.clone(),
+            rewind_seconds: Some(self.emulator.get_set_rewind_seconds(None)),
+            rewind_megabytes: Some(self.emulator.get_set_rewind_megabytes(None)),
+            palette: self.palette.clone(),
         };
         Self::write_to_config_file(&new_config)
             .unwrap_or_else(|err| eprintln!("Couldn't save config state"));
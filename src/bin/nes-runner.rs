@@ -0,0 +1,232 @@
+use image::RgbaImage;
+use nes_emu_egui::emulator::{Emulator, FrameInput, FRAME_HEIGHT, FRAME_WIDTH};
+use nes_emu_egui::nes::controller::NesButtonState;
+use nes_emu_egui::nes::cpu::trace::CpuTracer;
+use nes_emu_egui::setup::get_rom_from_file;
+use std::error::Error;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/*
+    Runs a ROM without a window for a fixed number of frames, then writes out whatever was asked
+    for and prints a hash of the final emulator state. Meant for smoke testing a ROM collection.
+
+    Exits with 1 for bad arguments or files that couldn't be read/written, and 2 if the ROM couldn't
+    be loaded (bad header, unsupported mapper).
+
+    Input scripts have one line per change in input, buttons are held until the next line:
+
+    # frame  player 1     player 2 (optional)
+    0        -
+    120      start
+    130      right,a      left
+*/
+
+const USAGE: &str = "\
+Usage: nes-runner <rom> [options]
+
+Options:
+    --frames <n>     Number of frames to run (default 600)
+    --input <file>   Input script to play back
+    --png <file>     Write a screenshot of the last frame
+    --wav <file>     Write the audio from the whole run
+    --trace <file>   Write a nestest-style CPU trace";
+
+const DEFAULT_FRAMES: u64 = 600;
+
+struct Args {
+    rom: PathBuf,
+    frames: u64,
+    input: Option<PathBuf>,
+    png: Option<PathBuf>,
+    wav: Option<PathBuf>,
+    trace: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    if std::env::args().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            return ExitCode::from(1);
+        }
+    };
+
+    let mut emulator = Emulator::new(None, None);
+    let loaded = get_rom_from_file(&args.rom)
+        .map_err(|err| err.to_string())
+        .and_then(|rom| emulator.load_game(rom).map_err(|err| err.to_string()));
+    if let Err(err) = loaded {
+        eprintln!("Couldn't load {}: {err}", args.rom.display());
+        return ExitCode::from(2);
+    }
+
+    match run(&args, &mut emulator) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(1)
+        }
+    }
+}
+
+fn run(args: &Args, emulator: &mut Emulator) -> Result<(), Box<dyn Error>> {
+    let script = match args.input.as_ref() {
+        Some(path) => parse_input_script(&fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+    if let Some(path) = args.trace.as_ref() {
+        emulator.start_trace(CpuTracer::to_file(path)?);
+    }
+
+    let mut input = FrameInput::default();
+    let mut script_pos = 0;
+    let mut audio = Vec::new();
+    let mut video = Vec::new();
+
+    for frame in 0..args.frames {
+        while script_pos < script.len() && script[script_pos].0 <= frame {
+            input = script[script_pos].1;
+            script_pos += 1;
+        }
+        let output = emulator.run_frame(input);
+        if args.wav.is_some() {
+            audio.extend(output.audio);
+        }
+        video = output.video;
+    }
+
+    if let Some(mut tracer) = emulator.stop_trace() {
+        if let Some(err) = tracer.error() {
+            return Err(format!("Couldn't write trace: {err}").into());
+        }
+        tracer.flush()?;
+    }
+    if let Some(path) = args.png.as_ref() {
+        RgbaImage::from_raw(FRAME_WIDTH as u32, FRAME_HEIGHT as u32, video)
+            .ok_or("Frame buffer has the wrong size")?
+            .save(path)?;
+    }
+    if let Some(path) = args.wav.as_ref() {
+        write_wav(path, &audio, emulator.sample_rate() as u32)?;
+    }
+
+    let hash = emulator.state_hash().ok_or("No game loaded")?;
+    println!("State hash: {hash:08x}");
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut rom = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut input = None;
+    let mut png = None;
+    let mut wav = None;
+    let mut trace = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--frames" => {
+                frames = value()?
+                    .parse()
+                    .map_err(|_| "--frames must be a number".to_owned())?
+            }
+            "--input" => input = Some(PathBuf::from(value()?)),
+            "--png" => png = Some(PathBuf::from(value()?)),
+            "--wav" => wav = Some(PathBuf::from(value()?)),
+            "--trace" => trace = Some(PathBuf::from(value()?)),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument {arg}")),
+        }
+    }
+
+    Ok(Args {
+        rom: rom.ok_or("No ROM given")?,
+        frames,
+        input,
+        png,
+        wav,
+        trace,
+    })
+}
+
+fn parse_input_script(script: &str) -> Result<Vec<(u64, FrameInput)>, String> {
+    let mut entries = Vec::new();
+    for (line_number, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |reason: String| format!("Input script line {}: {reason}", line_number + 1);
+
+        let mut fields = line.split_whitespace();
+        let frame = fields
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|_| error("Expected a frame number".to_owned()))?;
+        let con1 = parse_buttons(fields.next().unwrap_or("-")).map_err(error)?;
+        let con2 = parse_buttons(fields.next().unwrap_or("-")).map_err(error)?;
+        entries.push((frame, FrameInput { con1, con2 }));
+    }
+    entries.sort_by_key(|(frame, _)| *frame);
+    Ok(entries)
+}
+
+fn parse_buttons(buttons: &str) -> Result<NesButtonState, String> {
+    let mut state = NesButtonState::default();
+    if buttons == "-" {
+        return Ok(state);
+    }
+    for button in buttons.split(',') {
+        match button.to_ascii_lowercase().as_str() {
+            "up" => state.up = true,
+            "down" => state.down = true,
+            "left" => state.left = true,
+            "right" => state.right = true,
+            "a" => state.a = true,
+            "b" => state.b = true,
+            "start" => state.start = true,
+            "select" => state.select = true,
+            _ => return Err(format!("Unknown button {button}")),
+        }
+    }
+    Ok(state)
+}
+
+// 16-bit stereo PCM
+fn write_wav(path: &Path, samples: &[(f32, f32)], sample_rate: u32) -> std::io::Result<()> {
+    const CHANNELS: u16 = 2;
+    const BYTES_PER_SAMPLE: u16 = 2;
+    let block_align = CHANNELS * BYTES_PER_SAMPLE;
+    let data_size = samples.len() as u32 * block_align as u32;
+
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data_size).to_le_bytes())?;
+    file.write_all(b"WAVEfmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    file.write_all(&1u16.to_le_bytes())?; // PCM
+    file.write_all(&CHANNELS.to_le_bytes())?;
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    file.write_all(&block_align.to_le_bytes())?;
+    file.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())?;
+    for (left, right) in samples {
+        for channel in [left, right] {
+            let value = (channel.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            file.write_all(&value.to_le_bytes())?;
+        }
+    }
+    file.flush()
+}
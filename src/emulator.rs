@@ -1,18 +1,29 @@
-use crate::app::NesButtonState;
-use crate::nes::cartridge::{mapper0, mapper1, mapper2, mapper3, mapper4, mapper7, Cartridge};
+use crate::nes::cartridge::{create_cartridge, LoadError};
+use crate::nes::controller::NesButtonState;
 use crate::nes::Nes;
-use eframe::egui::{ColorImage, TextureFilter, TextureHandle, TextureOptions};
+use crate::rewind::RewindBuffer;
+use crate::save_state::{self, SaveStateError, SaveStateHeader};
+use image::imageops::FilterType;
+use image::{ImageOutputFormat, RgbaImage};
 use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::Cursor;
 use std::rc::Rc;
 use std::sync::mpsc::SyncSender;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::nes::apu;
+use crate::nes::breakpoints::{self, BreakEvent, Breakpoints};
 use crate::nes::cartridge::cartridge_def::RomConfig;
+use crate::nes::cdl::CodeDataLog;
 use crate::nes::cpu;
-use crate::nes::cpu::lookup_table::INSTRUCTIONS;
+use crate::nes::cpu::lookup_table::{Name, INSTRUCTIONS};
+use crate::nes::events::EventLog;
 use crate::nes::ppu;
+use crate::nes::ppu::palette::Palette;
 
-use crate::nes::cpu::debugger::{CpuDebuggerInstruction, InstrBytes};
+use crate::nes::cpu::debugger::{CpuDebuggerInstruction, Disassembler, DisassemblyView};
+use crate::nes::cpu::trace::CpuTracer;
 
 /*
     Would be nice to create a state machine diagram to show how the program works when pausing,
@@ -26,12 +37,145 @@ use crate::nes::cpu::debugger::{CpuDebuggerInstruction, InstrBytes};
 const CPU_CYCLES_PER_FRAME: f32 = 29780.5;
 const DEFAULT_FRAMERATE: f64 = 60.0;
 const EXPONENTIAL_MOVING_AVG_BETA: f64 = 0.999;
+const DEFAULT_REWIND_SECONDS: f64 = 600.0;
+const DEFAULT_REWIND_MEGABYTES: f64 = 256.0;
+const MEGABYTE: f64 = 1024.0 * 1024.0;
+// Used when there's no audio sink to take the sample rate from
+const DEFAULT_SAMPLE_RATE: f32 = 44100.0;
+
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+
+// Where finished frames go, e.g. a texture on screen. Frames are RGBA, FRAME_WIDTH x FRAME_HEIGHT.
+pub trait VideoSink {
+    fn present_frame(&mut self, frame: &[u8]);
+}
+
+// Where audio samples go, e.g. the sound card. Samples are (left, right).
+pub trait AudioSink {
+    fn sample_rate(&self) -> f32;
+    fn push_samples(&mut self, samples: &[(f32, f32)]);
+}
 
 pub struct AudioStream {
     pub sender: SyncSender<(f32, f32)>,
     pub sample_rate: f32,
 }
 
+impl AudioSink for AudioStream {
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+    fn push_samples(&mut self, samples: &[(f32, f32)]) {
+        for sample in samples {
+            // If the audio thread has fallen behind, drop samples rather than stall the emulator
+            let _ = self.sender.try_send(*sample);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct FrameInput {
+    pub con1: NesButtonState,
+    pub con2: NesButtonState,
+}
+
+pub struct FrameOutput {
+    // RGBA, FRAME_WIDTH x FRAME_HEIGHT
+    pub video: Vec<u8>,
+    // (left, right) samples at the emulator's sample rate
+    pub audio: Vec<(f32, f32)>,
+}
+
+// Debugger commands that run the emulator until something happens. Like breakpoints, the emulator
+// only ever stops between instructions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RunTarget {
+    // Run the next instruction, a JSR runs until the subroutine it calls returns
+    StepOver,
+    // Run until the current subroutine or interrupt handler returns
+    StepOut,
+    // Run until the instruction at this address is next
+    Address(u16),
+    NextFrame,
+    NextScanline,
+    // Stop at the start of the next NMI handler
+    NextNmi,
+}
+
+// A RunTarget along with what the emulator looked like when it was given
+struct RunUntil {
+    target: RunTarget,
+    s: u8,
+    stepping_over_jsr: bool,
+    scanline: i32,
+    frames: u64,
+    // Stack pointer at the start of each interrupt handler entered since, innermost last.
+    // Stepping over/out ignores everything that happens in them so interrupts can't cut it short.
+    handlers: Vec<u8>,
+}
+
+impl RunUntil {
+    fn new(target: RunTarget, nes: &mut Nes) -> RunUntil {
+        let opcode = nes.peek(nes.cpu.pc);
+        RunUntil {
+            target,
+            s: nes.cpu.s,
+            stepping_over_jsr: INSTRUCTIONS[opcode as usize].name == Name::JSR,
+            scanline: nes.ppu.scanline,
+            frames: nes.ppu.frames,
+            handlers: Vec::new(),
+        }
+    }
+
+    // Called whenever the CPU finishes an instruction or enters an interrupt handler
+    fn reached(&mut self, nes: &Nes, entered_interrupt: bool) -> bool {
+        let cpu = &nes.cpu;
+        match self.target {
+            RunTarget::Address(addr) => return cpu.pc == addr,
+            RunTarget::NextFrame => return nes.ppu.frames != self.frames,
+            RunTarget::NextScanline => return nes.ppu.scanline != self.scanline,
+            RunTarget::NextNmi => return entered_interrupt && cpu.interrupt_vector == 0xFFFA,
+            RunTarget::StepOver | RunTarget::StepOut => {}
+        }
+
+        if entered_interrupt {
+            self.handlers.push(cpu.s);
+            return false;
+        }
+        if let Some(&handler_s) = self.handlers.last() {
+            // RTI pulls P and PC, leaving the stack above where the handler started
+            if cpu.instruction.name == Name::RTI && cpu.s > handler_s {
+                self.handlers.pop();
+            }
+            return false;
+        }
+        let returned = matches!(cpu.instruction.name, Name::RTS | Name::RTI);
+        match self.target {
+            RunTarget::StepOver => !self.stepping_over_jsr || (returned && cpu.s >= self.s),
+            RunTarget::StepOut => returned && cpu.s > self.s,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// A controller's buttons changing, at the PPU cycle the emulator was at when it happened
+#[derive(Copy, Clone)]
+struct InputChange {
+    ppu_cycle: u64,
+    controller: u8,
+    button_state: u8,
+}
+
+impl InputChange {
+    fn apply(&self, nes: &mut Nes) {
+        match self.controller {
+            1 => nes.con1.button_state = self.button_state,
+            _ => nes.con2.button_state = self.button_state,
+        }
+    }
+}
+
 pub struct Emulator {
     // The emulator isn't gonna have a NES unless it has a game cartridge
     // The cartridge is hardwired into the address bus so that seems fair
@@ -39,66 +183,327 @@ pub struct Emulator {
     target_speed: f64,
     game_speed: f64,
     paused: bool,
-    pub video_output: TextureHandle, // accessed directly in main.rs, no point in using a getter
+    video_output: Option<Box<dyn VideoSink>>,
     frame: u64,
 
     time: f64,
 
-    audio_output: Option<AudioStream>,
+    audio_output: Option<Box<dyn AudioSink>>,
+    sample_rate: f32,
+    // Samples produced since the last frame was handed out
+    audio_buffer: Vec<(f32, f32)>,
     volume: f64,
     avg_sample_rate: f64,
     cpu_cycle_at_last_sample: u64,
     cached_cycles_per_sample: f32,
     stereo_pan: f32,
     rewind_state_index: f32,
-    rewind_states: Vec<Nes>,
+    rewind_states: RewindBuffer,
+    rom_hash: u32,
+    // Kept so the cartridge can be recreated when power cycling
+    rom_config: Option<RomConfig>,
 
     nes_frame: Rc<RefCell<Vec<u8>>>,
+    palette: Rc<Palette>,
 
     pub instruction_cache: Vec<CpuDebuggerInstruction>,
+    disassembler: Disassembler,
+    disassembly_view: DisassemblyView,
+    breakpoint_hit: Option<usize>,
+    run_until: Option<RunUntil>,
+    // Rewind states have the input from the start of each frame, this has any changes made
+    // partway through (e.g. while stepping) so stepping backwards can replay them
+    input_log: VecDeque<InputChange>,
+    // Paused at a point taken from the rewind history, with more history after it
+    rewound: bool,
+    run_target_reached: Option<RunTarget>,
 }
 
 impl Emulator {
-    pub fn new(texture_handle: TextureHandle, audio_output: Option<AudioStream>) -> Self {
-        let init_cycles_per_sample = match audio_output.as_ref() {
-            Some(s) => Self::cycles_per_sample(s.sample_rate, 1.0),
-            None => 0.0,
-        };
+    pub fn new(
+        video_output: Option<Box<dyn VideoSink>>,
+        audio_output: Option<Box<dyn AudioSink>>,
+    ) -> Self {
+        let sample_rate = audio_output
+            .as_ref()
+            .map_or(DEFAULT_SAMPLE_RATE, |sink| sink.sample_rate());
 
         Emulator {
             nes: None,
             game_speed: 1.0,
             target_speed: 1.0,
             paused: false,
-            video_output: texture_handle,
+            video_output,
             audio_output,
+            sample_rate,
+            audio_buffer: Vec::new(),
             volume: 1.0,
             avg_sample_rate: 1000.0,
             cpu_cycle_at_last_sample: 0,
-            cached_cycles_per_sample: init_cycles_per_sample,
+            cached_cycles_per_sample: Self::cycles_per_sample(sample_rate, 1.0),
             stereo_pan: 0.0,
             frame: 0,
             time: 0.0,
             rewind_state_index: 0.0,
-            rewind_states: Vec::new(),
-            nes_frame: Rc::new(RefCell::new(vec![0u8; 256usize * 240 * 4])),
+            rewind_states: RewindBuffer::new(
+                (DEFAULT_REWIND_SECONDS * DEFAULT_FRAMERATE) as usize,
+                (DEFAULT_REWIND_MEGABYTES * MEGABYTE) as usize,
+            ),
+            rom_hash: 0,
+            rom_config: None,
+            nes_frame: Rc::new(RefCell::new(vec![0u8; FRAME_WIDTH * FRAME_HEIGHT * 4])),
+            palette: Rc::default(),
             instruction_cache: Vec::new(),
+            disassembler: Disassembler::default(),
+            disassembly_view: DisassemblyView::default(),
+            breakpoint_hit: None,
+            run_until: None,
+            run_target_reached: None,
+            input_log: VecDeque::new(),
+            rewound: false,
+        }
+    }
+
+    pub fn load_game(&mut self, rom_config: RomConfig) -> Result<(), LoadError> {
+        let cartridge = create_cartridge(rom_config.clone())?;
+        self.rom_config = Some(rom_config);
+        self.rom_hash = cartridge.rom_data().rom_hash();
+        self.rewind_states.clear();
+        self.rewind_state_index = 0.0;
+        self.input_log.clear();
+
+        // Breakpoints are kept so they survive reloading a rebuilt ROM
+        let breakpoints = self.nes.take().map(|nes| nes.breakpoints);
+        let mut nes = Nes::new(
+            cartridge,
+            Rc::clone(&self.nes_frame),
+            Rc::clone(&self.palette),
+        );
+        nes.breakpoints = breakpoints.unwrap_or_default();
+        self.cpu_cycle_at_last_sample = nes.cpu.cycles;
+        self.nes = Some(nes);
+        self.breakpoint_hit = None;
+        self.run_until = None;
+        self.disassembler = Disassembler::default();
+        self.disassembly_view = DisassemblyView::default();
+        self.check_reset_breakpoints();
+        self.update_prg_rom_debug_cache();
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        if let Some(nes) = self.nes.as_mut() {
+            nes.reset();
+        }
+        self.check_reset_breakpoints();
+        self.update_prg_rom_debug_cache();
+    }
+
+    // The CPU only checks execute breakpoints after an instruction or interrupt, so the one at the
+    // reset vector's target is checked here, stopping before anything runs
+    fn check_reset_breakpoints(&mut self) {
+        let Some(nes) = self.nes.as_mut() else {
+            return;
+        };
+        if nes.breakpoints.is_empty() {
+            return;
+        }
+        breakpoints::check(nes, BreakEvent::Execute(nes.cpu.pc));
+        if let Some(index) = nes.breakpoints.hit.take() {
+            self.paused = true;
+            self.breakpoint_hit = Some(index);
+            self.run_until = None;
         }
     }
 
-    pub fn load_game(&mut self, rom_config: RomConfig) {
-        let cartridge: Box<dyn Cartridge> = match rom_config.ines_mapper_id {
-            0 => Box::new(mapper0::CartridgeM0::new(rom_config)),
-            1 => Box::new(mapper1::CartridgeM1::new(rom_config)),
-            2 => Box::new(mapper2::CartridgeM2::new(rom_config)),
-            3 => Box::new(mapper3::CartridgeM3::new(rom_config)),
-            4 => Box::new(mapper4::CartridgeM4::new(rom_config)),
-            7 => Box::new(mapper7::CartridgeM7::new(rom_config)),
-            id => unimplemented!("Mapper {id} not implemented"),
+    // Like turning the console off and on again, everything except battery-backed RAM is lost
+    pub fn power_cycle(&mut self) {
+        let Some(rom_config) = self.rom_config.clone() else {
+            return;
         };
+        let battery_ram = self.battery_ram().map(<[u8]>::to_vec);
+        let cartridge = create_cartridge(rom_config).expect("ROM has already been loaded once");
+        let nes = Nes::new(
+            cartridge,
+            Rc::clone(&self.nes_frame),
+            Rc::clone(&self.palette),
+        );
+        self.cpu_cycle_at_last_sample = nes.cpu.cycles;
+        self.replace_nes(nes);
+        // The new Nes starts again from PPU cycle 0, so the input so far can't be matched up
+        self.input_log.clear();
+        if let Some(data) = battery_ram {
+            self.load_battery_ram(&data);
+        }
+        self.check_reset_breakpoints();
+        self.update_prg_rom_debug_cache();
+    }
 
-        self.nes = Some(Nes::new(cartridge, Rc::clone(&self.nes_frame)));
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.nes.as_ref()?.cart.battery_ram()
+    }
+
+    pub fn load_battery_ram(&mut self, data: &[u8]) {
+        if let Some(nes) = self.nes.as_mut() {
+            nes.cart.load_battery_ram(data);
+        }
+    }
+
+    pub fn save_state(&self) -> Option<Vec<u8>> {
+        let nes = self.nes.as_ref()?;
+        let header = SaveStateHeader {
+            emulator_version: env!("CARGO_PKG_VERSION").to_owned(),
+            rom_hash: self.rom_hash,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            thumbnail: self.thumbnail_png(),
+        };
+        Some(save_state::encode(&header, nes))
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let Some(current) = self.nes.as_ref() else {
+            return Err(SaveStateError::NoGameLoaded);
+        };
+        let mut nes = save_state::decode_nes(data, self.rom_hash)?;
+        self.reattach(&mut nes, current);
+        self.cpu_cycle_at_last_sample = nes.cpu.cycles;
+        self.replace_nes(nes);
+
+        // The rewind history belongs to the timeline that was just replaced
+        self.rewind_states.clear();
+        self.rewind_state_index = 0.0;
+        self.input_log.clear();
         self.update_prg_rom_debug_cache();
+        Ok(())
+    }
+
+    // Deserialized states don't include the ROM, frame buffer or palette, they're shared with the
+    // current Nes
+    fn reattach(&self, nes: &mut Nes, current: &Nes) {
+        nes.cart.rom_data_mut().restore_rom(current.cart.rom_data());
+        nes.frame = Some(Rc::clone(&self.nes_frame));
+        nes.palette = Rc::clone(&self.palette);
+    }
+
+    // Debugging tools stay attached when the Nes is swapped out for a different state of the
+    // same game
+    fn replace_nes(&mut self, mut nes: Nes) {
+        if let Some(old) = self.nes.take() {
+            nes.tracer = old.tracer;
+            nes.breakpoints = old.breakpoints;
+            nes.cdl = old.cdl;
+            nes.events = old.events;
+        }
+        self.nes = Some(nes);
+        self.rewound = false;
+        // Wherever it was heading is meaningless in a different state
+        self.run_until = None;
+    }
+
+    fn thumbnail_png(&self) -> Vec<u8> {
+        let frame = RgbaImage::from_raw(
+            FRAME_WIDTH as u32,
+            FRAME_HEIGHT as u32,
+            self.nes_frame.borrow().clone(),
+        )
+        .expect("Frame buffer is 256x240 RGBA");
+        let thumbnail = image::imageops::resize(
+            &frame,
+            save_state::THUMBNAIL_WIDTH,
+            save_state::THUMBNAIL_HEIGHT,
+            FilterType::Triangle,
+        );
+        let mut png = Vec::new();
+        // Encoding into memory only fails if the image dimensions are invalid
+        let _ = thumbnail.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png);
+        png
+    }
+
+    // Traces belong to the running game, loading another one ends the trace
+    pub fn start_trace(&mut self, tracer: CpuTracer) {
+        if let Some(nes) = self.nes.as_mut() {
+            nes.tracer = Some(Box::new(tracer));
+        }
+    }
+
+    pub fn stop_trace(&mut self) -> Option<Box<CpuTracer>> {
+        self.nes.as_mut()?.tracer.take()
+    }
+
+    pub fn tracer(&self) -> Option<&CpuTracer> {
+        self.nes.as_ref()?.tracer.as_deref()
+    }
+
+    // Like traces, the log is for the running game and ends when another one is loaded
+    pub fn start_code_data_log(&mut self) {
+        if let Some(nes) = self.nes.as_mut() {
+            nes.cdl = Some(Box::new(CodeDataLog::new(nes)));
+        }
+    }
+
+    pub fn stop_code_data_log(&mut self) -> Option<Box<CodeDataLog>> {
+        self.nes.as_mut()?.cdl.take()
+    }
+
+    pub fn code_data_log(&self) -> Option<&CodeDataLog> {
+        self.nes.as_ref()?.cdl.as_deref()
+    }
+
+    pub fn code_data_log_mut(&mut self) -> Option<&mut CodeDataLog> {
+        self.nes.as_mut()?.cdl.as_deref_mut()
+    }
+
+    // For the PPU event viewer, which logs while it's open
+    pub fn start_event_log(&mut self) {
+        if let Some(nes) = self.nes.as_mut() {
+            nes.events = Some(Box::default());
+        }
+    }
+
+    pub fn stop_event_log(&mut self) {
+        if let Some(nes) = self.nes.as_mut() {
+            nes.events = None;
+        }
+    }
+
+    pub fn event_log(&self) -> Option<&EventLog> {
+        self.nes.as_ref()?.events.as_deref()
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = Rc::new(palette);
+        if let Some(nes) = self.nes.as_mut() {
+            nes.palette = Rc::clone(&self.palette);
+        }
+    }
+
+    pub fn breakpoints(&self) -> Option<&Breakpoints> {
+        Some(&self.nes.as_ref()?.breakpoints)
+    }
+
+    pub fn breakpoints_mut(&mut self) -> Option<&mut Breakpoints> {
+        Some(&mut self.nes.as_mut()?.breakpoints)
+    }
+
+    // Index of the breakpoint that paused the emulator, if one has since the last call
+    pub fn take_breakpoint_hit(&mut self) -> Option<usize> {
+        self.breakpoint_hit.take()
+    }
+
+    // Unpauses until the target is reached, then pauses again. Pausing in the meantime cancels it.
+    pub fn run_until(&mut self, target: RunTarget) {
+        let Some(nes) = self.nes.as_mut() else {
+            return;
+        };
+        self.run_until = Some(RunUntil::new(target, nes));
+        self.get_set_pause(Some(false));
+    }
+
+    // The target that paused the emulator, if one has since the last call
+    pub fn take_run_target_reached(&mut self) -> Option<RunTarget> {
+        self.run_target_reached.take()
     }
 
     pub fn game_loaded(&self) -> bool {
@@ -116,16 +521,54 @@ impl Emulator {
     pub fn get_set_pause(&mut self, pause: Option<bool>) -> bool {
         if let Some(pause) = pause {
             if self.paused && !pause && !self.rewind_states.is_empty() {
-                // self.rewind_states.truncate(self.rewind_state_index as usize + 1);
-                while self.rewind_states.len() - 1 != self.rewind_state_index as usize {
-                    self.rewind_states.pop();
+                self.rewind_states
+                    .truncate(self.rewind_state_index as usize + 1);
+                // So does the input from after where it's being unpaused
+                let now = self.nes.as_ref().map_or(0, |nes| nes.ppu.cycles);
+                while self
+                    .input_log
+                    .back()
+                    .is_some_and(|change| change.ppu_cycle > now)
+                {
+                    self.input_log.pop_back();
                 }
             }
+            if pause {
+                self.run_until = None;
+            } else {
+                self.rewound = false;
+            }
             self.paused = pause;
         }
         self.paused
     }
 
+    pub fn get_set_rewind_seconds(&mut self, seconds: Option<f64>) -> f64 {
+        if let Some(seconds) = seconds {
+            // Also set from the config file, which could hold anything
+            let seconds = seconds.max(0.0);
+            self.rewind_states.max_frames = (seconds * DEFAULT_FRAMERATE) as usize;
+        }
+        self.rewind_states.max_frames as f64 / DEFAULT_FRAMERATE
+    }
+
+    pub fn get_set_rewind_megabytes(&mut self, megabytes: Option<f64>) -> f64 {
+        if let Some(megabytes) = megabytes {
+            let megabytes = megabytes.max(0.0);
+            self.rewind_states.max_size = (megabytes * MEGABYTE) as usize;
+        }
+        self.rewind_states.max_size as f64 / MEGABYTE
+    }
+
+    // How far back the rewind buffer currently goes
+    pub fn rewind_seconds_available(&self) -> f64 {
+        self.rewind_states.len() as f64 / DEFAULT_FRAMERATE
+    }
+
+    pub fn rewind_megabytes_used(&self) -> f64 {
+        self.rewind_states.size() as f64 / MEGABYTE
+    }
+
     pub fn get_set_volume(&mut self, volume: Option<f64>) -> f64 {
         if let Some(v) = volume {
             assert!(v <= 1.0);
@@ -138,20 +581,148 @@ impl Emulator {
         if self.paused && !self.rewind_states.is_empty() && n_frames != 0.0 {
             self.rewind_state_index = (self.rewind_state_index + n_frames)
                 .clamp(0.0, (self.rewind_states.len() - 1) as f32);
-            self.nes = Some(self.rewind_states[self.rewind_state_index.round() as usize].clone());
+            let nes = self.rewind_state(self.rewind_state_index.round() as usize);
+            self.replace_nes(nes);
+            self.rewound = true;
             self.run_to_vblank();
         }
     }
 
+    fn rewind_state(&self, index: usize) -> Nes {
+        let state = self
+            .rewind_states
+            .get(index)
+            .expect("Rewind index is in range");
+        let mut nes: Nes = bincode::deserialize(&state).expect("Rewind states are always valid");
+        self.reattach(&mut nes, self.nes.as_ref().unwrap());
+        nes
+    }
+
+    // Goes back to before the last instruction ran, by replaying from the newest rewind state
+    // before it. Returns false if the rewind history doesn't go back far enough.
+    pub fn step_back(&mut self) -> bool {
+        let Some(current) = self.nes.as_ref() else {
+            return false;
+        };
+        let Some(target) = current.cpu.instruction_count.checked_sub(1) else {
+            return false;
+        };
+        let end = current.ppu.cycles;
+
+        for index in (0..self.rewind_states.len()).rev() {
+            let mut nes = self.rewind_state(index);
+            if nes.cpu.instruction_count >= target {
+                continue;
+            }
+            if self.replay(&mut nes, end, |nes| nes.cpu.instruction_count == target) {
+                self.rewound_to(index, nes);
+                return true;
+            }
+        }
+        false
+    }
+
+    // Runs backwards to the last place a breakpoint would have paused the emulator. Conditions are
+    // checked as usual but hit counts are ignored, and log points don't log anything.
+    pub fn reverse_continue(&mut self) -> bool {
+        let Some(current) = self.nes.as_ref() else {
+            return false;
+        };
+        let mut breakpoints = current.breakpoints.clone();
+        breakpoints.hit = None;
+        breakpoints.log.clear();
+        for bp in breakpoints.list.iter_mut() {
+            bp.enabled &= bp.log_message.is_none();
+            bp.break_on_hit = 0;
+        }
+        let mut end = current.ppu.cycles;
+
+        // Search a frame at a time, newest first
+        for index in (0..self.rewind_states.len()).rev() {
+            let mut nes = self.rewind_state(index);
+            let start = nes.ppu.cycles;
+            if start >= end {
+                continue;
+            }
+            nes.breakpoints = breakpoints.clone();
+            let mut last_hit = None;
+            self.replay(&mut nes, end, |nes| {
+                if let Some(hit) = nes.breakpoints.hit.take() {
+                    if nes.ppu.cycles < end {
+                        last_hit = Some((nes.ppu.cycles, hit));
+                    }
+                }
+                false
+            });
+
+            if let Some((position, hit)) = last_hit {
+                let mut nes = self.rewind_state(index);
+                self.replay(&mut nes, end, |nes| nes.ppu.cycles == position);
+                self.rewound_to(index, nes);
+                self.breakpoint_hit = Some(hit);
+                return true;
+            }
+            end = start;
+        }
+        false
+    }
+
+    fn rewound_to(&mut self, index: usize, nes: Nes) {
+        // Unpausing drops the rewind states from after this point
+        self.rewind_state_index = index as f32;
+        self.replace_nes(nes);
+        self.rewound = true;
+        self.update_prg_rom_debug_cache();
+    }
+
+    // Runs a Nes restored from a rewind state forward with the same input as the first time
+    // round, without any audio. Stops at the first point between instructions where `stop`
+    // returns true, or once it gets to PPU cycle `end`.
+    fn replay(&self, nes: &mut Nes, end: u64, mut stop: impl FnMut(&mut Nes) -> bool) -> bool {
+        let start = nes.ppu.cycles;
+        let mut input = self
+            .input_log
+            .iter()
+            .skip_while(|change| change.ppu_cycle < start)
+            .peekable();
+        while nes.ppu.cycles < end {
+            while let Some(change) = input.next_if(|change| change.ppu_cycle <= nes.ppu.cycles) {
+                change.apply(nes);
+            }
+
+            cpu::step_cpu(nes);
+
+            ppu::step_ppu(nes);
+            ppu::step_ppu(nes);
+            ppu::step_ppu(nes);
+
+            apu::step_apu(nes);
+
+            let between_instructions =
+                nes.cpu.instruction_cycle == 0 && nes.cpu.interrupt_cycle == 0;
+            if between_instructions && stop(nes) {
+                return true;
+            }
+        }
+        false
+    }
+
     fn update_prg_rom_debug_cache(&mut self) {
-        if let Some(nes) = self.nes.as_mut() {
-            let prg_rom_snapshot: Vec<u8> = (0x8000..=0xFFFF)
-                .map(|i| nes.cart.read_prg_rom(i))
-                .collect();
-            self.instruction_cache = Emulator::instructions_for_debug(prg_rom_snapshot.as_slice());
+        if let Some(nes) = self.nes.as_ref() {
+            self.disassembler.update(nes);
+            self.instruction_cache = self.disassembler.lines(nes, self.disassembly_view);
         }
     }
 
+    pub fn disassembly_view(&self) -> DisassemblyView {
+        self.disassembly_view
+    }
+
+    pub fn set_disassembly_view(&mut self, view: DisassemblyView) {
+        self.disassembly_view = view;
+        self.update_prg_rom_debug_cache();
+    }
+
     pub fn update(&mut self, time: f64) -> bool {
         self.time = time;
 
@@ -166,11 +737,9 @@ impl Emulator {
             if self.game_speed != self.target_speed {
                 self.game_speed = self.target_speed;
 
-                if let Some(stream) = &self.audio_output {
-                    self.cached_cycles_per_sample =
-                        Self::cycles_per_sample(stream.sample_rate, self.game_speed as f32);
-                    self.avg_sample_rate = self.cached_cycles_per_sample as f64;
-                }
+                self.cached_cycles_per_sample =
+                    Self::cycles_per_sample(self.sample_rate, self.game_speed as f32);
+                self.avg_sample_rate = self.cached_cycles_per_sample as f64;
                 let frame_length = 1.0 / (self.game_speed * DEFAULT_FRAMERATE);
                 let new_frame_number = (self.time / frame_length) as u64;
 
@@ -179,47 +748,63 @@ impl Emulator {
                 self.frame = frame_number;
             }
 
-            /*
-
-            Right so CHR RAM and PRG RAM can be behind Rc.
-            Stuff behind Rc is usually immutable (because otherwise you could potentially have multiple
-            mutable references at the same time to the same part of memory).
-
-            Turns out that you can actually mutate the data behind an Rc using make_mut.
-            This works in a copy-on-write way, so if multiple Rc exist that point to the same allocation
-            make_mut will clone the existing allocation then mutate it.
-
-            So I think all we need to do is make CHR RAM and PRG RAM Rc and modify them with make_mut
-
-            So when we clone the nes state into the rewind state list, both the rewind state and the
-            current nes state should be pointing to the same RAM allocations. The moment the current
-            nes modifies the ram it will be cloned and will point to a new allocation
-
-             */
-
             if !self.paused {
                 if !self.rewind_states.is_empty() {
                     self.rewind_state_index = (self.rewind_states.len() - 1) as f32;
                 }
-                let state = self.nes.as_ref().unwrap().clone();
-                self.rewind_states.push(state);
+                let state = bincode::serialize(self.nes.as_ref().unwrap())
+                    .expect("Nes can always be serialized");
+                if self.rewind_states.push(state) {
+                    // Input from before the oldest rewind state can't be replayed any more
+                    let oldest = self.rewind_state(0).ppu.cycles;
+                    while self
+                        .input_log
+                        .front()
+                        .is_some_and(|change| change.ppu_cycle < oldest)
+                    {
+                        self.input_log.pop_front();
+                    }
+                }
                 self.run_to_vblank();
             }
 
-            self.video_output.set(
-                ColorImage::from_rgba_unmultiplied([256, 240], self.nes_frame.borrow().as_slice()),
-                TextureOptions {
-                    magnification: TextureFilter::Nearest,
-                    minification: TextureFilter::Nearest,
-                    wrap_mode: Default::default(),
-                },
-            );
+            if let Some(sink) = self.audio_output.as_mut() {
+                sink.push_samples(&self.audio_buffer);
+            }
+            self.audio_buffer.clear();
+            if let Some(sink) = self.video_output.as_mut() {
+                sink.present_frame(self.nes_frame.borrow().as_slice());
+            }
             true
         } else {
             false
         }
     }
 
+    // Runs one frame as fast as possible, ignoring real time, pausing and rewind.
+    // This is the entry point for driving the emulator without a window.
+    pub fn run_frame(&mut self, input: FrameInput) -> FrameOutput {
+        self.set_controller(1, input.con1);
+        self.set_controller(2, input.con2);
+        if self.nes.is_some() {
+            self.run_to_vblank();
+        }
+        FrameOutput {
+            video: self.nes_frame.borrow().clone(),
+            audio: std::mem::take(&mut self.audio_buffer),
+        }
+    }
+
+    // CRC32 of the serialized Nes, for checking that two runs ended up in exactly the same state
+    pub fn state_hash(&self) -> Option<u32> {
+        let state = bincode::serialize(self.nes.as_ref()?).expect("Nes can always be serialized");
+        Some(crc32fast::hash(&state))
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
     pub fn run_one_cpu_instruction(&mut self) {
         if let Some(nes) = self.nes.as_mut() {
             loop {
@@ -235,15 +820,20 @@ impl Emulator {
                     break;
                 }
             }
+            // Already stopped, so there's nothing for a breakpoint to do
+            nes.breakpoints.hit = None;
         }
         self.update_prg_rom_debug_cache();
     }
 
     fn run_to_vblank(&mut self) {
+        let mut stopped = false;
         loop {
             self.try_audio_sample();
             if let Some(nes) = self.nes.as_mut() {
-                cpu::step_cpu(nes);
+                let entering_interrupt =
+                    nes.cpu.instruction_cycle == 0 && nes.cpu.interrupt_cycle == 6;
+                let end_of_instr = cpu::step_cpu(nes);
 
                 ppu::step_ppu(nes);
                 ppu::step_ppu(nes);
@@ -251,6 +841,34 @@ impl Emulator {
 
                 apu::step_apu(nes);
 
+                // Breakpoints are hit partway through an instruction, stop once it's finished
+                let between_instructions =
+                    nes.cpu.instruction_cycle == 0 && nes.cpu.interrupt_cycle == 0;
+                if between_instructions {
+                    if let Some(index) = nes.breakpoints.hit.take() {
+                        if !self.paused {
+                            self.paused = true;
+                            self.breakpoint_hit = Some(index);
+                            self.run_until = None;
+                            stopped = true;
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(run_until) = self.run_until.as_mut() {
+                    if (end_of_instr || entering_interrupt)
+                        && run_until.reached(nes, entering_interrupt)
+                        && !self.paused
+                    {
+                        self.paused = true;
+                        self.run_target_reached = Some(run_until.target);
+                        self.run_until = None;
+                        stopped = true;
+                        break;
+                    }
+                }
+
                 if nes.ppu.scanline == 239
                     && (nes.ppu.scanline_cycle >= 257 && nes.ppu.scanline_cycle <= 259)
                 {
@@ -258,6 +876,10 @@ impl Emulator {
                 }
             }
         }
+        // The debugger shows whichever banks are mapped in where it stopped
+        if stopped {
+            self.update_prg_rom_debug_cache();
+        }
     }
 
     fn try_audio_sample(&mut self) {
@@ -276,12 +898,31 @@ impl Emulator {
         }
     }
 
+    // Buttons are left as they were while paused at a point that's been rewound to, changing them
+    // there would make the recorded input disagree with the rewind states from after it. They're
+    // picked up again once it's unpaused, which drops that history.
     pub fn update_controller(&mut self, num: u8, pressed_buttons: NesButtonState) {
+        if !self.rewound {
+            self.set_controller(num, pressed_buttons);
+        }
+    }
+
+    fn set_controller(&mut self, num: u8, pressed_buttons: NesButtonState) {
         if let Some(nes) = self.nes.as_mut() {
-            match num {
-                1 => nes.con1.update_button_state(pressed_buttons),
-                // 2 => nes.con2.update_button_state(&pressed_buttons),
+            let controller = match num {
+                1 => &mut nes.con1,
+                2 => &mut nes.con2,
                 _ => panic!("Controller doesn't exist"),
+            };
+            let old_state = controller.button_state;
+            controller.update_button_state(pressed_buttons);
+            // Without any rewind states there's nothing to replay from
+            if controller.button_state != old_state && !self.rewind_states.is_empty() {
+                self.input_log.push_back(InputChange {
+                    ppu_cycle: nes.ppu.cycles,
+                    controller: num,
+                    button_state: controller.button_state,
+                });
             }
         }
     }
@@ -299,12 +940,7 @@ impl Emulator {
                 new_sample.1 * self.volume as f32,
             );
 
-            let _ = self
-                .audio_output
-                .as_mut()
-                .unwrap()
-                .sender
-                .try_send(new_sample_multiplied);
+            self.audio_buffer.push(new_sample_multiplied);
 
             let rolling_average = EXPONENTIAL_MOVING_AVG_BETA * self.avg_sample_rate
                 + (1.0 - EXPONENTIAL_MOVING_AVG_BETA)
@@ -314,26 +950,4 @@ impl Emulator {
             self.avg_sample_rate = rolling_average;
         }
     }
-
-    fn instructions_for_debug(prg_rom: &[u8]) -> Vec<CpuDebuggerInstruction> {
-        assert_eq!(prg_rom.len(), 0x8000);
-        let mut opcodes: Vec<CpuDebuggerInstruction> = Vec::new();
-        let mut window = prg_rom.array_windows().enumerate();
-        while let Some((index, [opc, arg1, arg2])) = window.next() {
-            let instr = INSTRUCTIONS[*opc as usize];
-            if !instr.is_unofficial() {
-                opcodes.push(CpuDebuggerInstruction {
-                    opc_addr: 0x8000 + index as u16,
-                    bytes: match instr.number_of_operands() {
-                        0 => InstrBytes::I1(*opc),
-                        1 => InstrBytes::I2(*opc, *arg1),
-                        2 => InstrBytes::I3(*opc, *arg1, *arg2),
-                        _ => unreachable!(),
-                    },
-                });
-                let _ = window.advance_by(instr.number_of_operands() as usize);
-            }
-        }
-        opcodes
-    }
 }
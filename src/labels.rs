@@ -0,0 +1,286 @@
+use crate::nes::Nes;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/*
+    Names and comments for addresses, shown by the debugger in place of raw addresses.
+
+    Labels can be imported from:
+      - ca65 debug info (.dbg, from ld65 --dbgfile)
+      - FCEUX name lists (game.nes.ram.nl for RAM, game.nes.N.nl for 16 KB PRG ROM bank N)
+      - Mesen label files (.mlb)
+
+    Labels in PRG ROM are kept against the ROM offset rather than the CPU address, so a label only
+    shows up while its bank is mapped in. Everything else is a CPU address.
+
+    Labels are saved per ROM in Mesen's format, so the same parser reads them back in.
+*/
+
+// iNES header, ld65 output offsets include it
+const INES_HEADER_SIZE: usize = 16;
+// Bank size used by FCEUX name lists, whatever the mapper's bank size is
+const NL_BANK_SIZE: usize = 0x4000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelAddress {
+    Cpu(u16),
+    PrgRom(usize),
+}
+
+impl LabelAddress {
+    // Addresses in cartridge space are taken to mean whatever's mapped there at the moment
+    pub fn from_cpu(nes: &Nes, addr: u16) -> LabelAddress {
+        if addr >= 0x8000 {
+            LabelAddress::PrgRom(nes.cart.prg_rom_offset(addr))
+        } else {
+            LabelAddress::Cpu(addr)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Label {
+    // Can be empty for a comment on its own
+    pub name: String,
+    pub comment: String,
+}
+
+#[derive(Clone, Default)]
+pub struct Labels {
+    labels: BTreeMap<LabelAddress, Label>,
+}
+
+impl Labels {
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&LabelAddress, &Label)> {
+        self.labels.iter()
+    }
+
+    pub fn insert(&mut self, addr: LabelAddress, label: Label) {
+        self.labels.insert(addr, label);
+    }
+
+    pub fn remove(&mut self, addr: LabelAddress) {
+        self.labels.remove(&addr);
+    }
+
+    pub fn get_exact(&self, addr: LabelAddress) -> Option<&Label> {
+        self.labels.get(&addr)
+    }
+
+    // The label for a CPU address, taking the current bank mapping into account
+    pub fn get(&self, nes: &Nes, addr: u16) -> Option<&Label> {
+        self.labels
+            .get(&LabelAddress::from_cpu(nes, addr))
+            .or_else(|| self.labels.get(&LabelAddress::Cpu(addr)))
+    }
+
+    // Where a label can currently be found in the CPU address space
+    pub fn address_of(&self, nes: &Nes, name: &str) -> Option<u16> {
+        let (addr, _) = self.labels.iter().find(|(_, label)| label.name == name)?;
+        match *addr {
+            LabelAddress::Cpu(addr) => Some(addr),
+            LabelAddress::PrgRom(offset) => {
+                (0x8000..=0xFFFF).find(|&addr| nes.cart.prg_rom_offset(addr) == offset)
+            }
+        }
+    }
+
+    // Adds the labels in a .dbg, .nl or .mlb file, returns how many there were
+    pub fn import(&mut self, path: &Path) -> Result<usize, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let labels = match extension.to_ascii_lowercase().as_str() {
+            "dbg" => parse_dbg(&text)?,
+            "nl" => parse_nl(&text, nl_bank(path)),
+            "mlb" => parse_mlb(&text),
+            _ => return Err(format!("Unknown label file type \".{extension}\"")),
+        };
+        let count = labels.len();
+        self.labels.extend(labels);
+        Ok(count)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_mlb())
+    }
+
+    fn to_mlb(&self) -> String {
+        let mut text = String::new();
+        for (addr, label) in &self.labels {
+            let (kind, addr) = match *addr {
+                LabelAddress::PrgRom(offset) => ("P", offset),
+                LabelAddress::Cpu(addr @ 0x0000..=0x1FFF) => ("R", addr as usize),
+                LabelAddress::Cpu(addr @ 0x6000..=0x7FFF) => ("W", addr as usize - 0x6000),
+                LabelAddress::Cpu(addr) => ("G", addr as usize),
+            };
+            text += &format!("{kind}:{addr:04X}:{}", label.name);
+            if !label.comment.is_empty() {
+                text += &format!(":{}", label.comment.replace('\n', "\\n"));
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text.trim(), 16).ok()
+}
+
+// Mesen: "P:1234:name:comment", the address can also be a range like "P:1234-1237"
+fn parse_mlb(text: &str) -> Vec<(LabelAddress, Label)> {
+    let mut labels = Vec::new();
+    for line in text.lines() {
+        let mut fields = line.splitn(4, ':');
+        let (Some(kind), Some(addr), Some(name)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Some(addr) = parse_hex(addr.split('-').next().unwrap_or(addr)) else {
+            continue;
+        };
+        let addr = match kind.trim() {
+            "P" | "NesPrgRom" => LabelAddress::PrgRom(addr),
+            "R" | "NesInternalRam" if addr < 0x0800 => LabelAddress::Cpu(addr as u16),
+            "W" | "S" | "NesWorkRam" | "NesSaveRam" if addr < 0x2000 => {
+                LabelAddress::Cpu(0x6000 + addr as u16)
+            }
+            "G" | "NesMemory" if addr <= 0xFFFF => LabelAddress::Cpu(addr as u16),
+            // CHR, palette, OAM etc.
+            _ => continue,
+        };
+        let label = Label {
+            name: name.trim().to_owned(),
+            comment: fields.next().unwrap_or("").replace("\\n", "\n"),
+        };
+        labels.push((addr, label));
+    }
+    labels
+}
+
+// "game.nes.A.nl" is bank 10 (FCEUX writes it in hex), anything else (normally
+// "game.nes.ram.nl") is CPU addresses
+fn nl_bank(path: &Path) -> Option<usize> {
+    let stem = Path::new(path.file_stem()?);
+    parse_hex(stem.extension()?.to_str()?)
+}
+
+// FCEUX: "$C000#name#comment", the address can also have a size like "$0300/10"
+fn parse_nl(text: &str, bank: Option<usize>) -> Vec<(LabelAddress, Label)> {
+    let mut labels = Vec::new();
+    for line in text.lines() {
+        let Some(line) = line.strip_prefix('$') else {
+            continue;
+        };
+        let mut fields = line.splitn(3, '#');
+        let (Some(addr), Some(name)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let Some(addr) = parse_hex(addr.split('/').next().unwrap_or(addr)) else {
+            continue;
+        };
+        let addr = match bank {
+            Some(bank) if addr >= 0x8000 => {
+                LabelAddress::PrgRom(bank * NL_BANK_SIZE + addr % NL_BANK_SIZE)
+            }
+            _ if addr <= 0xFFFF => LabelAddress::Cpu(addr as u16),
+            _ => continue,
+        };
+        let label = Label {
+            name: name.trim().to_owned(),
+            comment: fields.next().unwrap_or("").trim_end_matches('#').to_owned(),
+        };
+        labels.push((addr, label));
+    }
+    labels
+}
+
+// ca65: lines like "sym id=3,name="main",addrsize=absolute,scope=0,def=5,val=0x8000,seg=1,type=lab"
+fn parse_dbg(text: &str) -> Result<Vec<(LabelAddress, Label)>, String> {
+    let records: Vec<(&str, HashMap<&str, &str>)> = text
+        .lines()
+        .filter_map(|line| {
+            let (kind, fields) = line.split_once(char::is_whitespace)?;
+            Some((kind, dbg_fields(fields)))
+        })
+        .collect();
+    if !records.iter().any(|(kind, _)| *kind == "version") {
+        return Err("Not a ca65 debug info file".to_owned());
+    }
+    let number = |value: &str| match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    };
+
+    // Where each segment starts in the CPU address space and in the output file
+    let mut segments = HashMap::new();
+    for (_, fields) in records.iter().filter(|(kind, _)| *kind == "seg") {
+        let (Some(id), Some(start)) = (fields.get("id"), fields.get("start")) else {
+            continue;
+        };
+        let file_offset = fields.get("ooffs").and_then(|offset| number(offset));
+        segments.insert(*id, (number(start).unwrap_or(0), file_offset));
+    }
+
+    let mut labels = Vec::new();
+    for (_, fields) in records.iter().filter(|(kind, _)| *kind == "sym") {
+        // Constants ("equ") are as likely to be numbers as addresses
+        if fields.get("type") != Some(&"lab") {
+            continue;
+        }
+        let (Some(name), Some(addr)) = (
+            fields.get("name"),
+            fields.get("val").and_then(|v| number(v)),
+        ) else {
+            continue;
+        };
+        let segment = fields.get("seg").and_then(|seg| segments.get(seg));
+        let addr = match segment {
+            Some(&(start, Some(file_offset))) if addr >= 0x8000 => {
+                match (file_offset + addr).checked_sub(start + INES_HEADER_SIZE) {
+                    Some(offset) => LabelAddress::PrgRom(offset),
+                    None => continue,
+                }
+            }
+            _ if addr <= 0xFFFF => LabelAddress::Cpu(addr as u16),
+            _ => continue,
+        };
+        let label = Label {
+            name: name.to_string(),
+            comment: String::new(),
+        };
+        labels.push((addr, label));
+    }
+    Ok(labels)
+}
+
+// Comma separated key=value pairs, values can be quoted strings with commas in them
+fn dbg_fields(text: &str) -> HashMap<&str, &str> {
+    let mut fields = HashMap::new();
+    let mut rest = text.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        fields.insert(key.trim(), value);
+        rest = after.trim_start_matches(',');
+    }
+    fields
+}
@@ -1,14 +1,16 @@
 #![feature(bigint_helper_methods)]
 #![feature(array_chunks)]
-#![feature(array_windows)]
-#![feature(iter_advance_by)]
 #![feature(map_try_insert)]
 #![allow(clippy::unusual_byte_groupings)]
 
 pub mod app;
 pub mod emulator;
+pub mod labels;
 pub mod nes;
-mod setup;
+pub mod rewind;
+pub mod save_state;
+mod saves;
+pub mod setup;
 mod ui;
 mod util;
 mod widgets;
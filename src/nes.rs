@@ -1,15 +1,24 @@
 pub mod apu;
+pub mod breakpoints;
 pub mod cartridge;
+pub mod cdl;
 pub mod controller;
 pub mod cpu;
+pub mod events;
 mod mem;
 pub mod ppu;
 pub mod mem_consts;
+pub mod memory_region;
 
-use crate::nes::apu::Apu;
+use crate::nes::apu::{apu_status_write, Apu};
+use crate::nes::breakpoints::Breakpoints;
 use crate::nes::cartridge::Cartridge;
+use crate::nes::cdl::CodeDataLog;
 use crate::nes::controller::Controller;
+use crate::nes::cpu::trace::CpuTracer;
 use crate::nes::cpu::Cpu;
+use crate::nes::events::EventLog;
+use crate::nes::ppu::palette::Palette;
 use crate::nes::ppu::Ppu;
 use crate::util::concat_u8;
 use serde::{Deserialize, Serialize};
@@ -27,9 +36,22 @@ pub struct Nes {
     pub con1: Controller,
     pub con2: Controller,
     // External
-    // #[serde(skip)]
-    // #[serde(default = "frame_default")]
+    // Not part of save states, the emulator reattaches its frame buffer after loading one
+    #[serde(skip)]
     pub frame: Option<Rc<RefCell<Vec<u8>>>>,
+    // Same goes for the palette, which can be swapped out at any time
+    #[serde(skip)]
+    pub palette: Rc<Palette>,
+    // Debugging
+    // The emulator moves the tracer across when it swaps in a different Nes
+    #[serde(skip)]
+    pub tracer: Option<Box<CpuTracer>>,
+    #[serde(skip)]
+    pub breakpoints: Breakpoints,
+    #[serde(skip)]
+    pub cdl: Option<Box<CodeDataLog>>,
+    #[serde(skip)]
+    pub events: Option<Box<EventLog>>,
 }
 
 impl Clone for Nes {
@@ -43,12 +65,21 @@ impl Clone for Nes {
             con1: self.con1,
             con2: self.con2,
             frame: Some(Rc::clone(self.frame.as_ref().unwrap())),
+            palette: Rc::clone(&self.palette),
+            tracer: None,
+            breakpoints: self.breakpoints.clone(),
+            cdl: None,
+            events: None,
         }
     }
 }
 
 impl Nes {
-    pub fn new(cartridge: Box<dyn Cartridge>, frame: Rc<RefCell<Vec<u8>>>) -> Nes {
+    pub fn new(
+        cartridge: Box<dyn Cartridge>,
+        frame: Rc<RefCell<Vec<u8>>>,
+        palette: Rc<Palette>,
+    ) -> Nes {
         Nes {
             cpu: Cpu::new(concat_u8(
                 cartridge.read_prg_rom(0xFFFD),
@@ -63,6 +94,35 @@ impl Nes {
 
             // RGBA image (4 channels)
             frame: Some(frame),
+            palette,
+            tracer: None,
+            breakpoints: Default::default(),
+            cdl: None,
+            events: None,
         }
     }
+
+    // Pressing the console's reset button. RAM is left alone, power cycling is done by creating a
+    // new Nes.
+    pub fn reset(&mut self) {
+        self.cart.reset();
+        let reset_vector = concat_u8(
+            self.cart.read_prg_rom(0xFFFD),
+            self.cart.read_prg_rom(0xFFFC),
+        );
+        self.cpu.reset(reset_vector);
+        self.ppu.reset();
+        apu_status_write(0, self);
+        self.apu.reset();
+    }
+
+    // Reads CPU memory without side effects, for the debugger
+    pub fn peek(&mut self, addr: u16) -> u8 {
+        mem::peek_mem(addr, self)
+    }
+
+    // Changes CPU memory for the debugger, returns false for registers and unmapped addresses
+    pub fn poke(&mut self, addr: u16, val: u8) -> bool {
+        mem::poke_mem(addr, val, self)
+    }
 }
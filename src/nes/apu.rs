@@ -8,4 +8,4 @@ pub use self::step::{
     noise_channel_output, sample_channel_output, square_channel_output, step_apu,
     triangle_channel_output
 };
-pub use self::mem::{apu_status_read, apu_status_write, apu_channels_write};
+pub use self::mem::{apu_status_read, apu_status_peek, apu_status_write, apu_channels_write};
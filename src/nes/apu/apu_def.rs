@@ -49,6 +49,15 @@ impl Apu {
             interrupt_request: false,
         }
     }
+    // Silencing the channels is done by writing 0 to $4015 (see Nes::reset), this handles the rest
+    // of what the reset line does. The frame counter keeps its mode, as if $4017 was rewritten.
+    pub fn reset(&mut self) {
+        self.frame_sequencer_counter = 0;
+        self.interrupt_request = false;
+        self.triangle.sequencer_stage = 0;
+        self.sample.output &= 1;
+    }
+
     pub fn asserting_irq(&self) -> bool {
         self.interrupt_request || self.sample.interrupt_request
     }
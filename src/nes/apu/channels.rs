@@ -110,7 +110,7 @@ impl Triangle {
     }
 }
 
-#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Noise {
     pub enabled: bool,
     pub envelope_loop_and_length_counter_halt: bool,
@@ -126,8 +126,36 @@ pub struct Noise {
     pub mode: bool,
     pub timer_init_value: u16,
     pub timer_curr_value: u16,
+    // 15 bit linear feedback shift register, the channel is silent while bit 0 is set
+    #[serde(default = "Noise::power_on_shift_register")]
+    pub shift_register: u16,
+}
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            enabled: false,
+            envelope_loop_and_length_counter_halt: false,
+            constant_volume: false,
+            length_counter: 0,
+            length_counter_mute_signal: false,
+            envelope_start_flag: false,
+            envelope_decay_level: 0,
+            envelope_counter_curr_value: 0,
+            volume_and_envelope_period: 0,
+            sequencer_output: false,
+            envelope_output: 0,
+            mode: false,
+            timer_init_value: 0,
+            timer_curr_value: 0,
+            shift_register: Noise::power_on_shift_register(),
+        }
+    }
 }
 impl Noise {
+    fn power_on_shift_register() -> u16 {
+        1
+    }
+
     pub fn set_reg1_from_byte(&mut self, byte: u8) {
         // println!("Reg 1 set");
         self.envelope_loop_and_length_counter_halt = (byte & 0b0010_0000) > 0;
@@ -136,7 +164,7 @@ impl Noise {
     }
     pub fn set_reg2_from_byte(&mut self, byte: u8) {
         // println!("Reg 2 set");
-        // This will go unused. I'm not convinced that it does anything substantial
+        // Short mode makes a 93 step sequence instead of 32767 steps, which sounds more metallic
         self.mode = (byte & 0b1000_0000) > 0;
         self.timer_init_value = NOISE_PERIOD_TABLE[(byte & 0b0000_1111) as usize];
     }
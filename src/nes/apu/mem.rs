@@ -1,16 +1,21 @@
 use crate::nes::mem_consts::*;
 
 pub fn apu_status_read(nes: &mut crate::nes::Nes) -> u8 {
-    let result = nes.apu.square1.length_counter.min(1)
+    let result = apu_status_peek(nes);
+    nes.apu.interrupt_request = false;
+    result
+}
+
+// What reading 0x4015 would return, without acknowledging the frame interrupt
+pub fn apu_status_peek(nes: &crate::nes::Nes) -> u8 {
+    nes.apu.square1.length_counter.min(1)
         | (nes.apu.square2.length_counter.min(1) << 1)
         | (nes.apu.triangle.length_counter.min(1) << 2)
         | (nes.apu.noise.length_counter.min(1) << 3)
         | ((nes.apu.sample.remaining_sample_bytes.min(1) as u8) << 4)
         | (nes.cpu.open_bus & 0b0010_0000)
         | ((nes.apu.interrupt_request as u8) << 6)
-        | ((nes.apu.sample.interrupt_request as u8) << 7);
-    nes.apu.interrupt_request = false;
-    result
+        | ((nes.apu.sample.interrupt_request as u8) << 7)
 }
 
 pub fn apu_status_write(val: u8, nes: &mut crate::nes::Nes) {
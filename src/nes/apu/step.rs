@@ -1,5 +1,6 @@
 use super::channels::*;
-use crate::nes::mem::read_mem;
+use crate::nes::cdl::{self, PRG_DMC_SAMPLE};
+use crate::nes::mem::read_mem_unlogged;
 use crate::nes::Nes;
 
 const STEP_1: u16 = 3729;
@@ -66,7 +67,10 @@ fn clock_sample_timer(nes: &mut Nes) {
         {
             // DMC DMA
 
-            let new_sample_data = read_mem(nes.apu.sample.curr_sample_addr, nes);
+            let new_sample_data = read_mem_unlogged(nes.apu.sample.curr_sample_addr, nes);
+            if nes.cdl.is_some() {
+                cdl::log_prg(nes, nes.apu.sample.curr_sample_addr, PRG_DMC_SAMPLE);
+            }
             // println!("Sample curr addr {:04X}", nes.apu.sample.curr_sample_addr);
             // std::thread::sleep(std::time::Duration::from_millis(5));
             nes.apu.sample.sample_buffer = new_sample_data;
@@ -237,7 +241,11 @@ fn clock_noise_timer(noise: &mut Noise) {
     if noise.timer_curr_value == 0 {
         // Clock pulse sequencer
         noise.timer_curr_value = noise.timer_init_value;
-        noise.sequencer_output = fastrand::bool();
+        // Feedback is bit 0 XOR bit 6 in short mode or bit 1 otherwise
+        let tap = if noise.mode { 6 } else { 1 };
+        let feedback = (noise.shift_register ^ (noise.shift_register >> tap)) & 1;
+        noise.shift_register = (noise.shift_register >> 1) | (feedback << 14);
+        noise.sequencer_output = (noise.shift_register & 1) == 0;
     } else {
         noise.timer_curr_value -= 1;
     }
@@ -0,0 +1,196 @@
+pub mod expression;
+
+use crate::nes::Nes;
+use expression::{Expression, LogMessage};
+use std::collections::VecDeque;
+use std::fmt;
+
+/*
+    Breakpoints and watchpoints for the debugger.
+
+    The CPU, memory and PPU report events here as they happen. A matching breakpoint is recorded as
+    hit, and the emulator pauses once the current instruction has finished, so the CPU is always
+    stopped between instructions.
+
+    Breakpoints can also have a condition that's checked when they match, only pause from their
+    Nth hit, or be log points that add a message to the log instead of pausing.
+*/
+
+const MAX_LOG_LINES: usize = 1000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BreakpointKind {
+    // Before the instruction at this address runs
+    Execute(u16),
+    // CPU reads/writes in an address range
+    CpuRead { start: u16, end: u16 },
+    CpuWrite { start: u16, end: u16 },
+    // CPU reads/writes of a PPU register (0x2000-0x2007) or any of its mirrors
+    PpuRegisterRead(u16),
+    PpuRegisterWrite(u16),
+    // PPU address space, both PPUDATA accesses and the PPU's own rendering fetches
+    VramRead { start: u16, end: u16 },
+    VramWrite { start: u16, end: u16 },
+    // When the CPU enters the interrupt handler
+    Nmi,
+    Irq,
+    // Start of a scanline, the pre-render scanline is -1
+    Scanline(i32),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum BreakEvent {
+    Execute(u16),
+    CpuRead(u16),
+    CpuWrite(u16),
+    VramRead(u16),
+    VramWrite(u16),
+    Nmi,
+    Irq,
+    Scanline(i32),
+}
+
+impl BreakpointKind {
+    fn matches(&self, event: BreakEvent) -> bool {
+        use BreakpointKind as K;
+        let same_register = |reg: u16, addr: u16| {
+            (0x2000..=0x3FFF).contains(&addr) && (addr & 0x0007) == (reg & 0x0007)
+        };
+        match (*self, event) {
+            (K::Execute(bp_addr), BreakEvent::Execute(addr)) => bp_addr == addr,
+            (K::CpuRead { start, end }, BreakEvent::CpuRead(addr))
+            | (K::CpuWrite { start, end }, BreakEvent::CpuWrite(addr))
+            | (K::VramRead { start, end }, BreakEvent::VramRead(addr))
+            | (K::VramWrite { start, end }, BreakEvent::VramWrite(addr)) => {
+                (start..=end).contains(&addr)
+            }
+            (K::PpuRegisterRead(reg), BreakEvent::CpuRead(addr))
+            | (K::PpuRegisterWrite(reg), BreakEvent::CpuWrite(addr)) => same_register(reg, addr),
+            (K::Nmi, BreakEvent::Nmi) | (K::Irq, BreakEvent::Irq) => true,
+            (K::Scanline(bp_scanline), BreakEvent::Scanline(scanline)) => bp_scanline == scanline,
+            _ => false,
+        }
+    }
+
+    // Where in the CPU address space the breakpoint is (the start if it's a range)
+    pub fn cpu_address(&self) -> Option<u16> {
+        match *self {
+            BreakpointKind::Execute(addr)
+            | BreakpointKind::CpuRead { start: addr, .. }
+            | BreakpointKind::CpuWrite { start: addr, .. }
+            | BreakpointKind::PpuRegisterRead(addr)
+            | BreakpointKind::PpuRegisterWrite(addr) => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BreakpointKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = |start: u16, end: u16| {
+            if start == end {
+                format!("${start:04X}")
+            } else {
+                format!("${start:04X}-${end:04X}")
+            }
+        };
+        match *self {
+            BreakpointKind::Execute(addr) => write!(f, "Execute ${addr:04X}"),
+            BreakpointKind::CpuRead { start, end } => write!(f, "Read {}", range(start, end)),
+            BreakpointKind::CpuWrite { start, end } => write!(f, "Write {}", range(start, end)),
+            BreakpointKind::PpuRegisterRead(reg) => write!(f, "PPU register read ${reg:04X}"),
+            BreakpointKind::PpuRegisterWrite(reg) => write!(f, "PPU register write ${reg:04X}"),
+            BreakpointKind::VramRead { start, end } => write!(f, "VRAM read {}", range(start, end)),
+            BreakpointKind::VramWrite { start, end } => {
+                write!(f, "VRAM write {}", range(start, end))
+            }
+            BreakpointKind::Nmi => write!(f, "NMI"),
+            BreakpointKind::Irq => write!(f, "IRQ"),
+            BreakpointKind::Scanline(scanline) => write!(f, "Scanline {scanline}"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Breakpoint {
+    pub kind: BreakpointKind,
+    pub enabled: bool,
+    pub condition: Option<Expression>,
+    // Matches where the condition was true
+    pub hits: u64,
+    // Don't pause (or log) until this many hits, 0 and 1 both mean every hit
+    pub break_on_hit: u64,
+    // Log points add this to the log and carry on
+    pub log_message: Option<LogMessage>,
+}
+
+impl Breakpoint {
+    pub fn new(kind: BreakpointKind) -> Breakpoint {
+        Breakpoint {
+            kind,
+            enabled: true,
+            condition: None,
+            hits: 0,
+            break_on_hit: 0,
+            log_message: None,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Breakpoints {
+    pub list: Vec<Breakpoint>,
+    // Index into list of the first breakpoint hit since the emulator last checked
+    pub hit: Option<usize>,
+    // Messages from log points, oldest first
+    pub log: VecDeque<String>,
+}
+
+impl Breakpoints {
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn add(&mut self, breakpoint: Breakpoint) {
+        self.list.push(breakpoint);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.list.remove(index);
+        self.hit = None;
+    }
+}
+
+// Conditions and log messages read the emulator state, so the breakpoints are taken out of nes
+// while they're checked
+pub fn check(nes: &mut Nes, event: BreakEvent) {
+    let mut breakpoints = std::mem::take(&mut nes.breakpoints);
+    for (index, bp) in breakpoints.list.iter_mut().enumerate() {
+        if !bp.enabled || !bp.kind.matches(event) {
+            continue;
+        }
+        if let Some(condition) = &bp.condition {
+            if condition.evaluate(nes) == 0 {
+                continue;
+            }
+        }
+        bp.hits += 1;
+        if bp.hits < bp.break_on_hit {
+            continue;
+        }
+        match &bp.log_message {
+            Some(message) => {
+                if breakpoints.log.len() == MAX_LOG_LINES {
+                    breakpoints.log.pop_front();
+                }
+                breakpoints.log.push_back(message.format(nes));
+            }
+            None => {
+                if breakpoints.hit.is_none() {
+                    breakpoints.hit = Some(index);
+                }
+            }
+        }
+    }
+    nes.breakpoints = breakpoints;
+}
@@ -0,0 +1,434 @@
+use crate::nes::mem::peek_mem;
+use crate::nes::Nes;
+use std::fmt;
+
+/*
+    Expressions for breakpoint conditions and log messages, e.g.
+
+    A == $20 && [$0300] > 5 && scanline < 100
+
+    Numbers are decimal, hex ($20 or 0x20) or binary (%0010_0000).
+    [addr] reads a byte of CPU memory without side effects, registers give what reading them
+    would return (see peek_mem).
+    Operators and their precedence are the same as in C, comparisons give 1 or 0.
+
+    Names aren't case sensitive:
+    a x y s p pc        CPU registers
+    n v d i z c         Status flags (0 or 1)
+    scanline dot        PPU position, the pre-render scanline is -1
+    frame cycle         Frames since power on, CPU cycles since power on
+    bank0 bank1 ...     The mapper's bank registers, see Cartridge::bank_registers
+*/
+
+#[derive(Clone)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Number(i64),
+    Variable(Variable),
+    Memory(Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Variable {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    Pc,
+    // Bit of P
+    Flag(u8),
+    Scanline,
+    Dot,
+    Frame,
+    Cycle,
+    Bank(usize),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum UnaryOp {
+    Not,
+    Negate,
+    Complement,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl BinaryOp {
+    // Higher binds tighter
+    fn from_symbol(symbol: &str) -> Option<(BinaryOp, u8)> {
+        Some(match symbol {
+            "||" => (BinaryOp::Or, 1),
+            "&&" => (BinaryOp::And, 2),
+            "|" => (BinaryOp::BitOr, 3),
+            "^" => (BinaryOp::BitXor, 4),
+            "&" => (BinaryOp::BitAnd, 5),
+            "==" => (BinaryOp::Equal, 6),
+            "!=" => (BinaryOp::NotEqual, 6),
+            "<" => (BinaryOp::Less, 7),
+            "<=" => (BinaryOp::LessEqual, 7),
+            ">" => (BinaryOp::Greater, 7),
+            ">=" => (BinaryOp::GreaterEqual, 7),
+            "<<" => (BinaryOp::ShiftLeft, 8),
+            ">>" => (BinaryOp::ShiftRight, 8),
+            "+" => (BinaryOp::Add, 9),
+            "-" => (BinaryOp::Subtract, 9),
+            "*" => (BinaryOp::Multiply, 10),
+            "/" => (BinaryOp::Divide, 10),
+            "%" => (BinaryOp::Remainder, 10),
+            _ => return None,
+        })
+    }
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser.expression(0)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("Unexpected {token}"));
+        }
+        Ok(Expression {
+            source: source.trim().to_owned(),
+            root,
+        })
+    }
+
+    pub fn evaluate(&self, nes: &mut Nes) -> i64 {
+        evaluate(&self.root, nes)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn evaluate(node: &Node, nes: &mut Nes) -> i64 {
+    match node {
+        Node::Number(value) => *value,
+        Node::Variable(variable) => read_variable(*variable, nes),
+        Node::Memory(addr) => peek_mem(evaluate(addr, nes) as u16, nes) as i64,
+        Node::Unary(op, operand) => {
+            let value = evaluate(operand, nes);
+            match op {
+                UnaryOp::Not => (value == 0) as i64,
+                UnaryOp::Negate => value.wrapping_neg(),
+                UnaryOp::Complement => !value,
+            }
+        }
+        // Short-circuit so the right-hand side doesn't read memory it doesn't need to
+        Node::Binary(BinaryOp::And, lhs, rhs) => {
+            (evaluate(lhs, nes) != 0 && evaluate(rhs, nes) != 0) as i64
+        }
+        Node::Binary(BinaryOp::Or, lhs, rhs) => {
+            (evaluate(lhs, nes) != 0 || evaluate(rhs, nes) != 0) as i64
+        }
+        Node::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (evaluate(lhs, nes), evaluate(rhs, nes));
+            match op {
+                BinaryOp::BitOr => lhs | rhs,
+                BinaryOp::BitXor => lhs ^ rhs,
+                BinaryOp::BitAnd => lhs & rhs,
+                BinaryOp::Equal => (lhs == rhs) as i64,
+                BinaryOp::NotEqual => (lhs != rhs) as i64,
+                BinaryOp::Less => (lhs < rhs) as i64,
+                BinaryOp::LessEqual => (lhs <= rhs) as i64,
+                BinaryOp::Greater => (lhs > rhs) as i64,
+                BinaryOp::GreaterEqual => (lhs >= rhs) as i64,
+                BinaryOp::ShiftLeft => lhs.wrapping_shl(rhs as u32),
+                BinaryOp::ShiftRight => lhs.wrapping_shr(rhs as u32),
+                BinaryOp::Add => lhs.wrapping_add(rhs),
+                BinaryOp::Subtract => lhs.wrapping_sub(rhs),
+                BinaryOp::Multiply => lhs.wrapping_mul(rhs),
+                // Dividing by zero gives 0 rather than stopping the emulator
+                BinaryOp::Divide => lhs.checked_div(rhs).unwrap_or(0),
+                BinaryOp::Remainder => lhs.checked_rem(rhs).unwrap_or(0),
+                BinaryOp::And | BinaryOp::Or => unreachable!(),
+            }
+        }
+    }
+}
+
+fn read_variable(variable: Variable, nes: &mut Nes) -> i64 {
+    match variable {
+        Variable::A => nes.cpu.a as i64,
+        Variable::X => nes.cpu.x as i64,
+        Variable::Y => nes.cpu.y as i64,
+        Variable::S => nes.cpu.s as i64,
+        Variable::P => (nes.cpu.get_p() | 0b0010_0000) as i64,
+        Variable::Pc => nes.cpu.pc as i64,
+        Variable::Flag(bit) => ((nes.cpu.get_p() >> bit) & 1) as i64,
+        Variable::Scanline => nes.ppu.scanline as i64,
+        Variable::Dot => nes.ppu.scanline_cycle as i64,
+        Variable::Frame => nes.ppu.frames as i64,
+        Variable::Cycle => nes.cpu.cycles as i64,
+        // Mappers with fewer registers read as 0
+        Variable::Bank(index) => nes
+            .cart
+            .bank_registers()
+            .get(index)
+            .map_or(0, |&bank| bank as i64),
+    }
+}
+
+fn variable_from_name(name: &str) -> Option<Variable> {
+    let name = name.to_ascii_lowercase();
+    if let Some(index) = name.strip_prefix("bank") {
+        return index.parse().ok().map(Variable::Bank);
+    }
+    Some(match name.as_str() {
+        "a" => Variable::A,
+        "x" => Variable::X,
+        "y" => Variable::Y,
+        "s" | "sp" => Variable::S,
+        "p" => Variable::P,
+        "pc" => Variable::Pc,
+        "n" => Variable::Flag(7),
+        "v" => Variable::Flag(6),
+        "d" => Variable::Flag(3),
+        "i" => Variable::Flag(2),
+        "z" => Variable::Flag(1),
+        "c" => Variable::Flag(0),
+        "scanline" => Variable::Scanline,
+        "dot" => Variable::Dot,
+        "frame" => Variable::Frame,
+        "cycle" => Variable::Cycle,
+        _ => return None,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number {value}"),
+            Token::Name(name) => write!(f, "\"{name}\""),
+            Token::Symbol(symbol) => write!(f, "\"{symbol}\""),
+        }
+    }
+}
+
+// Longest first so "<=" isn't read as "<" then "="
+const SYMBOLS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "[", "]",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        // % is a binary number where a value is expected and the remainder operator anywhere else
+        let expecting_value = !matches!(
+            tokens.last(),
+            Some(Token::Number(_) | Token::Name(_) | Token::Symbol(")" | "]"))
+        );
+        let binary_number = expecting_value && rest.starts_with('%');
+        let symbol = SYMBOLS.iter().find(|s| rest.starts_with(**s));
+        let (token, len) = match symbol {
+            Some(symbol) if !binary_number => (Token::Symbol(symbol), symbol.len()),
+            _ => {
+                let prefix_len = if rest.starts_with(['$', '%']) { 1 } else { 0 };
+                let len = rest[prefix_len..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map_or(rest.len(), |len| len + prefix_len);
+                if len == 0 {
+                    // Not the start of a word, and could be any character
+                    let len = rest.chars().next().unwrap().len_utf8();
+                    return Err(format!("Unexpected \"{}\"", &rest[..len]));
+                }
+                (parse_word(&rest[..len])?, len)
+            }
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_word(word: &str) -> Result<Token, String> {
+    let invalid = || format!("\"{word}\" isn't a number or a name");
+    let digits = |text: &str| text.replace('_', "");
+    let number = if let Some(hex) = word.strip_prefix('$') {
+        i64::from_str_radix(&digits(hex), 16)
+    } else if let Some(hex) = word.strip_prefix("0x") {
+        i64::from_str_radix(&digits(hex), 16)
+    } else if let Some(binary) = word.strip_prefix('%') {
+        i64::from_str_radix(&digits(binary), 2)
+    } else if word.starts_with(|c: char| c.is_ascii_digit()) {
+        digits(word).parse()
+    } else if variable_from_name(word).is_some() {
+        return Ok(Token::Name(word.to_owned()));
+    } else {
+        return Err(format!("Unknown name \"{word}\""));
+    };
+    number.map(Token::Number).map_err(|_| invalid())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            Some(token) => Err(format!("Expected \"{symbol}\" but found {token}")),
+            None => Err(format!("Expected \"{symbol}\"")),
+        }
+    }
+
+    // Precedence climbing, only takes operators that bind at least as tightly as min_precedence
+    fn expression(&mut self, min_precedence: u8) -> Result<Node, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Symbol(symbol)) = self.tokens.get(self.pos) {
+            let Some((op, precedence)) = BinaryOp::from_symbol(symbol) else {
+                break;
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expression(precedence + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Symbol("!")) => UnaryOp::Not,
+            Some(Token::Symbol("-")) => UnaryOp::Negate,
+            Some(Token::Symbol("~")) => UnaryOp::Complement,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Node::Number(value)),
+            Some(Token::Name(name)) => Ok(Node::Variable(
+                variable_from_name(&name).expect("Names are checked when tokenizing"),
+            )),
+            Some(Token::Symbol("(")) => {
+                let inner = self.expression(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Some(Token::Symbol("[")) => {
+                let addr = self.expression(0)?;
+                self.expect("]")?;
+                Ok(Node::Memory(Box::new(addr)))
+            }
+            Some(token) => Err(format!("Unexpected {token}")),
+            None => Err("Expression ends too early".to_owned()),
+        }
+    }
+}
+
+/*
+    Messages for log points, expressions in braces are replaced with their value in hex:
+
+    Scroll set to {[$FD]} on scanline {scanline}
+*/
+
+#[derive(Clone)]
+pub struct LogMessage {
+    source: String,
+    parts: Vec<LogPart>,
+}
+
+#[derive(Clone)]
+enum LogPart {
+    Text(String),
+    Value(Expression),
+}
+
+impl LogMessage {
+    pub fn parse(source: &str) -> Result<LogMessage, String> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or("Missing \"}\" in log message")?
+                + start;
+            if start > 0 {
+                parts.push(LogPart::Text(rest[..start].to_owned()));
+            }
+            parts.push(LogPart::Value(Expression::parse(&rest[start + 1..end])?));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(LogPart::Text(rest.to_owned()));
+        }
+        Ok(LogMessage {
+            source: source.to_owned(),
+            parts,
+        })
+    }
+
+    pub fn format(&self, nes: &mut Nes) -> String {
+        let mut message = String::new();
+        for part in self.parts.iter() {
+            match part {
+                LogPart::Text(text) => message.push_str(text),
+                LogPart::Value(expression) => {
+                    message.push_str(&format!("{:02X}", expression.evaluate(nes)))
+                }
+            }
+        }
+        message
+    }
+}
+
+impl fmt::Display for LogMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
@@ -5,9 +5,11 @@ pub mod mapper2;
 pub mod mapper3;
 pub mod mapper4;
 pub mod mapper7;
+pub mod registry;
 
 pub use self::cartridge_def::Cartridge;
 pub use self::cartridge_def::Mirroring;
+pub use self::registry::{create_cartridge, LoadError};
 
 pub use self::mapper0::CartridgeM0;
 pub use self::mapper1::CartridgeM1;
@@ -2,7 +2,7 @@ use dyn_clone::DynClone;
 use serde::{Deserialize, Serialize};
 use std::rc::Rc;
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
@@ -10,16 +10,33 @@ pub enum Mirroring {
     SingleScreenUpper,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice10,
+    Extended(u8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+// ROM is left out of save states, it gets reattached from the loaded game with restore_rom
 #[derive(Clone, Serialize, Deserialize)]
 pub enum ChrMem {
-    Rom(Rc<Vec<u8>>),
+    Rom(#[serde(skip)] Rc<Vec<u8>>),
     Ram(Rc<Vec<u8>>),
 }
 impl ChrMem {
-    pub fn new(rom_data: Option<Vec<u8>>) -> Self {
+    pub fn new(rom_data: Option<Vec<u8>>, ram_size: usize) -> Self {
         match rom_data {
             Some(data) => Self::Rom(Rc::new(data)),
-            None => Self::Ram(Rc::new(vec![0u8; 0x2000])),
+            None => Self::Ram(Rc::new(vec![0u8; ram_size])),
         }
     }
 
@@ -38,38 +55,94 @@ impl ChrMem {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct RomConfig {
-    pub ines_mapper_id: u8,
+    pub ines_mapper_id: u16,
+    // Only NES 2.0 headers specify a submapper
+    pub submapper_id: Option<u8>,
     pub ines_mirroring: Mirroring,
+    pub has_battery: bool,
+    // RAM sizes in bytes, NVRAM is the battery-backed portion
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub console_type: ConsoleType,
+    pub region: Region,
+    pub default_expansion_device: u8,
     pub data: CartMemory,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CartMemory {
     pub prg_ram: Option<Rc<Vec<u8>>>,
+    #[serde(skip)]
     pub prg_rom: Rc<Vec<u8>>,
     pub chr_mem: ChrMem,
+    pub has_battery: bool,
 }
 
 impl CartMemory {
-    pub fn new(prg_rom: Vec<u8>, chr_rom: Option<Vec<u8>>, has_prg_ram: bool) -> Self {
+    pub fn new(
+        prg_rom: Vec<u8>,
+        chr_rom: Option<Vec<u8>>,
+        prg_ram_size: usize,
+        chr_ram_size: usize,
+        has_battery: bool,
+    ) -> Self {
         CartMemory {
-            prg_ram: match has_prg_ram {
-                // TODO: Fix this
-                /*
-                   Mario 3 writes to and reads from 0x6000-0x7FFF where PRG RAM would be
-                   but the cartridge doesn't actually have PRG RAM so any reads should just return
-                   the open bus. There are no mentions of Mario 3 relying on open bus behaviour
-                   to function but weirdly the game crashes at startup if don't enable PRG RAM.
-                   Tried to figure out what was going wrong but to no avail. It's difficult without
-                   a CPU instruction view, so maybe I should flesh that out first before trying
-                   to fix this bug.
-                */
-                true | false => Some(Rc::new(vec![0u8; 0x2000])),
-                // true => Some(Rc::new(vec![0u8; 0x2000])),
-                // false => None,
+            // Boards without PRG RAM leave 0x6000-0x7FFF as open bus.
+            // Note that the battery bit only says whether the RAM is battery-backed, Mario 3
+            // (TSROM, 8KB of PRG RAM with no battery) used to crash when it decided if there was
+            // any. See get_rom_from_bytes for iNES 1.0 headers, which rarely give a size.
+            prg_ram: match prg_ram_size {
+                0 => None,
+                size => Some(Rc::new(vec![0u8; size])),
             },
             prg_rom: Rc::new(prg_rom),
-            chr_mem: ChrMem::new(chr_rom),
+            chr_mem: ChrMem::new(chr_rom, chr_ram_size),
+            has_battery,
+        }
+    }
+
+    // PRG RAM smaller than the 8KB window at 0x6000-0x7FFF is mirrored across it
+    pub fn read_prg_ram(&self, addr: u16) -> Option<u8> {
+        let ram = self.prg_ram.as_ref()?;
+        Some(ram[(addr - 0x6000) as usize % ram.len()])
+    }
+    pub fn write_prg_ram(&mut self, addr: u16, byte: u8) {
+        if let Some(ram) = self.prg_ram.as_mut() {
+            let len = ram.len();
+            Rc::make_mut(ram)[(addr - 0x6000) as usize % len] = byte;
+        }
+    }
+
+    // CRC32 of PRG ROM followed by CHR ROM
+    pub fn rom_hash(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.prg_rom);
+        if let ChrMem::Rom(chr_rom) = &self.chr_mem {
+            hasher.update(chr_rom);
+        }
+        hasher.finalize()
+    }
+
+    pub fn restore_rom(&mut self, rom: &CartMemory) {
+        self.prg_rom = Rc::clone(&rom.prg_rom);
+        if let (ChrMem::Rom(chr_rom), ChrMem::Rom(loaded)) = (&mut self.chr_mem, &rom.chr_mem) {
+            *chr_rom = Rc::clone(loaded);
+        }
+    }
+
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        match self.has_battery {
+            true => self.prg_ram.as_deref().map(|ram| ram.as_slice()),
+            false => None,
+        }
+    }
+    pub fn load_battery_ram(&mut self, data: &[u8]) {
+        if let (true, Some(ram)) = (self.has_battery, self.prg_ram.as_mut()) {
+            let ram = Rc::make_mut(ram);
+            let len = ram.len().min(data.len());
+            ram[..len].copy_from_slice(&data[..len]);
         }
     }
 }
@@ -96,9 +169,13 @@ pub trait Cartridge: DynClone {
     fn write_prg_ram(&mut self, _addr: u16, _byte: u8) {}
 
     fn read_prg_rom(&self, addr: u16) -> u8;
+    // Where in PRG ROM a CPU address (0x8000-0xFFFF) is currently mapped to
+    fn prg_rom_offset(&self, addr: u16) -> usize;
     fn write_prg_rom(&mut self, _addr: u16, _byte: u8) {}
 
     fn read_chr(&mut self, addr: u16) -> u8;
+    // Where in CHR ROM/RAM a PPU address (0x0000-0x1FFF) is currently mapped to
+    fn chr_offset(&self, addr: u16) -> usize;
     fn write_chr(&mut self, _addr: u16, _byte: u8) {}
 
     fn asserting_irq(&mut self) -> bool {
@@ -109,4 +186,23 @@ pub trait Cartridge: DynClone {
     fn ppu_tick(&mut self, _addr_bus: u16) {}
 
     fn mirroring(&self) -> Mirroring;
+
+    // Called when the console is reset. Boards made of discrete logic don't see the reset line so
+    // the default does nothing, mappers that do should go back to their power-on state.
+    fn reset(&mut self) {}
+
+    fn rom_data(&self) -> &CartMemory;
+    fn rom_data_mut(&mut self) -> &mut CartMemory;
+
+    // Battery-backed memory that should persist between sessions, usually PRG RAM but some
+    // boards keep saves in other places (e.g. EEPROM)
+    fn battery_ram(&self) -> Option<&[u8]> {
+        None
+    }
+    fn load_battery_ram(&mut self, _data: &[u8]) {}
+
+    // Current values of the bank select registers, for the debugger
+    fn bank_registers(&self) -> Vec<usize> {
+        Vec::new()
+    }
 }
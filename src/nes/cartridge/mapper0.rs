@@ -19,15 +19,34 @@ impl CartridgeM0 {
 }
 #[typetag::serde]
 impl Cartridge for CartridgeM0 {
-    // NROM doesn't support PRG RAM
+    // Standard NROM boards have no PRG RAM, but Family BASIC has 8KB and homebrew and test ROMs
+    // expect it. Whether there is any is decided from the header, see get_rom_from_bytes.
+    fn read_prg_ram(&mut self, addr: u16) -> Option<u8> {
+        self.rom_data.read_prg_ram(addr)
+    }
+    fn write_prg_ram(&mut self, addr: u16, byte: u8) {
+        self.rom_data.write_prg_ram(addr, byte);
+    }
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom_data.battery_ram()
+    }
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        self.rom_data.load_battery_ram(data);
+    }
     // NROM has no internal registers to write to
     // NROM-128 is 16KB mirrored twice, NROM-256 is 32KB
     fn read_prg_rom(&self, addr: u16) -> u8 {
-        self.rom_data.prg_rom[addr as usize % self.rom_data.prg_rom.len()]
+        self.rom_data.prg_rom[self.prg_rom_offset(addr)]
+    }
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        addr as usize % self.rom_data.prg_rom.len()
     }
     // CHR ROM is fixed 8KB
     fn read_chr(&mut self, addr: u16) -> u8 {
-        self.rom_data.chr_mem.read(addr as usize)
+        self.rom_data.chr_mem.read(self.chr_offset(addr))
+    }
+    fn chr_offset(&self, addr: u16) -> usize {
+        addr as usize
     }
     // NROM doesn't actually support CHR RAM but some homebrew games use this mapper with RAM
     fn write_chr(&mut self, addr: u16, value: u8) {
@@ -36,4 +55,10 @@ impl Cartridge for CartridgeM0 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+    fn rom_data(&self) -> &CartMemory {
+        &self.rom_data
+    }
+    fn rom_data_mut(&mut self) -> &mut CartMemory {
+        &mut self.rom_data
+    }
 }
@@ -1,6 +1,5 @@
 use super::cartridge_def::{CartMemory, Cartridge, Mirroring, RomConfig, KB};
 use serde::{Deserialize, Serialize};
-use std::rc::Rc;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CartridgeM1 {
@@ -22,8 +21,12 @@ pub struct CartridgeM1 {
 
 impl CartridgeM1 {
     pub fn new(rom_config: RomConfig) -> CartridgeM1 {
+        Self::power_on_state(rom_config.data)
+    }
+
+    fn power_on_state(rom_data: CartMemory) -> CartridgeM1 {
         CartridgeM1 {
-            rom_data: rom_config.data,
+            rom_data,
             mirroring: Mirroring::Vertical,
 
             chr_bank_0: 0,
@@ -39,51 +42,39 @@ impl CartridgeM1 {
             consecutive_write_counter: 0,
         }
     }
-
-    fn calc_chr_addr(&self, addr: usize) -> usize {
-        match self.chr_bank_mode {
-            0 => (self.chr_bank_0 & 0b11110) * 8 * KB + addr,
-            1 => match addr {
-                0x0000..=0x0FFF => self.chr_bank_0 * 4 * KB + addr,
-                0x1000..=0x1FFF => self.chr_bank_1 * 4 * KB + (addr - 0x1000),
-                _ => unreachable!(),
-            },
-            _ => unreachable!(),
-        }
-    }
 }
 
 #[typetag::serde]
 impl Cartridge for CartridgeM1 {
     // MMC1 can optionally have PRG RAM
     fn read_prg_ram(&mut self, addr: u16) -> Option<u8> {
-        self.rom_data.prg_ram.as_ref()?.get((addr - 0x6000) as usize).cloned()
+        self.rom_data.read_prg_ram(addr)
     }
     fn write_prg_ram(&mut self, addr: u16, byte: u8) {
-        if let Some(ram) = self.rom_data.prg_ram.as_mut() {
-            Rc::make_mut(ram)[(addr - 0x6000) as usize] = byte;
-        }
+        self.rom_data.write_prg_ram(addr, byte);
+    }
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom_data.battery_ram()
+    }
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        self.rom_data.load_battery_ram(data);
     }
 
     fn read_prg_rom(&self, addr: u16) -> u8 {
+        self.rom_data.prg_rom[self.prg_rom_offset(addr)]
+    }
+    fn prg_rom_offset(&self, addr: u16) -> usize {
         let addru = addr as usize;
         match self.prg_bank_mode {
-            0 | 1 => self.rom_data.prg_rom[(self.prg_bank & 0b11110) * 32 * KB + (addru - 0x8000)],
+            0 | 1 => (self.prg_bank & 0b11110) * 32 * KB + (addru - 0x8000),
             2 => match addr {
-                0x8000..=0xBFFF => self.rom_data.prg_rom[addru - 0x8000],
-                0xC000..=0xFFFF => {
-                    self.rom_data.prg_rom[(self.prg_bank * 16 * KB) + (addru - 0xC000)]
-                }
+                0x8000..=0xBFFF => addru - 0x8000,
+                0xC000..=0xFFFF => (self.prg_bank * 16 * KB) + (addru - 0xC000),
                 _ => unreachable!(),
             },
             3 => match addr {
-                0x8000..=0xBFFF => {
-                    self.rom_data.prg_rom[(self.prg_bank * 16 * KB) + (addru - 0x8000)]
-                }
-                0xC000..=0xFFFF => {
-                    self.rom_data.prg_rom
-                        [(self.rom_data.prg_rom.len() - 16 * KB) + (addru - 0xC000)]
-                }
+                0x8000..=0xBFFF => (self.prg_bank * 16 * KB) + (addru - 0x8000),
+                0xC000..=0xFFFF => (self.rom_data.prg_rom.len() - 16 * KB) + (addru - 0xC000),
                 _ => unreachable!(),
             },
             _ => unreachable!(),
@@ -130,9 +121,19 @@ impl Cartridge for CartridgeM1 {
     }
 
     fn read_chr(&mut self, addr: u16) -> u8 {
-        self.rom_data
-            .chr_mem
-            .read(self.calc_chr_addr(addr as usize))
+        self.rom_data.chr_mem.read(self.chr_offset(addr))
+    }
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = addr as usize;
+        match self.chr_bank_mode {
+            0 => (self.chr_bank_0 & 0b11110) * 8 * KB + addr,
+            1 => match addr {
+                0x0000..=0x0FFF => self.chr_bank_0 * 4 * KB + addr,
+                0x1000..=0x1FFF => self.chr_bank_1 * 4 * KB + (addr - 0x1000),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
     }
     fn write_chr(&mut self, addr: u16, byte: u8) {
         self.rom_data.chr_mem.write(addr as usize, byte);
@@ -141,6 +142,18 @@ impl Cartridge for CartridgeM1 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+    fn bank_registers(&self) -> Vec<usize> {
+        vec![self.prg_bank, self.chr_bank_0, self.chr_bank_1]
+    }
+    fn reset(&mut self) {
+        *self = Self::power_on_state(self.rom_data.clone());
+    }
+    fn rom_data(&self) -> &CartMemory {
+        &self.rom_data
+    }
+    fn rom_data_mut(&mut self) -> &mut CartMemory {
+        &mut self.rom_data
+    }
 
     fn cpu_tick(&mut self) {
         self.consecutive_write_counter = self.consecutive_write_counter.saturating_sub(1);
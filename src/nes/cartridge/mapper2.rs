@@ -22,16 +22,14 @@ impl Cartridge for CartridgeM2 {
     // UxROM doesn't have PRG RAM support
 
     fn read_prg_rom(&self, addr: u16) -> u8 {
+        self.rom_data.prg_rom[self.prg_rom_offset(addr)]
+    }
+    fn prg_rom_offset(&self, addr: u16) -> usize {
         match addr {
             // Swappable 16KB at start of cartridge range
-            0x8000..=0xBFFF => {
-                self.rom_data.prg_rom[(self.bank_select * 0x4000) + (addr as usize - 0x8000)]
-            }
+            0x8000..=0xBFFF => (self.bank_select * 0x4000) + (addr as usize - 0x8000),
             // Fixed 16KB at end of addressable range
-            0xC000..=0xFFFF => {
-                self.rom_data.prg_rom
-                    [(self.rom_data.prg_rom.len() - 0x4000) + (addr as usize - 0xC000)]
-            }
+            0xC000..=0xFFFF => (self.rom_data.prg_rom.len() - 0x4000) + (addr as usize - 0xC000),
             _ => unreachable!(),
         }
     }
@@ -40,7 +38,10 @@ impl Cartridge for CartridgeM2 {
     }
 
     fn read_chr(&mut self, addr: u16) -> u8 {
-        self.rom_data.chr_mem.read(addr as usize)
+        self.rom_data.chr_mem.read(self.chr_offset(addr))
+    }
+    fn chr_offset(&self, addr: u16) -> usize {
+        addr as usize
     }
     fn write_chr(&mut self, addr: u16, value: u8) {
         self.rom_data.chr_mem.write(addr as usize, value);
@@ -48,4 +49,13 @@ impl Cartridge for CartridgeM2 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+    fn bank_registers(&self) -> Vec<usize> {
+        vec![self.bank_select]
+    }
+    fn rom_data(&self) -> &CartMemory {
+        &self.rom_data
+    }
+    fn rom_data_mut(&mut self) -> &mut CartMemory {
+        &mut self.rom_data
+    }
 }
@@ -20,17 +20,30 @@ impl CartridgeM3 {
 #[typetag::serde]
 impl Cartridge for CartridgeM3 {
     fn read_prg_rom(&self, addr: u16) -> u8 {
-        self.rom_data.prg_rom[addr as usize % self.rom_data.prg_rom.len()]
+        self.rom_data.prg_rom[self.prg_rom_offset(addr)]
+    }
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        addr as usize % self.rom_data.prg_rom.len()
     }
     fn write_prg_rom(&mut self, _addr: u16, byte: u8) {
         self.bank_select = (byte & 0b0000_0011) as usize;
     }
     fn read_chr(&mut self, addr: u16) -> u8 {
-        self.rom_data
-            .chr_mem
-            .read(self.bank_select * 0x2000 + addr as usize)
+        self.rom_data.chr_mem.read(self.chr_offset(addr))
+    }
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.bank_select * 0x2000 + addr as usize
     }
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+    fn bank_registers(&self) -> Vec<usize> {
+        vec![self.bank_select]
+    }
+    fn rom_data(&self) -> &CartMemory {
+        &self.rom_data
+    }
+    fn rom_data_mut(&mut self) -> &mut CartMemory {
+        &mut self.rom_data
+    }
 }
@@ -1,7 +1,6 @@
 use super::cartridge_def::{CartMemory, Cartridge, Mirroring, RomConfig, KB};
 use crate::util::get_bit;
 use serde::{Deserialize, Serialize};
-use std::rc::Rc;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CartridgeM4 {
@@ -23,6 +22,9 @@ pub struct CartridgeM4 {
     prg_fixed_bank_select: bool,
     chr_bank_size_select: bool,
 
+    prg_ram_enable: bool,
+    prg_ram_write_protect: bool,
+
     mirroring: Mirroring,
 
     scanline_counter_init: u8,
@@ -41,8 +43,12 @@ pub struct CartridgeM4 {
 
 impl CartridgeM4 {
     pub fn new(rom_config: RomConfig) -> CartridgeM4 {
+        Self::power_on_state(rom_config.data)
+    }
+
+    fn power_on_state(rom_data: CartMemory) -> CartridgeM4 {
         CartridgeM4 {
-            rom_data: rom_config.data,
+            rom_data,
             bank_index: 0,
             prg_bank_0_or_2: 0,
             prg_bank_1: 0,
@@ -54,6 +60,10 @@ impl CartridgeM4 {
             chr_1kb_bank_3: 0,
             prg_fixed_bank_select: false,
             chr_bank_size_select: false,
+            // Power-on state of 0xA001 isn't defined, games that never write to it expect
+            // PRG RAM to work so it starts enabled
+            prg_ram_enable: true,
+            prg_ram_write_protect: false,
             mirroring: Mirroring::Vertical,
             scanline_counter_init: 0,
             scanline_counter_curr: 0,
@@ -70,16 +80,28 @@ impl CartridgeM4 {
 impl Cartridge for CartridgeM4 {
     // MMC3 can optionally have PRG RAM
     fn read_prg_ram(&mut self, addr: u16) -> Option<u8> {
-        self.rom_data.prg_ram.as_ref()?.get((addr - 0x6000) as usize).cloned()
+        if !self.prg_ram_enable {
+            return None;
+        }
+        self.rom_data.read_prg_ram(addr)
     }
     fn write_prg_ram(&mut self, addr: u16, byte: u8) {
-        if let Some(ram) = self.rom_data.prg_ram.as_mut() {
-            Rc::make_mut(ram)[(addr - 0x6000) as usize] = byte;
+        if self.prg_ram_enable && !self.prg_ram_write_protect {
+            self.rom_data.write_prg_ram(addr, byte);
         }
     }
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom_data.battery_ram()
+    }
+    fn load_battery_ram(&mut self, data: &[u8]) {
+        self.rom_data.load_battery_ram(data);
+    }
 
     fn read_prg_rom(&self, addr: u16) -> u8 {
-        let base_bank_addr = match (addr, self.prg_fixed_bank_select) {
+        self.rom_data.prg_rom[self.prg_rom_offset(addr)]
+    }
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        match (addr, self.prg_fixed_bank_select) {
             (0xA000..=0xBFFF, _) => self.prg_bank_1 * 8 * KB + (addr as usize - 0xA000),
             (0xE000..=0xFFFF, _) => self.rom_data.prg_rom.len() - 8 * KB + (addr as usize - 0xE000),
 
@@ -94,8 +116,7 @@ impl Cartridge for CartridgeM4 {
             (0xC000..=0xDFFF, true) => self.prg_bank_0_or_2 * 8 * KB + (addr as usize - 0xC000),
 
             _ => unreachable!(),
-        };
-        self.rom_data.prg_rom[base_bank_addr]
+        }
     }
     fn write_prg_rom(&mut self, addr: u16, byte: u8) {
         let even = addr % 2 == 0;
@@ -133,7 +154,8 @@ impl Cartridge for CartridgeM4 {
                 }
             }
             (0xA000..=0xBFFF, false) => {
-                // PRG RAM write protect, omitted for now
+                self.prg_ram_enable = (byte & 0b1000_0000) > 0;
+                self.prg_ram_write_protect = (byte & 0b0100_0000) > 0;
             }
             (0xC000..=0xDFFF, true) => {
                 self.scanline_counter_init = byte;
@@ -153,9 +175,12 @@ impl Cartridge for CartridgeM4 {
     }
 
     fn read_chr(&mut self, addr: u16) -> u8 {
+        self.rom_data.chr_mem.read(self.chr_offset(addr))
+    }
+    fn chr_offset(&self, addr: u16) -> usize {
         let uaddr = addr as usize;
 
-        let chr_addr = if !self.chr_bank_size_select {
+        if !self.chr_bank_size_select {
             match addr {
                 0x0000..=0x07FF => self.chr_2kb_bank_0 * KB + uaddr,
                 0x0800..=0x0FFF => self.chr_2kb_bank_1 * KB + (uaddr - 0x0800),
@@ -177,8 +202,7 @@ impl Cartridge for CartridgeM4 {
                 0x1800..=0x1FFF => self.chr_2kb_bank_1 * KB + (uaddr - 0x1800),
                 _ => unreachable!(),
             }
-        };
-        self.rom_data.chr_mem.read(chr_addr)
+        }
     }
 
     fn asserting_irq(&mut self) -> bool {
@@ -218,4 +242,26 @@ impl Cartridge for CartridgeM4 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+    // In the same order as R0-R7
+    fn bank_registers(&self) -> Vec<usize> {
+        vec![
+            self.chr_2kb_bank_0,
+            self.chr_2kb_bank_1,
+            self.chr_1kb_bank_0,
+            self.chr_1kb_bank_1,
+            self.chr_1kb_bank_2,
+            self.chr_1kb_bank_3,
+            self.prg_bank_0_or_2,
+            self.prg_bank_1,
+        ]
+    }
+    fn reset(&mut self) {
+        *self = Self::power_on_state(self.rom_data.clone());
+    }
+    fn rom_data(&self) -> &CartMemory {
+        &self.rom_data
+    }
+    fn rom_data_mut(&mut self) -> &mut CartMemory {
+        &mut self.rom_data
+    }
 }
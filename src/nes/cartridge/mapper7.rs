@@ -20,7 +20,10 @@ impl CartridgeM7 {
 #[typetag::serde]
 impl Cartridge for CartridgeM7 {
     fn read_prg_rom(&self, addr: u16) -> u8 {
-        self.rom_data.prg_rom[self.bank_select * 0x8000 + (addr as usize - 0x8000)]
+        self.rom_data.prg_rom[self.prg_rom_offset(addr)]
+    }
+    fn prg_rom_offset(&self, addr: u16) -> usize {
+        self.bank_select * 0x8000 + (addr as usize - 0x8000)
     }
     fn write_prg_rom(&mut self, _addr: u16, byte: u8) {
         self.bank_select = (byte & 0b0000_0111) as usize;
@@ -32,7 +35,10 @@ impl Cartridge for CartridgeM7 {
     }
 
     fn read_chr(&mut self, addr: u16) -> u8 {
-        self.rom_data.chr_mem.read(addr as usize)
+        self.rom_data.chr_mem.read(self.chr_offset(addr))
+    }
+    fn chr_offset(&self, addr: u16) -> usize {
+        addr as usize
     }
     fn write_chr(&mut self, addr: u16, value: u8) {
         self.rom_data.chr_mem.write(addr as usize, value);
@@ -40,4 +46,13 @@ impl Cartridge for CartridgeM7 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+    fn bank_registers(&self) -> Vec<usize> {
+        vec![self.bank_select]
+    }
+    fn rom_data(&self) -> &CartMemory {
+        &self.rom_data
+    }
+    fn rom_data_mut(&mut self) -> &mut CartMemory {
+        &mut self.rom_data
+    }
 }
@@ -0,0 +1,123 @@
+use super::cartridge_def::{Cartridge, RomConfig};
+use super::{CartridgeM0, CartridgeM1, CartridgeM2, CartridgeM3, CartridgeM4, CartridgeM7};
+use std::error::Error;
+use std::fmt;
+
+pub struct MapperEntry {
+    pub id: u16,
+    pub name: &'static str,
+    pub new: fn(RomConfig) -> Box<dyn Cartridge>,
+}
+
+// Every supported mapper has an entry here. To add a new mapper, implement Cartridge for it in
+// its own module and add an entry to this table, nothing else needs to know about it.
+pub static MAPPERS: &[MapperEntry] = &[
+    MapperEntry {
+        id: 0,
+        name: "NROM",
+        new: |rom| Box::new(CartridgeM0::new(rom)),
+    },
+    MapperEntry {
+        id: 1,
+        name: "MMC1",
+        new: |rom| Box::new(CartridgeM1::new(rom)),
+    },
+    MapperEntry {
+        id: 2,
+        name: "UxROM",
+        new: |rom| Box::new(CartridgeM2::new(rom)),
+    },
+    MapperEntry {
+        id: 3,
+        name: "CNROM",
+        new: |rom| Box::new(CartridgeM3::new(rom)),
+    },
+    MapperEntry {
+        id: 4,
+        name: "MMC3",
+        new: |rom| Box::new(CartridgeM4::new(rom)),
+    },
+    MapperEntry {
+        id: 7,
+        name: "AxROM",
+        new: |rom| Box::new(CartridgeM7::new(rom)),
+    },
+];
+
+// Names of common mappers that aren't implemented yet, so the user gets something more useful
+// than a number when a game won't load
+static UNSUPPORTED_MAPPER_NAMES: &[(u16, &str)] = &[
+    (5, "MMC5"),
+    (9, "MMC2"),
+    (10, "MMC4"),
+    (11, "Color Dreams"),
+    (13, "CPROM"),
+    (16, "Bandai FCG"),
+    (19, "Namco 163"),
+    (21, "VRC4"),
+    (22, "VRC2"),
+    (23, "VRC2/VRC4"),
+    (24, "VRC6"),
+    (25, "VRC4"),
+    (26, "VRC6"),
+    (34, "BNROM/NINA-001"),
+    (64, "RAMBO-1"),
+    (66, "GxROM"),
+    (69, "Sunsoft FME-7"),
+    (71, "Camerica"),
+    (79, "NINA-03/06"),
+    (85, "VRC7"),
+    (206, "Namco 108"),
+];
+
+pub fn mapper_name(id: u16) -> &'static str {
+    MAPPERS
+        .iter()
+        .map(|entry| (entry.id, entry.name))
+        .chain(UNSUPPORTED_MAPPER_NAMES.iter().copied())
+        .find(|(mapper_id, _)| *mapper_id == id)
+        .map_or("Unknown", |(_, name)| name)
+}
+
+pub fn create_cartridge(rom_config: RomConfig) -> Result<Box<dyn Cartridge>, LoadError> {
+    let id = rom_config.ines_mapper_id;
+    match MAPPERS.iter().find(|entry| entry.id == id) {
+        Some(entry) => Ok((entry.new)(rom_config)),
+        None => Err(LoadError::UnsupportedMapper {
+            id,
+            name: mapper_name(id),
+            submapper: rom_config.submapper_id,
+        }),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LoadError {
+    InvalidRom(String),
+    UnsupportedMapper {
+        id: u16,
+        name: &'static str,
+        submapper: Option<u8>,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::InvalidRom(reason) => write!(f, "Invalid ROM: {reason}"),
+            LoadError::UnsupportedMapper {
+                id,
+                name,
+                submapper,
+            } => {
+                write!(f, "Mapper {id} ({name}")?;
+                if let Some(submapper) = submapper {
+                    write!(f, ", submapper {submapper}")?;
+                }
+                write!(f, ") is not supported")
+            }
+        }
+    }
+}
+
+impl Error for LoadError {}
@@ -0,0 +1,107 @@
+use crate::nes::cartridge::cartridge_def::ChrMem;
+use crate::nes::Nes;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/*
+    Code/Data Logger, records how each byte of the ROM has been used.
+
+    Bytes are recorded by ROM offset rather than CPU/PPU address so that bank switching doesn't
+    mix banks up. Saved files are in FCEUX's .cdl format (which Mesen also reads): one byte of
+    flags per byte of PRG ROM, followed by one per byte of CHR ROM.
+
+    PRG flags:
+      0x01  executed as code
+      0x02  read as data
+      0x0C  which 8 KB window of 0x8000-0xFFFF the byte was last accessed through
+      0x40  DMC sample data
+    CHR flags:
+      0x01  read by the PPU while rendering
+      0x02  read by the CPU through PPUDATA
+
+    The opcode flag (0x80) is only kept for the disassembler and left out of saved files, Mesen
+    uses that bit for something else.
+*/
+
+pub const PRG_CODE: u8 = 0x01;
+pub const PRG_DATA: u8 = 0x02;
+const PRG_WINDOW: u8 = 0x0C;
+pub const PRG_DMC_SAMPLE: u8 = 0x40;
+pub const PRG_OPCODE: u8 = 0x80;
+
+pub const CHR_RENDERED: u8 = 0x01;
+pub const CHR_READ: u8 = 0x02;
+
+#[derive(Clone)]
+pub struct CodeDataLog {
+    prg: Vec<u8>,
+    // Empty for CHR RAM, there's nothing to say about bytes the game writes itself
+    chr: Vec<u8>,
+}
+
+// How much of each part of the ROM has been seen, as fractions
+pub struct Coverage {
+    pub code: f64,
+    pub data: f64,
+    pub chr: Option<f64>,
+}
+
+impl CodeDataLog {
+    pub fn new(nes: &Nes) -> CodeDataLog {
+        let rom_data = nes.cart.rom_data();
+        let chr_len = match &rom_data.chr_mem {
+            ChrMem::Rom(rom) => rom.len(),
+            ChrMem::Ram(_) => 0,
+        };
+        CodeDataLog {
+            prg: vec![0; rom_data.prg_rom.len()],
+            chr: vec![0; chr_len],
+        }
+    }
+
+    pub fn prg_flags(&self, offset: usize) -> u8 {
+        self.prg.get(offset).copied().unwrap_or(0)
+    }
+
+    pub fn clear(&mut self) {
+        self.prg.fill(0);
+        self.chr.fill(0);
+    }
+
+    pub fn coverage(&self) -> Coverage {
+        let fraction = |log: &[u8], flags: u8| {
+            log.iter().filter(|&&byte| byte & flags != 0).count() as f64 / log.len().max(1) as f64
+        };
+        Coverage {
+            code: fraction(&self.prg, PRG_CODE),
+            data: fraction(&self.prg, PRG_DATA | PRG_DMC_SAMPLE),
+            chr: (!self.chr.is_empty()).then(|| fraction(&self.chr, CHR_RENDERED | CHR_READ)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let prg = self.prg.iter().map(|byte| byte & !PRG_OPCODE);
+        fs::write(
+            path,
+            prg.chain(self.chr.iter().copied()).collect::<Vec<u8>>(),
+        )
+    }
+}
+
+// Called for CPU reads of 0x8000-0xFFFF
+pub fn log_prg(nes: &mut Nes, addr: u16, flags: u8) {
+    let offset = nes.cart.prg_rom_offset(addr);
+    if let Some(byte) = nes.cdl.as_mut().and_then(|cdl| cdl.prg.get_mut(offset)) {
+        let window = ((addr >> 13) & 0b11) as u8;
+        *byte = (*byte & !PRG_WINDOW) | flags | (window << 2);
+    }
+}
+
+// Called for PPU reads of 0x0000-0x1FFF
+pub fn log_chr(nes: &mut Nes, addr: u16, flags: u8) {
+    let offset = nes.cart.chr_offset(addr);
+    if let Some(byte) = nes.cdl.as_mut().and_then(|cdl| cdl.chr.get_mut(offset)) {
+        *byte |= flags;
+    }
+}
@@ -1,4 +1,3 @@
-use crate::app::NesButtonState;
 use crate::util::to_mask;
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +8,18 @@ pub struct Controller {
     pub sr_latch_pin: bool,
 }
 
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct NesButtonState {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub b: bool,
+    pub a: bool,
+    pub start: bool,
+    pub select: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum NesButton {
     Up,
@@ -33,9 +44,14 @@ const B: u8 = 0b0000_0010;
 impl Controller {
     pub fn shift_out_button_state(&mut self) -> u8 {
         let button_state = self.shift_register & 1;
-        self.shift_register >>= 1;
+        // Official controllers return 1 once all 8 buttons have been read
+        self.shift_register = (self.shift_register >> 1) | 0x80;
         button_state
     }
+    // The bit the next read would return, without shifting
+    pub fn peek_button_state(&self) -> u8 {
+        self.shift_register & 1
+    }
     pub fn write_to_data_latch(&mut self, val: u8) {
         // If latch was high and first bit of written byte is low,
         // copy controller state into shift register.
@@ -5,6 +5,7 @@ pub mod debugger;
 pub mod lookup_table;
 mod operation_funcs;
 mod step;
+pub mod trace;
 
 pub use self::cpu_def::Cpu;
 pub use self::step::step_cpu;
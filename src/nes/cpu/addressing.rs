@@ -1,4 +1,4 @@
-use crate::nes::mem::{read_mem, write_mem};
+use crate::nes::mem::{dummy_read_mem, read_mem, write_mem};
 use crate::nes::Nes;
 use crate::util::concat_u8;
 
@@ -93,18 +93,18 @@ pub fn read_from_address(nes: &mut Nes) {
 }
 pub fn dummy_read_from_address(nes: &mut Nes) {
     let addr = nes.cpu.get_address();
-    nes.cpu.data = read_mem(addr, nes);
+    nes.cpu.data = dummy_read_mem(addr, nes);
 }
 
 pub fn dummy_read_from_stack(nes: &mut Nes) {
-    nes.cpu.data = read_mem(nes.cpu.s as u16, nes);
+    nes.cpu.data = dummy_read_mem(nes.cpu.s as u16, nes);
 }
 
 pub fn dummy_read_from_pc_address(nes: &mut Nes) {
-    nes.cpu.data = read_mem(nes.cpu.pc, nes);
+    nes.cpu.data = dummy_read_mem(nes.cpu.pc, nes);
 }
 pub fn dummy_read_from_indirect_address(nes: &mut Nes) {
-    nes.cpu.data = read_mem(nes.cpu.get_pointer(), nes);
+    nes.cpu.data = dummy_read_mem(nes.cpu.get_pointer(), nes);
 }
 
 // Write data
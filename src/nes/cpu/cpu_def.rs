@@ -54,6 +54,29 @@ impl Cpu {
         }
     }
 
+    // The reset line runs the interrupt sequence with writes to the stack suppressed, so S still
+    // goes down by 3. Registers other than S, P.I and PC are left as they were.
+    pub fn reset(&mut self, reset_vector: u16) {
+        *self = Cpu {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            s: self.s.wrapping_sub(3),
+            p_n: self.p_n,
+            p_v: self.p_v,
+            p_d: self.p_d,
+            p_i: true,
+            p_z: self.p_z,
+            p_c: self.p_c,
+            pc: reset_vector,
+            cycles: self.cycles + 7,
+            ppustatus_read_time: self.ppustatus_read_time,
+            open_bus: self.open_bus,
+            instruction_count: self.instruction_count,
+            ..Default::default()
+        };
+    }
+
     pub fn set_upper_pc(&mut self, byte: u8) {
         self.pc &= 0b00000000_11111111;
         self.pc |= (byte as u16) << 8;
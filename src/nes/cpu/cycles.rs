@@ -337,7 +337,12 @@ pub fn processing_cycles(nes: &mut Nes, instruction_cycle: i8) {
     match nes.cpu.instruction.category {
         Read => match adjusted_cycle {
             1 => {
-                read_from_address(nes);
+                // With a page crossed the address is wrong, and what's read is thrown away
+                if nes.cpu.internal_carry_out {
+                    dummy_read_from_address(nes);
+                } else {
+                    read_from_address(nes);
+                }
                 add_lower_address_carry_bit_to_upper_address(nes);
                 // Continue to next instruction if page wasn't crossed
                 if !nes.cpu.internal_carry_out {
@@ -1,82 +1,382 @@
-use crate::nes::cpu::lookup_table::{Mode, INSTRUCTIONS};
+use super::lookup_table::{Category, Instruction, Mode, Name, INSTRUCTIONS};
+use super::trace;
+use crate::labels::{LabelAddress, Labels};
+use crate::nes::cartridge::cartridge_def::KB;
+use crate::nes::cdl::{PRG_CODE, PRG_DATA, PRG_OPCODE};
+use crate::nes::Nes;
 use crate::util::concat_u8;
 
+/*
+    Disassembly for the CPU debugger.
+
+    Code is found by following control flow from the interrupt vectors and from wherever the CPU
+    is when the debugger looks, through branches, jumps and subroutine calls. Bytes that haven't
+    been reached are shown as data, so data sitting between code doesn't knock the instructions
+    after it out of alignment.
+
+    What's been found is recorded against PRG ROM offsets rather than CPU addresses, so it builds
+    up as different banks get mapped in. Jumps into a switchable window are followed into whichever
+    bank is mapped there at the time. That's not always the bank the game will have mapped when it
+    gets there, but the CPU's position is always treated as code so it corrects itself.
+
+    When the Code/Data Logger is running, every opcode it has seen executed is followed too, and
+    bytes it has only seen read as data aren't.
+
+    As well as what's mapped in at $8000-$FFFF, any bank of PRG ROM can be listed on its own, with
+    whatever code was found in it while it was mapped. A bank is shown at the address it's mapped
+    to at the moment, or from $8000 if it isn't mapped, in which case absolute addresses in it may
+    refer to some other bank.
+*/
+
+// The smallest bank any supported mapper switches
+pub const BANK_SIZE: usize = 8 * KB;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum DisassemblyView {
+    // 0x8000-0xFFFF as currently mapped
+    #[default]
+    Mapped,
+    // One BANK_SIZE bank of PRG ROM, mapped or not
+    Bank(usize),
+}
+
+impl DisassemblyView {
+    pub fn label(&self) -> String {
+        match self {
+            DisassemblyView::Mapped => "Mapped ($8000-$FFFF)".to_owned(),
+            DisassemblyView::Bank(bank) => {
+                format!("PRG ROM bank {bank} (${:05X})", bank * BANK_SIZE)
+            }
+        }
+    }
+
+    pub fn bank_count(nes: &Nes) -> usize {
+        nes.cart.rom_data().prg_rom.len().div_ceil(BANK_SIZE)
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum InstrBytes {
     I1(u8),
     I2(u8, u8),
     I3(u8, u8, u8),
+    // A byte that isn't known to be code
+    Data(u8),
 }
 
 #[derive(Debug, Copy, Clone)]
 pub struct CpuDebuggerInstruction {
     pub opc_addr: u16,
+    // Where the instruction is in PRG ROM, which isn't necessarily mapped to opc_addr right now
+    pub prg_rom_offset: usize,
     pub bytes: InstrBytes,
 }
 
 impl CpuDebuggerInstruction {
-    pub fn debug_string(&self) -> String {
-        let instr = match self.bytes {
-            InstrBytes::I1(opc) => INSTRUCTIONS[opc as usize],
-            InstrBytes::I2(opc, ..) => INSTRUCTIONS[opc as usize],
-            InstrBytes::I3(opc, ..) => INSTRUCTIONS[opc as usize],
+    // Whether the instruction is what the CPU would see at opc_addr at the moment
+    pub fn is_mapped(&self, nes: &Nes) -> bool {
+        nes.cart.prg_rom_offset(self.opc_addr) == self.prg_rom_offset
+    }
+
+    // In the same syntax as CPU traces, e.g. "C72D  90 04     BCC $C733", with labels in place of
+    // addresses and the label and comment for this address at the end
+    pub fn debug_string(&self, nes: &Nes, labels: &Labels) -> String {
+        let text = match self.instruction() {
+            Some((instr, operands)) => {
+                format!("{:?}{}", instr.name, self.operand_text(instr, &operands))
+            }
+            None => String::new(),
         };
-        match self.bytes {
-            InstrBytes::I1(_) => match instr.mode {
-                Mode::Accumulator | Mode::Implied => {
-                    format!("{:04X} {:#?}", self.opc_addr, instr.name)
-                }
-                _ => unreachable!(),
-            },
-            InstrBytes::I2(_, arg1) => match instr.mode {
-                Mode::Immediate => format!("{:04X} {:#?} {:02X}", self.opc_addr, instr.name, arg1),
-                Mode::ZeroPage => {
-                    format!("{:04X} {:#?} ZP[{:02X}]", self.opc_addr, instr.name, arg1)
+        self.line(text, nes, labels)
+    }
+
+    // Like debug_string, with the effective address and the value there for the instruction at PC
+    pub fn debug_string_with_values(&self, nes: &mut Nes, labels: &Labels) -> String {
+        match self.instruction() {
+            Some((instr, operands)) => {
+                let text = trace::disassemble(nes, self.opc_addr, instr, &operands);
+                self.line(text, nes, labels)
+            }
+            None => self.debug_string(nes, labels),
+        }
+    }
+
+    fn instruction(&self) -> Option<(Instruction, Vec<u8>)> {
+        let (opcode, operands) = match self.bytes {
+            InstrBytes::I1(opcode) => (opcode, vec![]),
+            InstrBytes::I2(opcode, arg1) => (opcode, vec![arg1]),
+            InstrBytes::I3(opcode, arg1, arg2) => (opcode, vec![arg1, arg2]),
+            InstrBytes::Data(_) => return None,
+        };
+        Some((INSTRUCTIONS[opcode as usize], operands))
+    }
+
+    fn line(&self, mut text: String, nes: &Nes, labels: &Labels) -> String {
+        // The operand's address always comes first, before any effective address or value
+        if let Some((addr, addr_text)) = self.operand_address() {
+            if let Some(label) = labels.get(nes, addr).filter(|label| !label.name.is_empty()) {
+                text = text.replacen(&addr_text, &label.name, 1);
+            }
+        }
+        let (bytes, marker, text) = match self.bytes {
+            InstrBytes::Data(byte) => (vec![byte], ' ', format!(".byte ${byte:02X}")),
+            InstrBytes::I1(opcode) => (vec![opcode], marker(opcode), text),
+            InstrBytes::I2(opcode, arg1) => (vec![opcode, arg1], marker(opcode), text),
+            InstrBytes::I3(opcode, arg1, arg2) => (vec![opcode, arg1, arg2], marker(opcode), text),
+        };
+        let bytes = bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let mut line = format!("{:04X}  {bytes:<8} {marker}{text}", self.opc_addr);
+
+        let label = if self.is_mapped(nes) {
+            labels.get(nes, self.opc_addr)
+        } else {
+            labels.get_exact(LabelAddress::PrgRom(self.prg_rom_offset))
+        };
+        if let Some(label) = label {
+            line += "  ;";
+            if !label.name.is_empty() {
+                line += &format!(" {}:", label.name);
+            }
+            if !label.comment.is_empty() {
+                line += &format!(" {}", label.comment.replace('\n', " "));
+            }
+        }
+        line
+    }
+
+    // The address an instruction's operand refers to, and how it's written
+    fn operand_address(&self) -> Option<(u16, String)> {
+        let (instr, operands) = self.instruction()?;
+        let arg = operands.first().copied().unwrap_or(0);
+        let arg16 = concat_u8(operands.get(1).copied().unwrap_or(0), arg);
+        match instr.mode {
+            Mode::ZeroPage
+            | Mode::ZeroPageX
+            | Mode::ZeroPageY
+            | Mode::IndirectX
+            | Mode::IndirectY => Some((arg as u16, format!("${arg:02X}"))),
+            Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::AbsoluteI => {
+                Some((arg16, format!("${arg16:04X}")))
+            }
+            Mode::Relative => {
+                let target = branch_target(self.opc_addr, arg);
+                Some((target, format!("${target:04X}")))
+            }
+            Mode::Implied | Mode::Accumulator | Mode::Immediate => None,
+        }
+    }
+
+    fn operand_text(&self, instr: Instruction, operands: &[u8]) -> String {
+        let arg = operands.first().copied().unwrap_or(0);
+        let arg16 = concat_u8(operands.get(1).copied().unwrap_or(0), arg);
+        match instr.mode {
+            Mode::Implied => String::new(),
+            Mode::Accumulator => " A".to_owned(),
+            Mode::Immediate => format!(" #${arg:02X}"),
+            Mode::ZeroPage => format!(" ${arg:02X}"),
+            Mode::ZeroPageX => format!(" ${arg:02X},X"),
+            Mode::ZeroPageY => format!(" ${arg:02X},Y"),
+            Mode::Absolute => format!(" ${arg16:04X}"),
+            Mode::AbsoluteX => format!(" ${arg16:04X},X"),
+            Mode::AbsoluteY => format!(" ${arg16:04X},Y"),
+            Mode::AbsoluteI => format!(" (${arg16:04X})"),
+            Mode::IndirectX => format!(" (${arg:02X},X)"),
+            Mode::IndirectY => format!(" (${arg:02X}),Y"),
+            Mode::Relative => format!(" ${:04X}", branch_target(self.opc_addr, arg)),
+        }
+    }
+}
+
+fn marker(opcode: u8) -> char {
+    if trace::is_unofficial_opcode(opcode) {
+        '*'
+    } else {
+        ' '
+    }
+}
+
+fn branch_target(addr: u16, offset: u8) -> u16 {
+    addr.wrapping_add(2).wrapping_add(offset as i8 as u16)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Byte {
+    Unknown,
+    Opcode,
+    Operand,
+}
+
+#[derive(Default)]
+pub struct Disassembler {
+    // What each byte of PRG ROM is
+    prg_rom: Vec<Byte>,
+}
+
+impl Disassembler {
+    // Follows any code that's newly reachable with the banks mapped in at the moment
+    pub fn update(&mut self, nes: &Nes) {
+        let prg_rom_len = nes.cart.rom_data().prg_rom.len();
+        if self.prg_rom.len() != prg_rom_len {
+            self.prg_rom = vec![Byte::Unknown; prg_rom_len];
+        }
+
+        // The CPU is definitely at an instruction, even if it was mistaken for data before
+        self.follow(nes, nes.cpu.pc, true);
+        if let Some(cdl) = nes.cdl.as_deref() {
+            for addr in 0x8000..=0xFFFF {
+                if cdl.prg_flags(nes.cart.prg_rom_offset(addr)) & PRG_OPCODE != 0 {
+                    self.follow(nes, addr, true);
                 }
-                Mode::ZeroPageX => {
-                    format!("{:04X} {:#?} ZP[{:02X}+X]", self.opc_addr, instr.name, arg1)
+            }
+        }
+        for vector in [0xFFFA, 0xFFFC, 0xFFFE] {
+            let addr = concat_u8(
+                nes.cart.read_prg_rom(vector + 1),
+                nes.cart.read_prg_rom(vector),
+            );
+            self.follow(nes, addr, false);
+        }
+    }
+
+    // Every line in the view
+    pub fn lines(&self, nes: &Nes, view: DisassemblyView) -> Vec<CpuDebuggerInstruction> {
+        match view {
+            DisassemblyView::Mapped => {
+                self.lines_in(nes, 0x8000, 0xFFFF, |addr| nes.cart.prg_rom_offset(addr))
+            }
+            DisassemblyView::Bank(bank) => {
+                let prg_rom_len = nes.cart.rom_data().prg_rom.len();
+                let start = bank * BANK_SIZE;
+                if start >= prg_rom_len {
+                    return Vec::new();
                 }
-                Mode::ZeroPageY => {
-                    format!("{:04X} {:#?} ZP[{:02X}+Y]", self.opc_addr, instr.name, arg1)
+                let base = [0x8000, 0xA000, 0xC000, 0xE000]
+                    .into_iter()
+                    .find(|&window| nes.cart.prg_rom_offset(window) == start)
+                    .unwrap_or(0x8000);
+                let last = base + (BANK_SIZE.min(prg_rom_len - start) - 1) as u16;
+                self.lines_in(nes, base, last, |addr| start + (addr - base) as usize)
+            }
+        }
+    }
+
+    // The lines from `first` to `last`, with `offset` giving where each address is in PRG ROM
+    fn lines_in(
+        &self,
+        nes: &Nes,
+        first: u16,
+        last: u16,
+        offset: impl Fn(u16) -> usize,
+    ) -> Vec<CpuDebuggerInstruction> {
+        let prg_rom = &nes.cart.rom_data().prg_rom;
+        let mut lines = Vec::new();
+        let mut addr = first;
+        loop {
+            let prg_rom_offset = offset(addr);
+            let opcode = prg_rom[prg_rom_offset];
+            let is_opcode = self.prg_rom[prg_rom_offset] == Byte::Opcode;
+            let mut len = 1;
+            if is_opcode {
+                len += INSTRUCTIONS[opcode as usize].number_of_operands() as u16;
+            }
+            // Instructions can't run off the end of what's shown
+            if last - addr < len - 1 {
+                len = 1;
+            }
+            let arg = |i: u16| prg_rom[offset(addr + i)];
+            let bytes = match len {
+                1 if is_opcode => InstrBytes::I1(opcode),
+                1 => InstrBytes::Data(opcode),
+                2 => InstrBytes::I2(opcode, arg(1)),
+                _ => InstrBytes::I3(opcode, arg(1), arg(2)),
+            };
+            lines.push(CpuDebuggerInstruction {
+                opc_addr: addr,
+                prg_rom_offset,
+                bytes,
+            });
+            if last - addr < len {
+                return lines;
+            }
+            addr += len;
+        }
+    }
+
+    fn byte(&self, nes: &Nes, addr: u16) -> Byte {
+        self.prg_rom[nes.cart.prg_rom_offset(addr)]
+    }
+
+    fn mark(&mut self, nes: &Nes, addr: u16, byte: Byte) {
+        self.prg_rom[nes.cart.prg_rom_offset(addr)] = byte;
+    }
+
+    // Recursive descent from `start`, without the recursion
+    fn follow(&mut self, nes: &Nes, start: u16, known_code: bool) {
+        if known_code && start >= 0x8000 && self.byte(nes, start) == Byte::Operand {
+            self.forget_instruction_over(nes, start);
+        }
+
+        let mut pending = vec![start];
+        while let Some(addr) = pending.pop() {
+            // Code running from RAM can change at any time, so it isn't worth following
+            if addr < 0x8000 || self.byte(nes, addr) == Byte::Opcode {
+                continue;
+            }
+            let opcode = nes.cart.read_prg_rom(addr);
+            let instr = INSTRUCTIONS[opcode as usize];
+            let len = 1 + instr.number_of_operands() as u16;
+            let Some(last) = addr.checked_add(len - 1) else {
+                continue;
+            };
+            // Overlapping code that's already been found means this is probably data
+            if (addr..=last).any(|a| self.byte(nes, a) != Byte::Unknown) {
+                continue;
+            }
+            if let Some(cdl) = nes.cdl.as_deref() {
+                let flags = cdl.prg_flags(nes.cart.prg_rom_offset(addr));
+                if flags & (PRG_CODE | PRG_DATA) == PRG_DATA {
+                    continue;
                 }
-                Mode::IndirectX => format!(
-                    "{:04X} {:#?} MEM[ ZP16[{:02X}+X] ]",
-                    self.opc_addr, instr.name, arg1
-                ),
-                Mode::IndirectY => format!(
-                    "{:04X} {:#?} MEM[ ZP16[{:02X}]+Y ]",
-                    self.opc_addr, instr.name, arg1
-                ),
-                Mode::Relative => format!("{:04X} {:#?} (offset)", self.opc_addr, instr.name),
-                _ => unreachable!(),
-            },
-            InstrBytes::I3(_, arg1, arg2) => match instr.mode {
-                Mode::Absolute => format!(
-                    "{:04X} {:#?} MEM[{:04X}]",
-                    self.opc_addr,
-                    instr.name,
-                    concat_u8(arg2, arg1)
-                ),
-                Mode::AbsoluteX => format!(
-                    "{:04X} {:#?} MEM[{:04X}+X]",
-                    self.opc_addr,
-                    instr.name,
-                    concat_u8(arg2, arg1)
-                ),
-                Mode::AbsoluteY => format!(
-                    "{:04X} {:#?} MEM[{:04X}+Y]",
-                    self.opc_addr,
-                    instr.name,
-                    concat_u8(arg2, arg1)
-                ),
-                Mode::AbsoluteI => format!(
-                    "{:04X} {:#?} MEM[ MEM16[{:04X}] ]",
-                    self.opc_addr,
-                    instr.name,
-                    concat_u8(arg2, arg1)
-                ),
-                _ => unreachable!(),
-            },
+            }
+            self.mark(nes, addr, Byte::Opcode);
+            for operand_addr in addr + 1..=last {
+                self.mark(nes, operand_addr, Byte::Operand);
+            }
+
+            let arg = nes.cart.read_prg_rom(addr.wrapping_add(1));
+            let arg16 = concat_u8(nes.cart.read_prg_rom(addr.wrapping_add(2)), arg);
+            let next = addr.wrapping_add(len);
+            match (instr.name, instr.mode) {
+                (Name::JMP, Mode::Absolute) => pending.push(arg16),
+                // Where an indirect JMP goes depends on RAM, which the debugger can't predict
+                (Name::JMP, _) | (Name::RTS | Name::RTI | Name::BRK | Name::JAM, _) => {}
+                (Name::JSR, _) => pending.extend([next, arg16]),
+                (_, Mode::Relative) => pending.extend([next, branch_target(addr, arg)]),
+                _ if instr.category == Category::Unimplemented => {}
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    // Unmarks the instruction that `addr` was thought to be an operand of
+    fn forget_instruction_over(&mut self, nes: &Nes, addr: u16) {
+        for back in 1..=2 {
+            let Some(start) = addr.checked_sub(back) else {
+                return;
+            };
+            if start < 0x8000 || self.byte(nes, start) != Byte::Opcode {
+                continue;
+            }
+            let opcode = nes.cart.read_prg_rom(start);
+            let len = 1 + INSTRUCTIONS[opcode as usize].number_of_operands() as u16;
+            for a in start..=start + (len - 1) {
+                self.mark(nes, a, Byte::Unknown);
+            }
+            return;
         }
     }
 }
@@ -5,6 +5,10 @@ use super::cycles::{
 };
 use super::lookup_table::{Category::*, INSTRUCTIONS};
 use super::operation_funcs::set_interrupt_inhibit_flag;
+use super::trace::trace_instruction;
+use crate::nes::breakpoints::{self, BreakEvent};
+use crate::nes::cdl::{self, PRG_OPCODE};
+use crate::nes::events::{self, EventKind};
 use crate::nes::mem::read_mem;
 use crate::nes::Nes;
 
@@ -41,6 +45,10 @@ pub fn step_cpu(nes: &mut Nes) -> bool {
                     nes.cpu.nmi_edge_detector_output = false;
                     nes.cpu.nmi_pending = false;
                     nes.cpu.interrupt_cycle = -1;
+                    if !nes.breakpoints.is_empty() {
+                        breakpoints::check(nes, BreakEvent::Nmi);
+                        breakpoints::check(nes, BreakEvent::Execute(nes.cpu.pc));
+                    }
                 }
                 _ => unreachable!(),
             }
@@ -74,12 +82,25 @@ pub fn step_cpu(nes: &mut Nes) -> bool {
                     fetch_upper_pc_from_interrupt_vector(nes);
                     nes.cpu.irq_pending = false;
                     nes.cpu.interrupt_cycle = -1;
+                    if !nes.breakpoints.is_empty() {
+                        breakpoints::check(nes, BreakEvent::Irq);
+                        breakpoints::check(nes, BreakEvent::Execute(nes.cpu.pc));
+                    }
                 }
                 _ => unreachable!(),
             }
             nes.cpu.interrupt_cycle += 1;
         } else {
+            if nes.tracer.is_some() {
+                trace_instruction(nes);
+            }
             let opcode = read_mem(nes.cpu.pc, nes);
+            if nes.cdl.is_some() && nes.cpu.pc >= 0x8000 {
+                cdl::log_prg(nes, nes.cpu.pc, PRG_OPCODE);
+            }
+            if let Some(log) = nes.events.as_mut() {
+                log.set_instruction_addr(nes.cpu.pc);
+            }
             nes.cpu.instruction = INSTRUCTIONS[opcode as usize];
             if nes.cpu.instruction.category == Unimplemented {
                 unimplemented!(
@@ -139,9 +160,16 @@ pub fn step_cpu(nes: &mut Nes) -> bool {
 fn end_cycle(nes: &mut Nes) {
     if !nes.cpu.prev_nmi_signal && nes.ppu.nmi_line {
         nes.cpu.nmi_edge_detector_output = true;
+        if nes.events.is_some() {
+            events::log(nes, EventKind::Nmi, 0, 0);
+        }
     }
     nes.cpu.prev_nmi_signal = nes.ppu.nmi_line;
-    nes.cpu.prev_irq_signal = nes.apu.asserting_irq() || nes.cart.asserting_irq();
+    let cart_irq = nes.cart.asserting_irq();
+    if nes.events.is_some() {
+        events::log_cart_irq(nes, cart_irq);
+    }
+    nes.cpu.prev_irq_signal = nes.apu.asserting_irq() || cart_irq;
 
     nes.cpu.cycles += 1;
     nes.cpu.instruction_cycle += 1;
@@ -170,4 +198,15 @@ fn end_instr(nes: &mut Nes) {
     nes.cpu.instruction_done = false;
 
     nes.cpu.instruction_count += 1;
+
+    // Checked here rather than at the opcode fetch so the emulator stops before the instruction.
+    // If an interrupt is taken next, the handler's first instruction is checked once it's entered.
+    if !nes.breakpoints.is_empty() && !taking_interrupt(nes) {
+        breakpoints::check(nes, BreakEvent::Execute(nes.cpu.pc));
+    }
+}
+
+// Whether the next step_cpu starts an interrupt sequence rather than fetching an opcode
+fn taking_interrupt(nes: &Nes) -> bool {
+    nes.cpu.nmi_pending || (nes.cpu.irq_pending && !nes.cpu.p_i)
 }
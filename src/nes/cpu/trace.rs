@@ -0,0 +1,204 @@
+use super::lookup_table::{Instruction, Mode::*, Name::*, INSTRUCTIONS};
+use crate::nes::mem::peek_mem;
+use crate::nes::Nes;
+use crate::util::concat_u8;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/*
+    Logs one line per instruction in the same layout as nestest.log, so traces can be diffed
+    against it (or against other emulators that use the same format):
+
+    C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+
+    Lines are made at the opcode fetch, before the instruction runs. Memory values in the
+    disassembly are peeked so tracing doesn't change what the CPU sees.
+*/
+
+enum TraceOutput {
+    File(BufWriter<File>),
+    // Oldest lines are dropped once it's full
+    Memory(VecDeque<String>, usize),
+}
+
+pub struct CpuTracer {
+    output: TraceOutput,
+    error: Option<io::Error>,
+}
+
+impl CpuTracer {
+    pub fn to_file(path: &Path) -> io::Result<CpuTracer> {
+        Ok(CpuTracer {
+            output: TraceOutput::File(BufWriter::new(File::create(path)?)),
+            error: None,
+        })
+    }
+
+    pub fn in_memory(max_lines: usize) -> CpuTracer {
+        CpuTracer {
+            output: TraceOutput::Memory(VecDeque::with_capacity(max_lines), max_lines),
+            error: None,
+        }
+    }
+
+    // Empty when writing to a file
+    pub fn lines(&self) -> &VecDeque<String> {
+        static NO_LINES: VecDeque<String> = VecDeque::new();
+        match &self.output {
+            TraceOutput::Memory(lines, _) => lines,
+            TraceOutput::File(_) => &NO_LINES,
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        matches!(self.output, TraceOutput::File(_))
+    }
+
+    // Writing stops after the first error
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            TraceOutput::File(file) => file.flush(),
+            TraceOutput::Memory(..) => Ok(()),
+        }
+    }
+
+    fn write_line(&mut self, line: String) {
+        match &mut self.output {
+            TraceOutput::File(file) => {
+                if self.error.is_none() {
+                    if let Err(err) = writeln!(file, "{line}") {
+                        self.error = Some(err);
+                    }
+                }
+            }
+            TraceOutput::Memory(lines, max_lines) => {
+                if lines.len() == *max_lines {
+                    lines.pop_front();
+                }
+                lines.push_back(line);
+            }
+        }
+    }
+}
+
+// Called by step_cpu on each opcode fetch while a tracer is attached
+pub fn trace_instruction(nes: &mut Nes) {
+    let line = trace_line(nes);
+    if let Some(tracer) = nes.tracer.as_mut() {
+        tracer.write_line(line);
+    }
+}
+
+pub fn trace_line(nes: &mut Nes) -> String {
+    let pc = nes.cpu.pc;
+    let opcode = peek_mem(pc, nes);
+    let instr = INSTRUCTIONS[opcode as usize];
+    let operands: Vec<u8> = (1..=instr.number_of_operands() as u16)
+        .map(|i| peek_mem(pc.wrapping_add(i), nes))
+        .collect();
+
+    let bytes = std::iter::once(opcode)
+        .chain(operands.iter().copied())
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let marker = if is_unofficial_opcode(opcode) {
+        '*'
+    } else {
+        ' '
+    };
+    let disassembly = disassemble(nes, pc, instr, &operands);
+
+    // nestest.log counts the pre-render line as 261
+    let scanline = match nes.ppu.scanline {
+        -1 => 261,
+        scanline => scanline,
+    };
+    let cpu = &nes.cpu;
+    format!(
+        "{pc:04X}  {bytes:<8} {marker}{disassembly:<32}\
+         A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{scanline:>3},{:>3} CYC:{}",
+        cpu.a,
+        cpu.x,
+        cpu.y,
+        // Bit 5 doesn't exist in the CPU but always reads as set
+        cpu.get_p() | 0b0010_0000,
+        cpu.s,
+        nes.ppu.scanline_cycle,
+        cpu.cycles,
+    )
+}
+
+// Instruction::is_unofficial only covers unofficial mnemonics, there are also unofficial NOPs and
+// a copy of SBC immediate
+pub(super) fn is_unofficial_opcode(opcode: u8) -> bool {
+    let instr = INSTRUCTIONS[opcode as usize];
+    instr.is_unofficial() || (instr.name == NOP && opcode != 0xEA) || opcode == 0xEB
+}
+
+pub(super) fn disassemble(nes: &mut Nes, pc: u16, instr: Instruction, operands: &[u8]) -> String {
+    let arg = operands.first().copied().unwrap_or(0);
+    let arg16 = concat_u8(operands.get(1).copied().unwrap_or(0), arg);
+    let (x, y) = (nes.cpu.x, nes.cpu.y);
+    let mut peek = |addr: u16| peek_mem(addr, nes);
+
+    let operand = match instr.mode {
+        Implied => String::new(),
+        Accumulator => "A".to_owned(),
+        Immediate => format!("#${arg:02X}"),
+        ZeroPage => format!("${arg:02X} = {:02X}", peek(arg as u16)),
+        ZeroPageX => {
+            let addr = arg.wrapping_add(x);
+            format!("${arg:02X},X @ {addr:02X} = {:02X}", peek(addr as u16))
+        }
+        ZeroPageY => {
+            let addr = arg.wrapping_add(y);
+            format!("${arg:02X},Y @ {addr:02X} = {:02X}", peek(addr as u16))
+        }
+        Absolute if matches!(instr.name, JMP | JSR) => format!("${arg16:04X}"),
+        Absolute => format!("${arg16:04X} = {:02X}", peek(arg16)),
+        AbsoluteX => {
+            let addr = arg16.wrapping_add(x as u16);
+            format!("${arg16:04X},X @ {addr:04X} = {:02X}", peek(addr))
+        }
+        AbsoluteY => {
+            let addr = arg16.wrapping_add(y as u16);
+            format!("${arg16:04X},Y @ {addr:04X} = {:02X}", peek(addr))
+        }
+        // The high byte of the pointer is read without carrying into the page
+        AbsoluteI => {
+            let high_byte_addr = (arg16 & 0xFF00) | (arg16.wrapping_add(1) & 0x00FF);
+            let target = concat_u8(peek(high_byte_addr), peek(arg16));
+            format!("(${arg16:04X}) = {target:04X}")
+        }
+        IndirectX => {
+            let pointer = arg.wrapping_add(x);
+            let addr = concat_u8(peek(pointer.wrapping_add(1) as u16), peek(pointer as u16));
+            format!(
+                "(${arg:02X},X) @ {pointer:02X} = {addr:04X} = {:02X}",
+                peek(addr)
+            )
+        }
+        IndirectY => {
+            let base = concat_u8(peek(arg.wrapping_add(1) as u16), peek(arg as u16));
+            let addr = base.wrapping_add(y as u16);
+            format!(
+                "(${arg:02X}),Y = {base:04X} @ {addr:04X} = {:02X}",
+                peek(addr)
+            )
+        }
+        Relative => format!("${:04X}", pc.wrapping_add(2).wrapping_add(arg as i8 as u16)),
+    };
+
+    if operand.is_empty() {
+        format!("{:?}", instr.name)
+    } else {
+        format!("{:?} {operand}", instr.name)
+    }
+}
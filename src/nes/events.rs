@@ -0,0 +1,112 @@
+use crate::nes::Nes;
+use std::mem;
+
+/*
+    Event log for the PPU event viewer.
+
+    Records PPU register accesses, OAM DMA, mapper register writes and interrupts along with
+    where the PPU was when they happened, so mid-frame writes can be lined up against the dots
+    they affect. Events are kept for the frame in progress and the one before it, a new frame
+    starts at the pre-render scanline.
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    PpuRead,
+    PpuWrite,
+    OamDma,
+    MapperWrite,
+    MapperIrq,
+    Nmi,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 6] = [
+        EventKind::PpuRead,
+        EventKind::PpuWrite,
+        EventKind::OamDma,
+        EventKind::MapperWrite,
+        EventKind::MapperIrq,
+        EventKind::Nmi,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            EventKind::PpuRead => "PPU register read",
+            EventKind::PpuWrite => "PPU register write",
+            EventKind::OamDma => "OAM DMA",
+            EventKind::MapperWrite => "Mapper register write",
+            EventKind::MapperIrq => "Mapper IRQ",
+            EventKind::Nmi => "NMI",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    // Zero for interrupts
+    pub addr: u16,
+    pub value: u8,
+    // Address of the instruction that was running
+    pub pc: u16,
+    pub scanline: i32,
+    pub cycle: i32,
+    pub cpu_cycle: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct EventLog {
+    current: Vec<Event>,
+    previous: Vec<Event>,
+    instruction_addr: u16,
+    cart_irq: bool,
+}
+
+impl EventLog {
+    pub fn current_frame(&self) -> &[Event] {
+        &self.current
+    }
+
+    pub fn previous_frame(&self) -> &[Event] {
+        &self.previous
+    }
+
+    // Called at the start of the pre-render scanline
+    pub fn new_frame(&mut self) {
+        mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
+    }
+
+    // Called on each opcode fetch
+    pub fn set_instruction_addr(&mut self, addr: u16) {
+        self.instruction_addr = addr;
+    }
+}
+
+pub fn log(nes: &mut Nes, kind: EventKind, addr: u16, value: u8) {
+    let (scanline, cycle, cpu_cycle) = (nes.ppu.scanline, nes.ppu.scanline_cycle, nes.cpu.cycles);
+    if let Some(log) = nes.events.as_mut() {
+        log.current.push(Event {
+            kind,
+            addr,
+            value,
+            pc: log.instruction_addr,
+            scanline,
+            cycle,
+            cpu_cycle,
+        });
+    }
+}
+
+// Called every CPU cycle with the cartridge's IRQ line, logs when it goes high
+pub fn log_cart_irq(nes: &mut Nes, asserting: bool) {
+    let Some(log) = nes.events.as_mut() else {
+        return;
+    };
+    let rising = asserting && !log.cart_irq;
+    log.cart_irq = asserting;
+    if rising {
+        self::log(nes, EventKind::MapperIrq, 0, 0);
+    }
+}
@@ -1,9 +1,23 @@
-use crate::nes::apu::{apu_channels_write, apu_status_read, apu_status_write};
+use crate::nes::apu::{apu_channels_write, apu_status_peek, apu_status_read, apu_status_write};
+use crate::nes::breakpoints::{self, BreakEvent};
+use crate::nes::cdl::{self, PRG_CODE, PRG_DATA};
+use crate::nes::events::{self, EventKind};
 use crate::nes::Nes;
-use crate::nes::ppu::{memory_mapped_register_read, memory_mapped_register_write};
+use crate::nes::ppu::{
+    memory_mapped_register_peek, memory_mapped_register_read, memory_mapped_register_write,
+};
 use crate::nes::mem_consts::*;
+use std::rc::Rc;
 
 pub fn read_mem(addr: u16, nes: &mut Nes) -> u8 {
+    // Fetching an instruction (which is always from PC) is execution, not a read to watch
+    if !nes.breakpoints.is_empty() && addr != nes.cpu.pc {
+        breakpoints::check(nes, BreakEvent::CpuRead(addr));
+    }
+    read_mem_unwatched(addr, nes)
+}
+
+fn read_mem_unwatched(addr: u16, nes: &mut Nes) -> u8 {
     let value_read = match addr {
         ..=WRAM_END_1FFF =>
             nes.wram[(addr % 0x800) as usize],
@@ -28,10 +42,92 @@ pub fn read_mem(addr: u16, nes: &mut Nes) -> u8 {
     if addr != APU_STATUS_4015 {
         nes.cpu.open_bus = value_read;
     }
+    // The CPU only reads from PC to fetch the instruction it's running
+    if nes.cdl.is_some() && addr >= PRG_ROM_START_8000 {
+        let flags = if addr == nes.cpu.pc { PRG_CODE } else { PRG_DATA };
+        cdl::log_prg(nes, addr, flags);
+    }
+    if nes.events.is_some() && (PPU_REG_START_2000..=PPU_REG_END_3FFF).contains(&addr) {
+        events::log(nes, EventKind::PpuRead, addr, value_read);
+    }
     value_read
 }
 
+// For reads that are logged differently by the caller
+pub fn read_mem_unlogged(addr: u16, nes: &mut Nes) -> u8 {
+    let cdl = nes.cdl.take();
+    let value = read_mem(addr, nes);
+    nes.cdl = cdl;
+    value
+}
+
+// Reads the CPU throws away don't say anything about what the bytes are, so they aren't logged,
+// and the program never sees the value so they don't trigger read breakpoints either
+pub fn dummy_read_mem(addr: u16, nes: &mut Nes) -> u8 {
+    let cdl = nes.cdl.take();
+    let value = read_mem_unwatched(addr, nes);
+    nes.cdl = cdl;
+    value
+}
+
+// Reads memory without any side effects, for debugging.
+// Registers show what reading them would return, without e.g. clearing the vblank flag or
+// shifting the controller.
+pub fn peek_mem(addr: u16, nes: &mut Nes) -> u8 {
+    match addr {
+        ..=WRAM_END_1FFF =>
+            nes.wram[(addr % 0x800) as usize],
+        PPU_REG_START_2000..=PPU_REG_END_3FFF =>
+            memory_mapped_register_peek(addr, nes),
+        OPEN_BUS_4000..=OPEN_BUS_4014 =>
+            nes.cpu.open_bus,
+        APU_STATUS_4015 =>
+            apu_status_peek(nes),
+        CON_1_4016 =>
+            nes.con1.peek_button_state() | (nes.cpu.open_bus & 0b1110_0000),
+        CON_2_4017 =>
+            nes.con2.peek_button_state() | (nes.cpu.open_bus & 0b1110_0000),
+        OPEN_BUS_4018..=OPEN_BUS_5FFF =>
+            nes.cpu.open_bus,
+        PRG_RAM_START_6000..=PRG_RAM_END_7FFF =>
+            nes.cart.read_prg_ram(addr).unwrap_or(nes.cpu.open_bus),
+        PRG_ROM_START_8000.. =>
+            nes.cart.read_prg_rom(addr),
+    }
+}
+
+// Writes memory for the debugger. Only memory can be changed, registers are left alone because
+// writing to them has side effects. Returns whether anything was written.
+pub fn poke_mem(addr: u16, val: u8, nes: &mut Nes) -> bool {
+    match addr {
+        ..=WRAM_END_1FFF =>
+            nes.wram[(addr % 0x800) as usize] = val,
+        PRG_RAM_START_6000..=PRG_RAM_END_7FFF if nes.cart.read_prg_ram(addr).is_some() =>
+            nes.cart.write_prg_ram(addr, val),
+        PRG_ROM_START_8000.. => {
+            let offset = nes.cart.prg_rom_offset(addr);
+            Rc::make_mut(&mut nes.cart.rom_data_mut().prg_rom)[offset] = val;
+        }
+        _ => return false,
+    }
+    true
+}
+
 pub fn write_mem(addr: u16, val: u8, nes: &mut Nes) {
+    if !nes.breakpoints.is_empty() {
+        breakpoints::check(nes, BreakEvent::CpuWrite(addr));
+    }
+    if nes.events.is_some() {
+        let kind = match addr {
+            PPU_REG_START_2000..=PPU_REG_END_3FFF => Some(EventKind::PpuWrite),
+            OAMDMA_4014 => Some(EventKind::OamDma),
+            PRG_ROM_START_8000.. => Some(EventKind::MapperWrite),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            events::log(nes, kind, addr, val);
+        }
+    }
     nes.cpu.open_bus = val;
     match addr {
         ..=WRAM_END_1FFF =>
@@ -49,10 +145,12 @@ pub fn write_mem(addr: u16, val: u8, nes: &mut Nes) {
         }
         APU_STATUS_4015 =>
             apu_status_write(val, nes),
-        CON_1_4016 =>
-            nes.con1.write_to_data_latch(val),
-        CON_2_AND_APU_FRAME_COUNTER_4017 => {
+        // The strobe line goes to both controller ports
+        CON_1_4016 => {
+            nes.con1.write_to_data_latch(val);
             nes.con2.write_to_data_latch(val);
+        }
+        CON_2_AND_APU_FRAME_COUNTER_4017 => {
             nes.apu.frame_sequencer_mode_1 = (val & 0b1000_0000) > 0;
             nes.apu.frame_sequencer_interrupt_inhibit = (val & 0b0100_0000) > 0;
         }
@@ -0,0 +1,98 @@
+use crate::nes::cartridge::cartridge_def::ChrMem;
+use crate::nes::Nes;
+use std::rc::Rc;
+
+// The different memories the memory viewer can show, addressed from 0
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum MemoryRegion {
+    // As the CPU sees it, through the current bank mapping
+    #[default]
+    Cpu,
+    Wram,
+    PrgRom,
+    PrgRam,
+    // CHR ROM or RAM, whichever the cartridge has
+    Chr,
+    Nametables,
+    Palette,
+    Oam,
+}
+
+impl MemoryRegion {
+    pub const ALL: [MemoryRegion; 8] = [
+        MemoryRegion::Cpu,
+        MemoryRegion::Wram,
+        MemoryRegion::PrgRom,
+        MemoryRegion::PrgRam,
+        MemoryRegion::Chr,
+        MemoryRegion::Nametables,
+        MemoryRegion::Palette,
+        MemoryRegion::Oam,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MemoryRegion::Cpu => "CPU memory",
+            MemoryRegion::Wram => "WRAM",
+            MemoryRegion::PrgRom => "PRG ROM",
+            MemoryRegion::PrgRam => "PRG RAM",
+            MemoryRegion::Chr => "CHR",
+            MemoryRegion::Nametables => "Nametables",
+            MemoryRegion::Palette => "Palette RAM",
+            MemoryRegion::Oam => "OAM",
+        }
+    }
+
+    pub fn len(&self, nes: &Nes) -> usize {
+        let rom_data = nes.cart.rom_data();
+        match self {
+            MemoryRegion::Cpu => 0x10000,
+            MemoryRegion::Wram => nes.wram.len(),
+            MemoryRegion::PrgRom => rom_data.prg_rom.len(),
+            MemoryRegion::PrgRam => rom_data.prg_ram.as_ref().map_or(0, |ram| ram.len()),
+            MemoryRegion::Chr => match &rom_data.chr_mem {
+                ChrMem::Rom(mem) | ChrMem::Ram(mem) => mem.len(),
+            },
+            MemoryRegion::Nametables => nes.ppu.vram.len(),
+            MemoryRegion::Palette => nes.ppu.palette_mem.len(),
+            MemoryRegion::Oam => nes.ppu.oam.len(),
+        }
+    }
+
+    // Reads without side effects, offset must be less than len()
+    pub fn peek(&self, nes: &mut Nes, offset: usize) -> u8 {
+        let rom_data = nes.cart.rom_data();
+        match self {
+            MemoryRegion::Cpu => nes.peek(offset as u16),
+            MemoryRegion::Wram => nes.wram[offset],
+            MemoryRegion::PrgRom => rom_data.prg_rom[offset],
+            MemoryRegion::PrgRam => rom_data.prg_ram.as_ref().map_or(0, |ram| ram[offset]),
+            MemoryRegion::Chr => rom_data.chr_mem.read(offset),
+            MemoryRegion::Nametables => nes.ppu.vram[offset],
+            MemoryRegion::Palette => nes.ppu.palette_mem[offset],
+            MemoryRegion::Oam => nes.ppu.oam[offset],
+        }
+    }
+
+    // ROM can be edited too, the change lasts until the game is reloaded.
+    // Returns false if the byte can't be changed (CPU registers).
+    pub fn poke(&self, nes: &mut Nes, offset: usize, val: u8) -> bool {
+        let rom_data = nes.cart.rom_data_mut();
+        match self {
+            MemoryRegion::Cpu => return nes.poke(offset as u16, val),
+            MemoryRegion::Wram => nes.wram[offset] = val,
+            MemoryRegion::PrgRom => Rc::make_mut(&mut rom_data.prg_rom)[offset] = val,
+            MemoryRegion::PrgRam => match rom_data.prg_ram.as_mut() {
+                Some(ram) => Rc::make_mut(ram)[offset] = val,
+                None => return false,
+            },
+            MemoryRegion::Chr => match &mut rom_data.chr_mem {
+                ChrMem::Rom(mem) | ChrMem::Ram(mem) => Rc::make_mut(mem)[offset] = val,
+            },
+            MemoryRegion::Nametables => nes.ppu.vram[offset] = val,
+            MemoryRegion::Palette => nes.ppu.palette_mem[offset] = val,
+            MemoryRegion::Oam => nes.ppu.oam[offset] = val,
+        }
+        true
+    }
+}
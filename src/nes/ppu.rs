@@ -1,9 +1,11 @@
+pub mod debug_view;
 mod mem;
+pub mod palette;
 mod ppu_def;
 mod step;
 
-pub use self::mem::{memory_mapped_register_read, memory_mapped_register_write, increment_v_after_ppudata_access, read_vram, write_vram, set_dynamic_latch, get_dynamic_latch};
-pub use self::ppu_def::Ppu;
+pub use self::mem::{memory_mapped_register_read, memory_mapped_register_write, increment_v_after_ppudata_access, read_vram, write_vram, set_dynamic_latch, get_dynamic_latch, memory_mapped_register_peek, peek_vram};
+pub use self::ppu_def::{Ppu, ScanlineSprites};
 pub use self::step::{
     step_ppu, COARSE_X, COARSE_Y, FINE_Y, NAMETABLE, NAMETABLE_LSB, NAMETABLE_MSB,
 };
@@ -0,0 +1,261 @@
+use super::mem::peek_vram;
+use super::step::{COARSE_X, COARSE_Y, FINE_Y, NAMETABLE};
+use crate::nes::cartridge::cartridge_def::ChrMem;
+use crate::nes::Nes;
+
+/*
+    Pictures of PPU memory for the debug windows.
+
+    Everything here reads through peek_vram, so drawing doesn't disturb the address bus (which
+    mappers like MMC3 watch), breakpoints or the Code/Data Logger. Tiles are drawn with whatever
+    banks and palettes are current, which isn't necessarily what the game used mid-frame.
+*/
+
+// All four logical nametables, two across and two down
+pub const NAMETABLES_WIDTH: usize = 512;
+pub const NAMETABLES_HEIGHT: usize = 480;
+// Both pattern tables side by side, 16x16 tiles each
+pub const PATTERN_TABLES_WIDTH: usize = 256;
+pub const PATTERN_TABLES_HEIGHT: usize = 128;
+// All 32 palette entries in two rows, background then sprites
+pub const PALETTES_WIDTH: usize = 256;
+pub const PALETTES_HEIGHT: usize = 32;
+const PALETTE_SWATCH_SIZE: usize = 16;
+// All 64 sprites in an 8x8 grid of 8x16 cells, whatever size sprites are set to
+pub const SPRITES_WIDTH: usize = 64;
+pub const SPRITES_HEIGHT: usize = 128;
+// CHR is browsed 8 KB at a time, the size of the PPU's pattern table space
+const CHR_PAGE_SIZE: usize = 0x2000;
+
+#[derive(Copy, Clone, Debug)]
+pub struct NametableTile {
+    pub ppu_addr: u16,
+    pub tile: u8,
+    pub attr_addr: u16,
+    pub attr: u8,
+    // Which of the four background palettes the attribute picks for this tile
+    pub palette: u8,
+}
+
+// tile_x is 0..64 and tile_y is 0..60, across all four nametables
+pub fn nametable_tile(nes: &Nes, tile_x: usize, tile_y: usize) -> NametableTile {
+    let nametable = (tile_x / 32 + (tile_y / 30) * 2) as u16;
+    let (x, y) = ((tile_x % 32) as u16, (tile_y % 30) as u16);
+    let ppu_addr = 0x2000 + nametable * 0x400 + y * 32 + x;
+    let attr_addr = 0x23C0 + nametable * 0x400 + (y / 4) * 8 + x / 4;
+    let attr = peek_vram(attr_addr, nes);
+    let shift = ((y % 4) / 2) * 4 + ((x % 4) / 2) * 2;
+    NametableTile {
+        ppu_addr,
+        tile: peek_vram(ppu_addr, nes),
+        attr_addr,
+        attr,
+        palette: (attr >> shift) & 0b11,
+    }
+}
+
+// The 2-bit colour of each pixel in one row of a tile
+pub fn tile_row(nes: &Nes, pattern_table: u16, tile: u8, row: u16) -> [u8; 8] {
+    let addr = pattern_table + (tile as u16) * 16 + row;
+    pattern_row(peek_vram(addr, nes), peek_vram(addr + 8, nes))
+}
+
+fn pattern_row(lsb: u8, msb: u8) -> [u8; 8] {
+    std::array::from_fn(|i| (((msb >> (7 - i)) & 1) << 1) | ((lsb >> (7 - i)) & 1))
+}
+
+// The colour at a palette RAM address (0x3F00-0x3F1F), without emphasis or greyscale
+pub fn colour(nes: &Nes, palette_addr: u16) -> (u8, u8, u8) {
+    nes.palette.rgb(peek_vram(palette_addr, nes), 0)
+}
+
+// RGBA pixels of all four nametables with the current background pattern table
+pub fn render_nametables(nes: &Nes) -> Vec<u8> {
+    let pattern_table = if nes.ppu.bg_ptable_select {
+        0x1000
+    } else {
+        0x0000
+    };
+    let mut pixels = vec![0; NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 4];
+    for tile_y in 0..NAMETABLES_HEIGHT / 8 {
+        for tile_x in 0..NAMETABLES_WIDTH / 8 {
+            let tile = nametable_tile(nes, tile_x, tile_y);
+            // Colour 0 of every palette is the shared backdrop colour
+            let colours: [(u8, u8, u8); 4] = std::array::from_fn(|i| match i {
+                0 => colour(nes, 0x3F00),
+                _ => colour(nes, 0x3F00 + (tile.palette as u16) * 4 + i as u16),
+            });
+            for row in 0..8 {
+                let values = tile_row(nes, pattern_table, tile.tile, row as u16);
+                for (col, value) in values.into_iter().enumerate() {
+                    let (r, g, b) = colours[value as usize];
+                    let index = ((tile_y * 8 + row) * NAMETABLES_WIDTH + tile_x * 8 + col) * 4;
+                    pixels[index..index + 4].copy_from_slice(&[r, g, b, 255]);
+                }
+            }
+        }
+    }
+    pixels
+}
+
+// How many 8 KB pages of CHR ROM/RAM the cartridge has
+pub fn chr_pages(nes: &Nes) -> usize {
+    match &nes.cart.rom_data().chr_mem {
+        ChrMem::Rom(mem) | ChrMem::Ram(mem) => mem.len().div_ceil(CHR_PAGE_SIZE),
+    }
+}
+
+// RGBA pixels of both pattern tables. With no page they're drawn as the PPU currently sees them,
+// otherwise from that 8 KB page of CHR. With no palette they're drawn in shades of grey.
+pub fn render_pattern_tables(nes: &Nes, page: Option<usize>, palette: Option<u8>) -> Vec<u8> {
+    let colours: [(u8, u8, u8); 4] = match palette {
+        Some(palette) => std::array::from_fn(|i| match i {
+            0 => colour(nes, 0x3F00),
+            _ => colour(nes, 0x3F00 + (palette as u16) * 4 + i as u16),
+        }),
+        None => [(0, 0, 0), (85, 85, 85), (170, 170, 170), (255, 255, 255)],
+    };
+    let chr_mem = &nes.cart.rom_data().chr_mem;
+    let chr_len = match chr_mem {
+        ChrMem::Rom(mem) | ChrMem::Ram(mem) => mem.len(),
+    };
+    let byte = |addr: u16| match page {
+        Some(page) => {
+            let offset = page * CHR_PAGE_SIZE + addr as usize;
+            if offset < chr_len {
+                chr_mem.read(offset)
+            } else {
+                0
+            }
+        }
+        None => peek_vram(addr, nes),
+    };
+
+    let mut pixels = vec![0; PATTERN_TABLES_WIDTH * PATTERN_TABLES_HEIGHT * 4];
+    for table in 0..2 {
+        for tile in 0..256 {
+            let (tile_x, tile_y) = (table * 16 + tile % 16, tile / 16);
+            for row in 0..8 {
+                let addr = (table * 0x1000 + tile * 16 + row) as u16;
+                for (col, value) in pattern_row(byte(addr), byte(addr + 8))
+                    .into_iter()
+                    .enumerate()
+                {
+                    let (r, g, b) = colours[value as usize];
+                    let index = ((tile_y * 8 + row) * PATTERN_TABLES_WIDTH + tile_x * 8 + col) * 4;
+                    pixels[index..index + 4].copy_from_slice(&[r, g, b, 255]);
+                }
+            }
+        }
+    }
+    pixels
+}
+
+// RGBA pixels of a swatch for each palette entry, 0x3F00-0x3F0F on top and 0x3F10-0x3F1F below
+pub fn render_palettes(nes: &Nes) -> Vec<u8> {
+    let mut pixels = vec![0; PALETTES_WIDTH * PALETTES_HEIGHT * 4];
+    for y in 0..PALETTES_HEIGHT {
+        for x in 0..PALETTES_WIDTH {
+            let entry = (y / PALETTE_SWATCH_SIZE) * 16 + x / PALETTE_SWATCH_SIZE;
+            let (r, g, b) = colour(nes, 0x3F00 + entry as u16);
+            let index = (y * PALETTES_WIDTH + x) * 4;
+            pixels[index..index + 4].copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+    pixels
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Sprite {
+    // One less than the first scanline the sprite is drawn on
+    pub y: u8,
+    pub tile: u8,
+    pub attr: u8,
+    pub x: u8,
+}
+
+impl Sprite {
+    pub fn palette(&self) -> u8 {
+        self.attr & 0b11
+    }
+
+    pub fn behind_background(&self) -> bool {
+        self.attr & 0b0010_0000 != 0
+    }
+
+    pub fn flip_horizontal(&self) -> bool {
+        self.attr & 0b0100_0000 != 0
+    }
+
+    pub fn flip_vertical(&self) -> bool {
+        self.attr & 0b1000_0000 != 0
+    }
+}
+
+pub fn sprite(nes: &Nes, index: usize) -> Sprite {
+    let bytes = &nes.ppu.oam[index * 4..index * 4 + 4];
+    Sprite {
+        y: bytes[0],
+        tile: bytes[1],
+        attr: bytes[2],
+        x: bytes[3],
+    }
+}
+
+pub fn sprite_height(nes: &Nes) -> usize {
+    if nes.ppu.tall_sprites {
+        16
+    } else {
+        8
+    }
+}
+
+// RGBA pixels of every sprite as it would be drawn, transparent where it's transparent on screen
+pub fn render_sprites(nes: &Nes) -> Vec<u8> {
+    let height = sprite_height(nes);
+    let mut pixels = vec![0; SPRITES_WIDTH * SPRITES_HEIGHT * 4];
+    for index in 0..64 {
+        let sprite = sprite(nes, index);
+        let (cell_x, cell_y) = ((index % 8) * 8, (index / 8) * 16);
+        for row in 0..height {
+            let source_row = if sprite.flip_vertical() {
+                height - 1 - row
+            } else {
+                row
+            };
+            // 8x16 sprites take the pattern table from bit 0 and use that tile and the next
+            let (pattern_table, tile) = if nes.ppu.tall_sprites {
+                let tile = (sprite.tile & !1) + (source_row / 8) as u8;
+                ((sprite.tile as u16 & 1) * 0x1000, tile)
+            } else {
+                ((nes.ppu.sprite_ptable_select as u16) * 0x1000, sprite.tile)
+            };
+            let mut values = tile_row(nes, pattern_table, tile, (source_row % 8) as u16);
+            if sprite.flip_horizontal() {
+                values.reverse();
+            }
+            for (col, value) in values.into_iter().enumerate() {
+                if value == 0 {
+                    continue;
+                }
+                let palette_addr = 0x3F10 + (sprite.palette() as u16) * 4 + value as u16;
+                let (r, g, b) = colour(nes, palette_addr);
+                let index = ((cell_y + row) * SPRITES_WIDTH + cell_x + col) * 4;
+                pixels[index..index + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+    pixels
+}
+
+// Where in the picture from render_nametables the PPU would start drawing from
+pub fn scroll_position(v: u16, x: u8) -> (usize, usize) {
+    let nametable = ((v & NAMETABLE) >> 10) as usize;
+    let coarse_x = (v & COARSE_X) as usize;
+    let coarse_y = ((v & COARSE_Y) >> 5) as usize;
+    let fine_y = ((v & FINE_Y) >> 12) as usize;
+    let px = (nametable & 1) * 256 + coarse_x * 8 + x as usize;
+    // Coarse Y can be set past the bottom of a nametable into its attribute table
+    let py = ((nametable >> 1) * 240 + coarse_y * 8 + fine_y) % NAMETABLES_HEIGHT;
+    (px % NAMETABLES_WIDTH, py)
+}
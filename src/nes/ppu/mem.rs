@@ -1,3 +1,5 @@
+use crate::nes::breakpoints::{self, BreakEvent};
+use crate::nes::cdl::{self, CHR_READ, CHR_RENDERED};
 use crate::nes::cartridge::Mirroring;
 use crate::nes::{ppu, Nes};
 use crate::nes::mem_consts::*;
@@ -38,6 +40,25 @@ pub fn memory_mapped_register_read(addr: u16, nes: &mut Nes) -> u8 {
     }
 }
 
+// What reading a register would return, without any of the side effects of reading it
+pub fn memory_mapped_register_peek(addr: u16, nes: &Nes) -> u8 {
+    let latch = peek_dynamic_latch(nes);
+    match 0x2000 + (addr % 8) {
+        PPUSTATUS_2002 => nes.ppu.get_ppustatus_byte() | (latch & 0b0001_1111),
+        OAMDATA_2004 => nes.ppu.oam_addr,
+        PPUDATA_2007 if nes.ppu.addr_bus < 0x3F00 => nes.ppu.ppudata_buffer,
+        PPUDATA_2007 => {
+            let colour = nes.ppu.palette_mem[map_vram_addr_to_palette_addr(nes.ppu.v)];
+            if nes.ppu.greyscale {
+                colour & 0b0011_0000
+            } else {
+                colour
+            }
+        }
+        _ => latch,
+    }
+}
+
 pub fn memory_mapped_register_write(addr: u16, val: u8, nes: &mut Nes) {
     const PPU_WARMUP: u64 = 29658;
     if matches!(addr, PPUCTRL_2000 | PPUMASK_2001 | PPUSCROLL_2005 | PPUADDR_2006) && nes.cpu.cycles < PPU_WARMUP {
@@ -96,12 +117,22 @@ pub fn memory_mapped_register_write(addr: u16, val: u8, nes: &mut Nes) {
 }
 
 pub fn read_vram(addr: u16, nes: &mut Nes) -> u8 {
+    if !nes.breakpoints.is_empty() {
+        breakpoints::check(nes, BreakEvent::VramRead(addr));
+    }
     // Colour palette reads don't put anything on the PPU address bus
     if addr < PALETTE_RAM_START_3F00 {
         nes.ppu.addr_bus = addr;
     }
     match addr {
-        0x0000..=PATTERN_TABLE_END_1FFF => nes.cart.read_chr(addr),
+        0x0000..=PATTERN_TABLE_END_1FFF => {
+            if nes.cdl.is_some() {
+                // Outside of rendering, the only reads are the CPU's through PPUDATA
+                let rendering = (nes.ppu.show_bg || nes.ppu.show_sprites) && nes.ppu.scanline < 240;
+                cdl::log_chr(nes, addr, if rendering { CHR_RENDERED } else { CHR_READ });
+            }
+            nes.cart.read_chr(addr)
+        }
         VRAM_START_2000..=VRAM_END_3EFF => {
             let mapped_vram_addr = mirroring_mapping(addr, nes.cart.mirroring());
             nes.ppu.vram[mapped_vram_addr as usize]
@@ -118,7 +149,27 @@ pub fn read_vram(addr: u16, nes: &mut Nes) -> u8 {
     }
 }
 
+// Like read_vram but without touching the address bus, breakpoints or the Code/Data Logger, and
+// palette entries are returned without greyscale applied
+pub fn peek_vram(addr: u16, nes: &Nes) -> u8 {
+    match addr {
+        0x0000..=PATTERN_TABLE_END_1FFF => {
+            nes.cart.rom_data().chr_mem.read(nes.cart.chr_offset(addr))
+        }
+        VRAM_START_2000..=VRAM_END_3EFF => {
+            nes.ppu.vram[mirroring_mapping(addr, nes.cart.mirroring()) as usize]
+        }
+        PALETTE_RAM_START_3F00..=PALETTE_RAM_END_3FFF => {
+            nes.ppu.palette_mem[map_vram_addr_to_palette_addr(addr)]
+        }
+        x => panic!("Invalid PPU address {x:016b}"),
+    }
+}
+
 pub fn write_vram(addr: u16, val: u8, nes: &mut Nes) {
+    if !nes.breakpoints.is_empty() {
+        breakpoints::check(nes, BreakEvent::VramWrite(addr));
+    }
     if addr < PALETTE_RAM_START_3F00 {
         nes.ppu.addr_bus = addr;
     }
@@ -164,8 +215,14 @@ pub fn set_dynamic_latch(val: u8, nes: &mut Nes) {
 
 const PPU_DYNAMIC_LATCH_DECAY_TIME: u64 = 500000;
 pub fn get_dynamic_latch(nes: &mut Nes) -> u8 {
+    nes.ppu.dynamic_latch = peek_dynamic_latch(nes);
+    nes.ppu.dynamic_latch
+}
+
+fn peek_dynamic_latch(nes: &Nes) -> u8 {
     if nes.ppu.cycles - nes.ppu.dynamic_latch_last_set_cycle > PPU_DYNAMIC_LATCH_DECAY_TIME {
-        nes.ppu.dynamic_latch = 0;
+        0
+    } else {
+        nes.ppu.dynamic_latch
     }
-    nes.ppu.dynamic_latch
 }
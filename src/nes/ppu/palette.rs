@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::f32::consts::PI;
+use std::path::Path;
+use std::{fmt, fs};
+
+/*
+    Master palettes, turning the 6-bit colours in palette RAM into RGB.
+
+    Every palette has 512 entries, one set of 64 colours for each combination of the PPUMASK
+    emphasis bits, indexed the same way as 1536 byte .pal files: emphasis (bit 0 red, 1 green,
+    2 blue) * 64 + colour. Palettes with only 64 colours have the other sets worked out by
+    darkening the channels that aren't emphasised, like the 2C02 does.
+*/
+
+const COLOURS: usize = 64;
+const EMPHASIS_COMBINATIONS: usize = 8;
+// How much the 2C02 dims the signal during the parts of the colour wave that aren't emphasised.
+// The RGB approximation is used for palettes that don't come with their own emphasis colours.
+const SIGNAL_ATTENUATION: f32 = 0.746;
+const RGB_ATTENUATION: f32 = 0.816;
+
+// Composite signal voltages for luma levels 0-3, at the low and high points of the colour wave
+const SIGNAL_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f32 = 0.518;
+const SIGNAL_WHITE: f32 = 1.962;
+// Lines the decoded hues up with the colour wheel, in twelfths of a turn
+const HUE_OFFSET: f32 = 4.0;
+const SATURATION: f32 = 1.4;
+
+// Channel levels (0-7) of the RGB PPU's colours, written as octal digits
+static RGB_PPU_COLOURS: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022,
+    0o000, 0o000, 0o000, 0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140,
+    0o040, 0o053, 0o044, 0o000, 0o000, 0o000, 0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740,
+    0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o444, 0o000, 0o000, 0o777, 0o567, 0o657, 0o757,
+    0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o666, 0o000, 0o000,
+];
+
+static DEFAULT_COLOURS: [(u8, u8, u8); 64] = [
+    (84, 84, 84),
+    (0, 30, 116),
+    (8, 16, 144),
+    (48, 0, 136),
+    (68, 0, 100),
+    (92, 0, 48),
+    (84, 4, 0),
+    (60, 24, 0),
+    (32, 42, 0),
+    (8, 58, 0),
+    (0, 64, 0),
+    (0, 60, 0),
+    (0, 50, 60),
+    (0, 0, 0),
+    (0, 0, 0),
+    (0, 0, 0),
+    (152, 150, 152),
+    (8, 76, 196),
+    (48, 50, 236),
+    (92, 30, 228),
+    (136, 20, 176),
+    (160, 20, 100),
+    (152, 34, 32),
+    (120, 60, 0),
+    (84, 90, 0),
+    (40, 114, 0),
+    (8, 124, 0),
+    (0, 118, 40),
+    (0, 102, 120),
+    (0, 0, 0),
+    (0, 0, 0),
+    (0, 0, 0),
+    (236, 238, 236),
+    (76, 154, 236),
+    (120, 124, 236),
+    (176, 98, 236),
+    (228, 84, 236),
+    (236, 88, 180),
+    (236, 106, 100),
+    (212, 136, 32),
+    (160, 170, 0),
+    (116, 196, 0),
+    (76, 208, 32),
+    (56, 204, 108),
+    (56, 180, 204),
+    (60, 60, 60),
+    (0, 0, 0),
+    (0, 0, 0),
+    (236, 238, 236),
+    (168, 204, 236),
+    (188, 188, 236),
+    (212, 178, 236),
+    (236, 174, 236),
+    (236, 174, 212),
+    (236, 180, 176),
+    (228, 196, 144),
+    (204, 210, 120),
+    (180, 222, 120),
+    (168, 226, 144),
+    (152, 226, 180),
+    (160, 214, 228),
+    (160, 162, 160),
+    (0, 0, 0),
+    (0, 0, 0),
+];
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuiltInPalette {
+    #[default]
+    Default,
+    NtscComposite,
+    RgbPpu,
+}
+
+impl BuiltInPalette {
+    pub const ALL: [BuiltInPalette; 3] = [
+        BuiltInPalette::Default,
+        BuiltInPalette::NtscComposite,
+        BuiltInPalette::RgbPpu,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BuiltInPalette::Default => "Default",
+            BuiltInPalette::NtscComposite => "NTSC composite",
+            BuiltInPalette::RgbPpu => "RGB PPU (2C03)",
+        }
+    }
+
+    pub fn palette(&self) -> Palette {
+        match self {
+            BuiltInPalette::Default => Palette::from_colours(&DEFAULT_COLOURS),
+            BuiltInPalette::NtscComposite => Palette::from_fn(ntsc_colour),
+            BuiltInPalette::RgbPpu => Palette::from_fn(rgb_ppu_colour),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(String),
+    WrongSize(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Io(reason) => write!(f, "Couldn't read palette: {reason}"),
+            PaletteError::WrongSize(size) => write!(
+                f,
+                "Palette is {size} bytes, expected {} (64 colours) or {} (512 colours)",
+                COLOURS * 3,
+                COLOURS * EMPHASIS_COMBINATIONS * 3
+            ),
+        }
+    }
+}
+
+impl Error for PaletteError {}
+
+#[derive(Clone, Debug)]
+pub struct Palette {
+    colours: Vec<(u8, u8, u8)>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        BuiltInPalette::default().palette()
+    }
+}
+
+impl Palette {
+    // Emphasis is the PPUMASK emphasis bits shifted down, bit 0 red, bit 1 green, bit 2 blue
+    pub fn rgb(&self, colour: u8, emphasis: u8) -> (u8, u8, u8) {
+        self.colours[(emphasis as usize & 0b111) * COLOURS + (colour as usize & 0b11_1111)]
+    }
+
+    pub fn load(path: &Path) -> Result<Palette, PaletteError> {
+        let data = fs::read(path).map_err(|err| PaletteError::Io(err.to_string()))?;
+        Palette::from_pal_file(&data)
+    }
+
+    // A .pal file is just RGB triples, either the 64 colours or all 512 with emphasis
+    pub fn from_pal_file(data: &[u8]) -> Result<Palette, PaletteError> {
+        if data.len() % 3 != 0 {
+            return Err(PaletteError::WrongSize(data.len()));
+        }
+        let colours: Vec<(u8, u8, u8)> = data
+            .chunks_exact(3)
+            .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+            .collect();
+        match colours.len() {
+            COLOURS => Ok(Palette::from_colours(&colours)),
+            n if n == COLOURS * EMPHASIS_COMBINATIONS => Ok(Palette { colours }),
+            _ => Err(PaletteError::WrongSize(data.len())),
+        }
+    }
+
+    fn from_colours(colours: &[(u8, u8, u8)]) -> Palette {
+        Palette::from_fn(|colour, emphasis| {
+            let (r, g, b) = colours[colour as usize];
+            // Each channel is dimmed when any of the other channels are emphasised
+            let dim = |value: u8, channel: u8| {
+                if emphasis & !channel != 0 {
+                    (value as f32 * RGB_ATTENUATION).round() as u8
+                } else {
+                    value
+                }
+            };
+            (dim(r, 0b001), dim(g, 0b010), dim(b, 0b100))
+        })
+    }
+
+    fn from_fn(f: impl Fn(u8, u8) -> (u8, u8, u8)) -> Palette {
+        let colours = (0..EMPHASIS_COMBINATIONS as u8)
+            .flat_map(|emphasis| (0..COLOURS as u8).map(move |colour| (colour, emphasis)))
+            .map(|(colour, emphasis)| f(colour, emphasis))
+            .collect();
+        Palette { colours }
+    }
+}
+
+// Generates the composite signal the 2C02 outputs for a colour and decodes it the way an NTSC
+// TV would. The colour wave has 12 phases, a colour's hue decides which 6 of them are high.
+fn ntsc_colour(colour: u8, emphasis: u8) -> (u8, u8, u8) {
+    let hue = (colour & 0x0F) as i32;
+    // Columns $xE and $xF are always black
+    let level = if hue >= 0x0E {
+        1
+    } else {
+        (colour >> 4) as usize
+    };
+    let low = if hue == 0x00 {
+        SIGNAL_HIGH[level]
+    } else {
+        SIGNAL_LOW[level]
+    };
+    let high = if hue <= 0x0C {
+        SIGNAL_HIGH[level]
+    } else {
+        SIGNAL_LOW[level]
+    };
+    let in_colour_phase = |hue: i32, phase: i32| (hue + phase) % 12 < 6;
+
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for phase in 0..12 {
+        let mut signal = if in_colour_phase(hue, phase) {
+            high
+        } else {
+            low
+        };
+        // Emphasis dims the signal for the half of the wave that's furthest from that colour
+        if (emphasis & 0b001 != 0 && in_colour_phase(0x0C, phase))
+            || (emphasis & 0b010 != 0 && in_colour_phase(0x04, phase))
+            || (emphasis & 0b100 != 0 && in_colour_phase(0x08, phase))
+        {
+            signal *= SIGNAL_ATTENUATION;
+        }
+        let signal = (signal - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK);
+        let angle = PI * (phase as f32 + HUE_OFFSET) / 6.0;
+        y += signal / 12.0;
+        i += signal * angle.cos() / 12.0 * SATURATION;
+        q += signal * angle.sin() / 12.0 * SATURATION;
+    }
+
+    let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    (
+        to_u8(y + 0.946882 * i + 0.623557 * q),
+        to_u8(y - 0.274788 * i - 0.635691 * q),
+        to_u8(y - 1.108545 * i + 1.709007 * q),
+    )
+}
+
+// The RGB PPU has no colour wave to dim, emphasis turns channels fully on instead
+fn rgb_ppu_colour(colour: u8, emphasis: u8) -> (u8, u8, u8) {
+    let levels = RGB_PPU_COLOURS[colour as usize];
+    let channel = |shift: u16, bit: u8| {
+        if emphasis & bit != 0 {
+            255
+        } else {
+            (((levels >> shift) & 0o7) * 255 / 7) as u8
+        }
+    };
+    (channel(6, 0b001), channel(3, 0b010), channel(0, 0b100))
+}
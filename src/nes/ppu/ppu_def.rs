@@ -67,7 +67,19 @@ pub struct Ppu {
     pub nmi_line: bool,
     pub ppudata_buffer: u8,
     pub cycles: u64,
+    // Frames since power on, counted at the start of the pre-render scanline
+    #[serde(default)]
+    pub frames: u64,
     pub addr_bus: u16,
+    // v and x as each visible scanline started drawing, for the nametable viewer
+    #[serde(skip, default = "scanline_scroll_default")]
+    pub scanline_scroll: Vec<(u16, u8)>,
+    // Sprite evaluation on each visible scanline and where sprite 0 hit happened, for the OAM
+    // viewer
+    #[serde(skip, default = "scanline_sprites_default")]
+    pub scanline_sprites: Vec<ScanlineSprites>,
+    #[serde(skip)]
+    pub sprite_zero_hit_at: Option<(i32, i32)>,
 
     pub dynamic_latch: u8,
     pub dynamic_latch_last_set_cycle: u64
@@ -87,6 +99,23 @@ impl Default for Ppu {
     }
 }
 
+// The sprites found for one scanline, which are drawn on the scanline after
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ScanlineSprites {
+    // OAM indices (0-63) of the sprites copied to secondary OAM
+    pub sprites: [u8; 8],
+    pub count: u8,
+    pub overflow: bool,
+}
+
+fn scanline_scroll_default() -> Vec<(u16, u8)> {
+    vec![(0, 0); 240]
+}
+
+fn scanline_sprites_default() -> Vec<ScanlineSprites> {
+    vec![ScanlineSprites::default(); 240]
+}
+
 impl Ppu {
     pub fn new() -> Ppu {
         Ppu {
@@ -150,13 +179,29 @@ impl Ppu {
             nmi_line: false,
             ppudata_buffer: 0,
             cycles: 0,
+            frames: 0,
             addr_bus: 0,
+            scanline_scroll: scanline_scroll_default(),
+            scanline_sprites: scanline_sprites_default(),
+            sprite_zero_hit_at: None,
 
             dynamic_latch: 0,
             dynamic_latch_last_set_cycle: 0
         }
     }
 
+    // Reset clears PPUCTRL, PPUMASK, the scroll/address latch and the read buffer.
+    // Memories, PPUSTATUS and OAMADDR keep their values.
+    pub fn reset(&mut self) {
+        self.set_ppuctrl_from_byte(0);
+        self.set_ppumask_from_byte(0);
+        self.t = 0;
+        self.x = 0;
+        self.w = false;
+        self.ppudata_buffer = 0;
+        self.odd_frame = false;
+    }
+
     pub fn set_ppuctrl_from_byte(&mut self, byte: u8) {
         self.nmi_enable = get_bit(byte, 7);
         self.master_slave = get_bit(byte, 6);
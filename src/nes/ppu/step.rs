@@ -1,74 +1,9 @@
 use super::mem::read_vram;
+use super::ppu_def::ScanlineSprites;
+use crate::nes::breakpoints::{self, BreakEvent};
 use crate::nes::Nes;
 use crate::util::*;
 
-pub static PALETTE: [(u8, u8, u8); 64] = [
-    (84, 84, 84),
-    (0, 30, 116),
-    (8, 16, 144),
-    (48, 0, 136),
-    (68, 0, 100),
-    (92, 0, 48),
-    (84, 4, 0),
-    (60, 24, 0),
-    (32, 42, 0),
-    (8, 58, 0),
-    (0, 64, 0),
-    (0, 60, 0),
-    (0, 50, 60),
-    (0, 0, 0),
-    (0, 0, 0),
-    (0, 0, 0),
-    (152, 150, 152),
-    (8, 76, 196),
-    (48, 50, 236),
-    (92, 30, 228),
-    (136, 20, 176),
-    (160, 20, 100),
-    (152, 34, 32),
-    (120, 60, 0),
-    (84, 90, 0),
-    (40, 114, 0),
-    (8, 124, 0),
-    (0, 118, 40),
-    (0, 102, 120),
-    (0, 0, 0),
-    (0, 0, 0),
-    (0, 0, 0),
-    (236, 238, 236),
-    (76, 154, 236),
-    (120, 124, 236),
-    (176, 98, 236),
-    (228, 84, 236),
-    (236, 88, 180),
-    (236, 106, 100),
-    (212, 136, 32),
-    (160, 170, 0),
-    (116, 196, 0),
-    (76, 208, 32),
-    (56, 204, 108),
-    (56, 180, 204),
-    (60, 60, 60),
-    (0, 0, 0),
-    (0, 0, 0),
-    (236, 238, 236),
-    (168, 204, 236),
-    (188, 188, 236),
-    (212, 178, 236),
-    (236, 174, 236),
-    (236, 174, 212),
-    (236, 180, 176),
-    (228, 196, 144),
-    (204, 210, 120),
-    (180, 222, 120),
-    (168, 226, 144),
-    (152, 226, 180),
-    (160, 214, 228),
-    (160, 162, 160),
-    (0, 0, 0),
-    (0, 0, 0),
-];
-
 pub const NAMETABLE: u16 = 0b000_11_00000_00000;
 pub const NAMETABLE_MSB: u16 = 0b000_10_00000_00000;
 pub const NAMETABLE_LSB: u16 = 0b000_01_00000_00000;
@@ -98,6 +33,11 @@ pub fn step_ppu(nes: &mut Nes) {
     let scanline = nes.ppu.scanline;
     let rendering_enabled = nes.ppu.show_bg || nes.ppu.show_sprites;
 
+    // The first tile of the next scanline is fetched from here on
+    if cycle == 321 && (-1..=238).contains(&scanline) {
+        nes.ppu.scanline_scroll[(scanline + 1) as usize] = (nes.ppu.v, nes.ppu.x);
+    }
+
     // could move this down or whatever
     // the shift registers shift at the end(?) of the cycles 2..=257, 322..=337
     if (2..=257).contains(&cycle) || (322..=337).contains(&cycle) {
@@ -138,12 +78,30 @@ pub fn step_ppu(nes: &mut Nes) {
                         nes.ppu.s_oam[((nes.ppu.in_range_counter * 4) as usize) + i] =
                             nes.ppu.oam[n + i];
                     }
+                    nes.ppu.scanline_sprites[scanline as usize].sprites
+                        [nes.ppu.in_range_counter as usize] = (n / 4) as u8;
                     // move index of next free space in secondary oam
                     nes.ppu.in_range_counter += 1;
                 }
                 // move n to next sprite in oam
                 n += 4;
             }
+            nes.ppu.scanline_sprites[scanline as usize].count = nes.ppu.in_range_counter;
+
+            // After 8 sprites the PPU keeps looking for a ninth to set the overflow flag, but a
+            // hardware bug steps the byte within each sprite along too, so tile numbers,
+            // attributes and X positions get checked as if they were Y positions
+            let mut m = 0;
+            while n < 256 {
+                let sprite_y = nes.ppu.oam[n + m] as i32;
+                if (sprite_y <= nes.ppu.scanline) && (sprite_y + sprite_height > nes.ppu.scanline) {
+                    nes.ppu.sprite_overflow = true;
+                    nes.ppu.scanline_sprites[scanline as usize].overflow = true;
+                    break;
+                }
+                n += 4;
+                m = (m + 1) % 4;
+            }
         }
 
         // After this point, both the background pixel and sprite pixel are calculated
@@ -236,6 +194,9 @@ pub fn step_ppu(nes: &mut Nes) {
             && nes.ppu.sprite_zero_in_latches
             && !(cycle <= 8 && (!nes.ppu.show_leftmost_bg || !nes.ppu.show_leftmost_sprites))
         {
+            if !nes.ppu.sprite_zero_hit {
+                nes.ppu.sprite_zero_hit_at = Some((scanline, cycle));
+            }
             nes.ppu.sprite_zero_hit = true;
         }
 
@@ -273,27 +234,11 @@ pub fn step_ppu(nes: &mut Nes) {
         // I think palette memory can be accessed internally without a proper memory read
         let pixel_hue_value = read_vram(palette_index, nes) & 0b0011_1111;
 
-        let mut pixel_rgb = PALETTE[pixel_hue_value as usize];
-
-        let r_delta = pixel_rgb.0 / 4;
-        let g_delta = pixel_rgb.1 / 4;
-        let b_delta = pixel_rgb.2 / 4;
-
-        if nes.ppu.red_emphasis {
-            pixel_rgb.0 = pixel_rgb.0.saturating_add(r_delta);
-            pixel_rgb.1 = pixel_rgb.1.saturating_sub(g_delta);
-            pixel_rgb.2 = pixel_rgb.2.saturating_sub(b_delta);
-        }
-        if nes.ppu.green_emphasis {
-            pixel_rgb.0 = pixel_rgb.0.saturating_sub(r_delta);
-            pixel_rgb.1 = pixel_rgb.1.saturating_add(g_delta);
-            pixel_rgb.2 = pixel_rgb.2.saturating_sub(b_delta);
-        }
-        if nes.ppu.blue_emphasis {
-            pixel_rgb.0 = pixel_rgb.0.saturating_sub(r_delta);
-            pixel_rgb.1 = pixel_rgb.1.saturating_sub(g_delta);
-            pixel_rgb.2 = pixel_rgb.2.saturating_add(b_delta);
-        }
+        // Greyscale is applied by the palette RAM read above, emphasis is up to the palette
+        let emphasis = ((nes.ppu.blue_emphasis as u8) << 2)
+            | ((nes.ppu.green_emphasis as u8) << 1)
+            | nes.ppu.red_emphasis as u8;
+        let pixel_rgb = nes.palette.rgb(pixel_hue_value, emphasis);
 
         // TODO: Note frame thing here
         // Draw the pixel!
@@ -320,7 +265,10 @@ pub fn step_ppu(nes: &mut Nes) {
     else if scanline == -1 && cycle == 1 {
         nes.ppu.in_vblank = false;
         nes.ppu.sprite_zero_hit = false;
+        nes.ppu.sprite_overflow = false;
         nes.ppu.nmi_line = false;
+        nes.ppu.sprite_zero_hit_at = None;
+        nes.ppu.scanline_sprites.fill(ScanlineSprites::default());
         // nes.ppu.sprite_zero_in_latches = false;
         // nes.ppu.sprite_zero_in_soam = false;
     }
@@ -558,9 +506,17 @@ pub fn step_ppu(nes: &mut Nes) {
             // Pre-render scanline is -1 instead of 261 for convenience
             nes.ppu.scanline = -1;
             nes.ppu.odd_frame = !nes.ppu.odd_frame;
+            nes.ppu.frames += 1;
+            if let Some(log) = nes.events.as_mut() {
+                log.new_frame();
+            }
         }
     }
     nes.ppu.cycles += 1;
+
+    if nes.ppu.scanline_cycle == 0 && !nes.breakpoints.is_empty() {
+        breakpoints::check(nes, BreakEvent::Scanline(nes.ppu.scanline));
+    }
 }
 
 fn inc_v_horizontal(nes: &mut Nes) {
@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+
+/*
+    Rewind history, one serialized NES state per frame.
+
+    Keeping a full Nes clone for every frame grows forever, so states are serialized and grouped
+    into segments. Each segment starts with a keyframe and every frame after it is stored as the
+    XOR of its state with the keyframe, which is almost entirely zeros between nearby frames.
+    Keyframes and deltas are both run-length encoded (a keyframe is just a delta from nothing).
+
+    Diffing against the keyframe instead of the previous frame means any frame can be rebuilt
+    from two decodes, so scrubbing backwards doesn't have to replay a chain of deltas.
+    When the buffer goes over its limits the oldest segment is dropped.
+*/
+
+// Frames per keyframe
+const SEGMENT_LENGTH: usize = 60;
+
+struct Segment {
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+}
+
+impl Segment {
+    fn frames(&self) -> usize {
+        1 + self.deltas.len()
+    }
+
+    fn size(&self) -> usize {
+        self.keyframe.len() + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+pub struct RewindBuffer {
+    segments: VecDeque<Segment>,
+    // Decoded keyframe of the last segment, new deltas are made against it
+    last_keyframe: Vec<u8>,
+    frames: usize,
+    size: usize,
+    pub max_frames: usize,
+    pub max_size: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(max_frames: usize, max_size: usize) -> Self {
+        RewindBuffer {
+            segments: VecDeque::new(),
+            last_keyframe: Vec::new(),
+            frames: 0,
+            size: 0,
+            max_frames,
+            max_size,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames == 0
+    }
+
+    // Memory used by the encoded states in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.last_keyframe.clear();
+        self.frames = 0;
+        self.size = 0;
+    }
+
+    // Returns true if the oldest frames were dropped to stay within the limits
+    pub fn push(&mut self, state: Vec<u8>) -> bool {
+        match self.segments.back_mut() {
+            Some(segment) if segment.frames() < SEGMENT_LENGTH => {
+                let delta = encode_delta(&self.last_keyframe, &state);
+                self.size += delta.len();
+                segment.deltas.push(delta);
+            }
+            _ => {
+                let keyframe = encode_delta(&[], &state);
+                self.size += keyframe.len();
+                self.segments.push_back(Segment {
+                    keyframe,
+                    deltas: Vec::new(),
+                });
+                self.last_keyframe = state;
+            }
+        }
+        self.frames += 1;
+        self.enforce_limits()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Vec<u8>> {
+        let (segment, offset) = self.locate(index)?;
+        let keyframe = decode_delta(&[], &segment.keyframe);
+        match offset {
+            0 => Some(keyframe),
+            n => Some(decode_delta(&keyframe, &segment.deltas[n - 1])),
+        }
+    }
+
+    // Drops every frame after the first `len`
+    pub fn truncate(&mut self, len: usize) {
+        while self.frames > len {
+            let Some(segment) = self.segments.back_mut() else {
+                break;
+            };
+            let excess = self.frames - len;
+            if excess >= segment.frames() {
+                self.frames -= segment.frames();
+                self.size -= segment.size();
+                self.segments.pop_back();
+            } else {
+                let kept = segment.frames() - excess;
+                for delta in segment.deltas.drain(kept - 1..) {
+                    self.size -= delta.len();
+                }
+                self.frames = len;
+            }
+        }
+        self.last_keyframe = self
+            .segments
+            .back()
+            .map_or(Vec::new(), |segment| decode_delta(&[], &segment.keyframe));
+    }
+
+    fn enforce_limits(&mut self) -> bool {
+        let mut dropped = false;
+        // Always keep the segment being written to
+        while self.segments.len() > 1
+            && (self.frames > self.max_frames || self.size > self.max_size)
+        {
+            let segment = self.segments.pop_front().unwrap();
+            self.frames -= segment.frames();
+            self.size -= segment.size();
+            dropped = true;
+        }
+        dropped
+    }
+
+    fn locate(&self, mut index: usize) -> Option<(&Segment, usize)> {
+        for segment in self.segments.iter() {
+            if index < segment.frames() {
+                return Some((segment, index));
+            }
+            index -= segment.frames();
+        }
+        None
+    }
+}
+
+/*
+    Delta encoding:
+
+    varint    length of the state
+    then pairs of
+    varint    number of unchanged bytes to skip
+    varint    number of changed bytes N
+    N bytes   changed bytes XORed with the base
+
+    The base is treated as zero-padded if the state is longer than it.
+*/
+
+fn encode_delta(base: &[u8], state: &[u8]) -> Vec<u8> {
+    let xor = |i: usize| state[i] ^ base.get(i).copied().unwrap_or(0);
+    let mut out = Vec::new();
+    write_varint(&mut out, state.len());
+
+    let mut i = 0;
+    while i < state.len() {
+        let unchanged_start = i;
+        while i < state.len() && xor(i) == 0 {
+            i += 1;
+        }
+        let changed_start = i;
+        while i < state.len() && xor(i) != 0 {
+            i += 1;
+        }
+        write_varint(&mut out, changed_start - unchanged_start);
+        write_varint(&mut out, i - changed_start);
+        out.extend((changed_start..i).map(xor));
+    }
+    out
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut state = vec![0u8; len];
+    let common = len.min(base.len());
+    state[..common].copy_from_slice(&base[..common]);
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let changed = read_varint(delta, &mut pos);
+        for (byte, change) in state[i..i + changed]
+            .iter_mut()
+            .zip(&delta[pos..pos + changed])
+        {
+            *byte ^= change;
+        }
+        i += changed;
+        pos += changed;
+    }
+    state
+}
+
+// LEB128
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,114 @@
+use crate::nes::Nes;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+
+/*
+    Save state file layout:
+
+    8 bytes   magic number
+    4 bytes   format version (u32, little endian)
+    ...       MessagePack encoded header
+    ...       MessagePack encoded Nes
+
+    The magic number and format version are kept outside of the MessagePack data so that states
+    from an incompatible version can be refused without trying to decode them.
+*/
+
+const MAGIC: &[u8; 8] = b"NESSTATE";
+// Structs are encoded with their field names, so adding a field to the Nes (or anything inside it)
+// with #[serde(default)] or #[serde(skip)] keeps older states loadable and doesn't need a bump.
+// Removing, renaming or changing the type of a serialized field, or changing what one means, does.
+// Without a bump, older states would fail to decode and be reported as corrupt.
+// The emulator version in the header is only there to say which build made a state, it's not
+// checked, the format version alone decides whether a state can be loaded.
+pub const FORMAT_VERSION: u32 = 1;
+
+pub const THUMBNAIL_WIDTH: u32 = 128;
+pub const THUMBNAIL_HEIGHT: u32 = 120;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveStateHeader {
+    pub emulator_version: String,
+    // CRC32 of the PRG and CHR ROM, so states can't be loaded into the wrong game
+    pub rom_hash: u32,
+    // Seconds since the unix epoch
+    pub timestamp: u64,
+    // PNG encoded, THUMBNAIL_WIDTH x THUMBNAIL_HEIGHT
+    pub thumbnail: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    NoGameLoaded,
+    NotASaveState,
+    IncompatibleVersion(u32),
+    DifferentRom,
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::NoGameLoaded => write!(f, "No game is loaded"),
+            SaveStateError::NotASaveState => write!(f, "File is not a save state"),
+            SaveStateError::IncompatibleVersion(version) => write!(
+                f,
+                "Save state uses format version {version}, this version of the emulator only \
+                supports version {FORMAT_VERSION}"
+            ),
+            SaveStateError::DifferentRom => write!(f, "Save state was made with a different ROM"),
+            SaveStateError::Corrupt(reason) => write!(f, "Save state is corrupt: {reason}"),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+pub fn encode(header: &SaveStateHeader, nes: &Nes) -> Vec<u8> {
+    let mut data = Vec::with_capacity(0x10000);
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    // Serializing into a Vec can't fail unless a Serialize impl returns an error, none of ours do
+    rmp_serde::encode::write_named(&mut data, header).expect("Failed to encode save state");
+    rmp_serde::encode::write_named(&mut data, nes).expect("Failed to encode save state");
+    data
+}
+
+pub fn decode_header(data: &[u8]) -> Result<SaveStateHeader, SaveStateError> {
+    decode(data).map(|(header, _)| header)
+}
+
+// Returns the header and the rest of the data, which is the encoded Nes
+fn decode(data: &[u8]) -> Result<(SaveStateHeader, &[u8]), SaveStateError> {
+    let (magic, rest) = data
+        .split_first_chunk::<8>()
+        .ok_or(SaveStateError::NotASaveState)?;
+    if magic != MAGIC {
+        return Err(SaveStateError::NotASaveState);
+    }
+    let (version, mut rest) = rest
+        .split_first_chunk::<4>()
+        .ok_or(SaveStateError::NotASaveState)?;
+    let version = u32::from_le_bytes(*version);
+    if version != FORMAT_VERSION {
+        return Err(SaveStateError::IncompatibleVersion(version));
+    }
+    let header = rmp_serde::decode::from_read(&mut rest)
+        .map_err(|err| SaveStateError::Corrupt(err.to_string()))?;
+    Ok((header, rest))
+}
+
+// The decoded Nes has no ROM or frame buffer, the emulator has to reattach them
+pub fn decode_nes(data: &[u8], rom_hash: u32) -> Result<Nes, SaveStateError> {
+    let (header, rest) = decode(data)?;
+    if header.rom_hash != rom_hash {
+        return Err(SaveStateError::DifferentRom);
+    }
+    rmp_serde::from_slice(rest).map_err(|err| {
+        SaveStateError::Corrupt(format!(
+            "{err} (saved by version {})",
+            header.emulator_version
+        ))
+    })
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// How often battery-backed RAM gets written to disk while a game is running (in seconds)
+pub const BATTERY_FLUSH_INTERVAL: f64 = 10.0;
+
+pub const SAVE_STATE_SLOTS: usize = 10;
+
+// Saves go next to the ROM unless a saves directory has been configured
+fn save_path(rom_path: &Path, saves_directory: Option<&Path>, extension: &str) -> PathBuf {
+    let file_name = rom_path.with_extension(extension);
+    match saves_directory {
+        Some(dir) => dir.join(file_name.file_name().unwrap_or_default()),
+        None => file_name,
+    }
+}
+
+// Slots are stored as game.ss0 to game.ss9
+pub fn save_state_path(rom_path: &Path, saves_directory: Option<&Path>, slot: usize) -> PathBuf {
+    save_path(rom_path, saves_directory, &format!("ss{slot}"))
+}
+
+// User and imported debugger labels, in Mesen's label format
+pub fn labels_path(rom_path: &Path, saves_directory: Option<&Path>) -> PathBuf {
+    save_path(rom_path, saves_directory, "mlb")
+}
+
+pub fn write_save_state(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, data)
+}
+
+pub struct BatterySave {
+    path: PathBuf,
+    last_written: Vec<u8>,
+    last_flush_time: f64,
+}
+
+impl BatterySave {
+    pub fn new(rom_path: &Path, saves_directory: Option<&Path>) -> Self {
+        BatterySave {
+            path: save_path(rom_path, saves_directory, "sav"),
+            last_written: Vec::new(),
+            last_flush_time: 0.0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read(&mut self) -> Option<Vec<u8>> {
+        let data = fs::read(&self.path).ok()?;
+        self.last_written.clone_from(&data);
+        Some(data)
+    }
+
+    // Only touches the disk if the RAM has changed since it was last written
+    pub fn flush(&mut self, ram: &[u8]) -> io::Result<()> {
+        if ram == self.last_written.as_slice() {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, ram)?;
+        self.last_written = ram.to_vec();
+        Ok(())
+    }
+
+    pub fn flush_if_due(&mut self, ram: &[u8], time: f64) -> io::Result<()> {
+        if time - self.last_flush_time < BATTERY_FLUSH_INTERVAL {
+            return Ok(());
+        }
+        self.last_flush_time = time;
+        self.flush(ram)
+    }
+}
@@ -1,5 +1,5 @@
 use crate::emulator::AudioStream;
-use crate::nes::cartridge::cartridge_def::{CartMemory, RomConfig};
+use crate::nes::cartridge::cartridge_def::{CartMemory, ConsoleType, Region, RomConfig};
 use crate::nes::cartridge::Mirroring;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use std::error::Error;
@@ -7,62 +7,177 @@ use std::fs;
 use std::path::Path;
 use std::sync::mpsc;
 
+// Discrete logic boards that never came with PRG RAM: UxROM, CNROM and AxROM. NROM didn't either,
+// but homebrew and test ROMs (which report their results at 0x6000) expect RAM there, so iNES 1.0
+// NROM dumps get it unless byte 10 says otherwise.
+const MAPPERS_WITHOUT_PRG_RAM: [u16; 3] = [2, 3, 7];
+
 pub fn get_rom_from_file(path: &Path) -> Result<RomConfig, Box<dyn Error>> {
+    // TODO: Do proper path checks
+    let ines_data = fs::read(path)?;
+    get_rom_from_bytes(&ines_data)
+        .map_err(|err| format!("{} is {err}", path.to_str().unwrap()).into())
+}
+
+pub fn get_rom_from_bytes(ines_data: &[u8]) -> Result<RomConfig, Box<dyn Error>> {
     const INES_HEADER_SIZE: usize = 16;
+    const TRAINER_SIZE: usize = 512;
     const KB: usize = 1024;
 
-    // TODO: Do proper path checks
+    if ines_data.len() < INES_HEADER_SIZE || !ines_data.starts_with(b"NES\x1A") {
+        return Err("not a vaild iNES rom file (header doesn't fit)".into());
+    }
 
-    let ines_data = fs::read(path)?;
+    // NES 2.0 headers are identified by bits 2-3 of byte 7 being 0b10
+    let is_nes2 = (ines_data[7] & 0b0000_1100) == 0b0000_1000;
 
-    if ines_data.len() < INES_HEADER_SIZE || !ines_data.starts_with(b"NES\x1A") {
-        return Err(format!(
-            "{} is not a vaild iNES rom file (header doesn't fit)",
-            path.to_str().unwrap()
+    let has_trainer = (ines_data[6] & 0b100) > 0;
+    let prg_rom_start = INES_HEADER_SIZE + if has_trainer { TRAINER_SIZE } else { 0 };
+
+    let (prg_rom_size, chr_rom_size) = if is_nes2 {
+        (
+            nes2_rom_size(ines_data[4], ines_data[9] & 0x0F, 16 * KB),
+            nes2_rom_size(ines_data[5], ines_data[9] >> 4, 8 * KB),
         )
-        .into());
-    }
+    } else {
+        (
+            Some(16 * KB * ines_data[4] as usize),
+            Some(8 * KB * ines_data[5] as usize),
+        )
+    };
 
-    let prg_rom_end = INES_HEADER_SIZE + 16 * KB * ines_data[4] as usize;
-    let chr_rom_end = prg_rom_end + 8 * KB * ines_data[5] as usize;
+    // Sizes that don't fit in a usize are from a corrupt header, the file can't be that long
+    let prg_rom_end = prg_rom_size.and_then(|size| prg_rom_start.checked_add(size));
+    let chr_rom_end = prg_rom_end
+        .zip(chr_rom_size)
+        .and_then(|(prg_rom_end, size)| prg_rom_end.checked_add(size));
+    let (Some(prg_rom_end), Some(chr_rom_end)) = (prg_rom_end, chr_rom_end) else {
+        return Err("not a vaild iNES rom file (file not long enough)".into());
+    };
 
     if (ines_data.len()) < chr_rom_end {
-        return Err(format!(
-            "{} is not a vaild iNES rom file (file not long enough)",
-            path.to_str().unwrap()
-        )
-        .into());
+        return Err("not a vaild iNES rom file (file not long enough)".into());
     }
 
     let chr_rom_is_ram = prg_rom_end == chr_rom_end;
 
-    let has_prg_ram = (ines_data[6] & 0b10) > 0;
+    let has_battery = (ines_data[6] & 0b10) > 0;
+
+    let mut ines_mapper_id = ((ines_data[7] & 0xF0) | (ines_data[6] >> 4)) as u16;
 
-    let ines_mapper_id = (ines_data[7] & 0xF0) | (ines_data[6] >> 4);
+    let submapper_id;
+    let (prg_ram_size, prg_nvram_size, chr_ram_size, chr_nvram_size);
+    let console_type;
+    let region;
+    let default_expansion_device;
 
-    println!(
-        "Mapper: {}\nPRG RAM: {}\nCHR RAM: {}",
-        ines_mapper_id, has_prg_ram, chr_rom_is_ram
-    );
+    if is_nes2 {
+        ines_mapper_id |= ((ines_data[8] & 0x0F) as u16) << 8;
+        submapper_id = Some(ines_data[8] >> 4);
+
+        prg_ram_size = nes2_ram_size(ines_data[10] & 0x0F);
+        prg_nvram_size = nes2_ram_size(ines_data[10] >> 4);
+        chr_ram_size = nes2_ram_size(ines_data[11] & 0x0F);
+        chr_nvram_size = nes2_ram_size(ines_data[11] >> 4);
+
+        console_type = match ines_data[7] & 0b11 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::Playchoice10,
+            3 => ConsoleType::Extended(ines_data[13] & 0x0F),
+            _ => unreachable!(),
+        };
+        region = match ines_data[12] & 0b11 {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            2 => Region::MultiRegion,
+            3 => Region::Dendy,
+            _ => unreachable!(),
+        };
+        default_expansion_device = ines_data[15] & 0b0011_1111;
+    } else {
+        submapper_id = None;
+
+        // iNES 1.0 gives PRG RAM size in 8KB units in byte 8, but most dumps leave it as 0. In that
+        // case a battery means there's RAM, bit 4 of byte 10 means there isn't, and otherwise it
+        // depends on the board.
+        let prg_ram_total = match ines_data[8] as usize {
+            0 if has_battery => 8 * KB,
+            0 if ines_data[10] & 0b0001_0000 != 0 => 0,
+            0 if MAPPERS_WITHOUT_PRG_RAM.contains(&ines_mapper_id) => 0,
+            0 => 8 * KB,
+            banks => 8 * KB * banks,
+        };
+        (prg_ram_size, prg_nvram_size) = match has_battery {
+            true => (0, prg_ram_total),
+            false => (prg_ram_total, 0),
+        };
+        chr_ram_size = if chr_rom_is_ram { 8 * KB } else { 0 };
+        chr_nvram_size = 0;
+
+        console_type = match ines_data[7] & 0b11 {
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::Playchoice10,
+            _ => ConsoleType::Nes,
+        };
+        region = Region::Ntsc;
+        default_expansion_device = 0;
+    }
 
     Ok(RomConfig {
         ines_mapper_id,
+        submapper_id,
         ines_mirroring: match ines_data[6] & 1 {
             1 => Mirroring::Vertical,
             0 => Mirroring::Horizontal,
             _ => unreachable!(),
         },
+        has_battery,
+        prg_ram_size,
+        prg_nvram_size,
+        chr_ram_size,
+        chr_nvram_size,
+        console_type,
+        region,
+        default_expansion_device,
         data: CartMemory::new(
-            ines_data[INES_HEADER_SIZE..prg_rom_end].to_owned(),
+            ines_data[prg_rom_start..prg_rom_end].to_owned(),
             match chr_rom_is_ram {
                 false => Some(ines_data[prg_rom_end..chr_rom_end].to_owned()),
                 true => None,
             },
-            has_prg_ram,
+            prg_ram_size + prg_nvram_size,
+            // Boards without CHR ROM always have some CHR RAM, even if the header says otherwise
+            match chr_ram_size + chr_nvram_size {
+                0 => 8 * KB,
+                size => size,
+            },
+            has_battery,
         ),
     })
 }
 
+// None if the size doesn't fit in a usize
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> Option<usize> {
+    // When the MSB nibble is 0xF, the LSB is an exponent-multiplier pair EEEEEEMM
+    // giving a size of 2^E * (MM * 2 + 1) bytes
+    if msb == 0x0F {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        2usize.checked_pow(exponent)?.checked_mul(multiplier)
+    } else {
+        (((msb as usize) << 8) | lsb as usize).checked_mul(unit)
+    }
+}
+
+fn nes2_ram_size(shift_count: u8) -> usize {
+    // A shift count of 0 means no RAM, otherwise the size is 64 << shift_count bytes
+    match shift_count {
+        0 => 0,
+        shift => 64 << shift,
+    }
+}
+
 pub fn create_audio_stream() -> Result<AudioStream, Box<dyn Error>> {
     let (tx, rx) = mpsc::sync_channel::<(f32, f32)>(4096);
     let device = cpal::default_host()
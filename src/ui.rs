@@ -1,9 +1,39 @@
-use crate::app::App;
-use crate::setup;
-use crate::widgets::input_select::{InputSelect, InputType};
+use crate::app::{App, BreakpointType, InputMapping, PaletteChoice};
+use crate::emulator::{RunTarget, FRAME_WIDTH};
+use crate::labels::LabelAddress;
+use crate::nes::cartridge::LoadError;
+use crate::nes::cpu::debugger::DisassemblyView;
+use crate::nes::cpu::trace::CpuTracer;
+use crate::nes::ppu::debug_view;
+use crate::nes::ppu::palette::BuiltInPalette;
+use crate::saves::SAVE_STATE_SLOTS;
+use crate::widgets::input_select::{Input, InputSelect, InputType};
 use eframe::egui;
 use eframe::egui::load::SizedTexture;
-use eframe::egui::{include_image, Color32, Image, RichText, ViewportBuilder, ViewportId};
+use eframe::egui::{
+    include_image, vec2, Color32, Image, Rect, RichText, Sense, Stroke, ViewportBuilder, ViewportId,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod chr_viewer;
+mod event_viewer;
+mod memory_viewer;
+mod nametable_viewer;
+mod oam_viewer;
+mod palette_viewer;
+
+pub use chr_viewer::ChrViewer;
+pub use event_viewer::EventViewer;
+pub use memory_viewer::MemoryViewer;
+pub use nametable_viewer::NametableViewer;
+pub use oam_viewer::OamViewer;
+
+type InputField = fn(&mut InputMapping) -> &mut Input;
+
+// How long messages like "Saved state to slot 1" stay in the bottom panel (in seconds)
+const STATUS_MESSAGE_DURATION: f64 = 3.0;
+// Lines kept when tracing the CPU to memory
+const TRACE_MEMORY_LINES: usize = 10_000;
 
 impl App {
     pub fn define_main_top_panel(&mut self, ctx: &egui::Context) {
@@ -11,9 +41,7 @@ impl App {
             ui.horizontal_centered(|ui| {
                 if ui.button("Load ROM").clicked() {
                     if let Some(path) = rfd::FileDialog::new().pick_file() {
-                        if let Ok(rom) = setup::get_rom_from_file(path.as_path()) {
-                            self.emulator.load_game(rom);
-                        }
+                        self.load_rom(path.as_path());
                     }
                 }
 
@@ -21,12 +49,105 @@ impl App {
                     self.show_controller_config = !self.show_controller_config
                 }
 
+                let saves_hover_text = match self.saves_directory.as_ref() {
+                    Some(dir) => format!("{}\n(right click to reset)", dir.display()),
+                    None => "Saves are stored next to the ROM".to_owned(),
+                };
+                let saves_button = ui.button("Saves Folder").on_hover_text(saves_hover_text);
+                if saves_button.clicked() {
+                    if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                        self.saves_directory = Some(dir);
+                        self.save_slot_previews.clear();
+                    }
+                } else if saves_button.secondary_clicked() {
+                    self.saves_directory = None;
+                    self.save_slot_previews.clear();
+                }
+
                 ui.separator();
 
+                ui.menu_button("Rewind", |ui| {
+                    egui::Grid::new("rewind-settings-grid").show(ui, |ui| {
+                        ui.label("Length (minutes):");
+                        ui.add(
+                            egui::DragValue::from_get_set(|val| {
+                                self.emulator
+                                    .get_set_rewind_seconds(val.map(|minutes| minutes * 60.0))
+                                    / 60.0
+                            })
+                            .clamp_range(0.0..=60.0)
+                            .speed(0.1),
+                        );
+                        ui.end_row();
+                        ui.label("Memory limit (MB):");
+                        ui.add(
+                            egui::DragValue::from_get_set(|val| {
+                                self.emulator.get_set_rewind_megabytes(val)
+                            })
+                            .clamp_range(1.0..=4096.0)
+                            .speed(1.0),
+                        );
+                        ui.end_row();
+                        ui.label("Using:");
+                        ui.label(format!("{:.1} MB", self.emulator.rewind_megabytes_used()));
+                        ui.end_row();
+                    });
+                });
+
+                ui.menu_button("Palette", |ui| {
+                    let time = ui.input(|i| i.time);
+                    for built_in in BuiltInPalette::ALL {
+                        let choice = PaletteChoice::BuiltIn(built_in);
+                        if ui.radio(self.palette == choice, built_in.label()).clicked() {
+                            self.set_palette(choice, time);
+                        }
+                    }
+                    if let PaletteChoice::File(path) = &self.palette {
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        ui.radio(true, name)
+                            .on_hover_text(path.display().to_string());
+                    }
+                    ui.separator();
+                    if ui.button("Load .pal File...").clicked() {
+                        ui.close_menu();
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Palette", &["pal"])
+                            .pick_file()
+                        {
+                            self.set_palette(PaletteChoice::File(path), time);
+                        }
+                    }
+                });
+
                 ui.add_enabled_ui(self.emulator.game_loaded(), |ui| {
+                    ui.menu_button("Save States", |ui| self.define_save_states_menu(ui));
+                    if ui.button("Reset").clicked() {
+                        self.emulator.reset();
+                    }
+                    if ui.button("Power Cycle").clicked() {
+                        self.emulator.power_cycle();
+                    }
                     if ui.button("CPU Debugger").clicked() {
                         self.show_cpu_debugger = !self.show_cpu_debugger;
                     }
+                    if ui.button("Memory Viewer").clicked() {
+                        self.show_memory_viewer = !self.show_memory_viewer;
+                    }
+                    if ui.button("Nametable Viewer").clicked() {
+                        self.show_nametable_viewer = !self.show_nametable_viewer;
+                    }
+                    if ui.button("CHR Viewer").clicked() {
+                        self.show_chr_viewer = !self.show_chr_viewer;
+                    }
+                    if ui.button("Palette Viewer").clicked() {
+                        self.show_palette_viewer = !self.show_palette_viewer;
+                    }
+                    if ui.button("OAM Viewer").clicked() {
+                        self.show_oam_viewer = !self.show_oam_viewer;
+                    }
+                    if ui.button("Event Viewer").clicked() {
+                        self.show_event_viewer = !self.show_event_viewer;
+                    }
                 });
             });
         });
@@ -64,6 +185,20 @@ impl App {
                     egui::Slider::from_get_set(0.0..=1.0, |val| self.emulator.get_set_volume(val))
                         .text("Volume"),
                 );
+
+                ui.label(format!(
+                    "Rewind: {:.0}s",
+                    self.emulator.rewind_seconds_available()
+                ))
+                .on_hover_text("How far back the game can be rewound");
+
+                if let Some((message, shown_at)) = self.status_message.as_ref() {
+                    if ui.input(|input| input.time) - shown_at < STATUS_MESSAGE_DURATION {
+                        ui.label(message);
+                    } else {
+                        self.status_message = None;
+                    }
+                }
             });
         });
     }
@@ -71,9 +206,22 @@ impl App {
     pub fn define_main_central_panel(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             let emulator_screen = ui.add(
-                egui::Image::from_texture(SizedTexture::from_handle(&self.emulator.video_output))
+                egui::Image::from_texture(SizedTexture::from_handle(&self.screen_texture))
                     .shrink_to_fit(),
             );
+            if let (Some(index), Some(nes)) = (self.highlighted_sprite, self.emulator.nes.as_ref())
+            {
+                // Sprites are drawn a scanline below their Y position
+                let sprite = debug_view::sprite(nes, index);
+                let size = vec2(8.0, debug_view::sprite_height(nes) as f32);
+                let scale = emulator_screen.rect.width() / FRAME_WIDTH as f32;
+                let top_left = vec2(sprite.x as f32, sprite.y as f32 + 1.0);
+                ui.painter_at(emulator_screen.rect).rect_stroke(
+                    Rect::from_min_size(emulator_screen.rect.min + top_left * scale, size * scale),
+                    0.0,
+                    Stroke::new(2.0, Color32::RED),
+                );
+            }
             let screen_centre_rect = emulator_screen.rect.expand(-200.0);
             if self.scrubbing_rate < 0.0 && self.is_paused {
                 ui.put(
@@ -91,6 +239,107 @@ impl App {
         });
     }
 
+    fn define_save_states_menu(&mut self, ui: &mut egui::Ui) {
+        let time = ui.input(|input| input.time);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        egui::Grid::new("save-slots-grid").show(ui, |ui| {
+            for slot in 0..SAVE_STATE_SLOTS {
+                ui.radio_value(&mut self.save_state_slot, slot, format!("Slot {slot}"));
+
+                let preview = self.save_slot_preview(ui.ctx(), slot);
+                let slot_used = preview.is_some();
+                match preview {
+                    Some(preview) => {
+                        ui.label(format_age(now.saturating_sub(preview.timestamp)))
+                            .on_hover_ui(|ui| {
+                                ui.image(SizedTexture::from_handle(&preview.thumbnail));
+                            });
+                    }
+                    None => {
+                        ui.weak("Empty");
+                    }
+                }
+
+                if ui.button("Save").clicked() {
+                    self.save_state_to_slot(slot, time);
+                    ui.close_menu();
+                }
+                if ui
+                    .add_enabled(slot_used, egui::Button::new("Load"))
+                    .clicked()
+                {
+                    self.load_state_from_slot(slot, time);
+                    ui.close_menu();
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    pub fn define_save_state_error_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("Save state error")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if let Some(err) = self.save_state_error.as_ref() {
+                    ui.label(err);
+                }
+                if ui.button("OK").clicked() {
+                    self.save_state_error = None;
+                }
+            });
+        if !open {
+            self.save_state_error = None;
+        }
+    }
+
+    pub fn define_load_error_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = true;
+        egui::Window::new("Couldn't load ROM")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .open(&mut open)
+            .show(ctx, |ui| {
+                match self.load_error.as_ref() {
+                    Some(LoadError::UnsupportedMapper {
+                        id,
+                        name,
+                        submapper,
+                    }) => {
+                        ui.label("This game uses a mapper that isn't supported yet.");
+                        egui::Grid::new("load-error-grid").show(ui, |ui| {
+                            ui.label("Mapper:");
+                            ui.label(id.to_string());
+                            ui.end_row();
+                            ui.label("Name:");
+                            ui.label(*name);
+                            ui.end_row();
+                            ui.label("Submapper:");
+                            ui.label(submapper.map_or("-".to_owned(), |s| s.to_string()));
+                            ui.end_row();
+                        });
+                    }
+                    Some(err) => {
+                        ui.label(err.to_string());
+                    }
+                    None => {}
+                }
+                if ui.button("OK").clicked() {
+                    self.load_error = None;
+                }
+            });
+        if !open {
+            self.load_error = None;
+        }
+    }
+
     pub fn define_cpu_debugger(&mut self, ctx: &egui::Context) {
         ctx.show_viewport_immediate(
             ViewportId::from_hash_of("cpu_debugger"),
@@ -101,12 +350,22 @@ impl App {
                     if advance_button.clicked() {
                         self.emulator.run_one_cpu_instruction();
                     }
+                    self.define_run_controls(ui);
 
                     ui.separator();
+                    self.define_cpu_trace_controls(ui);
+                    self.define_code_data_log_controls(ui);
+                    ui.separator();
+                    self.define_breakpoints_panel(ui);
+                    ui.separator();
+                    self.define_labels_panel(ui);
+                    ui.separator();
+                    self.define_disassembly_view_select(ui);
 
                     ui.add_enabled_ui(self.is_paused, |ui| {
                         let mut scroll_builder = egui::ScrollArea::vertical().auto_shrink(false);
-                        if advance_button.clicked() {
+                        if advance_button.clicked() || self.scroll_debugger_to_pc {
+                            self.scroll_debugger_to_pc = false;
                             let row = self
                                 .emulator
                                 .instruction_cache
@@ -126,14 +385,26 @@ impl App {
                             self.emulator.instruction_cache.len(),
                             |ui, row_range| {
                                 for row in row_range {
-                                    if let Some(nes) = self.emulator.nes.as_ref() {
+                                    if let Some(nes) = self.emulator.nes.as_mut() {
                                         let debug_instr = self.emulator.instruction_cache[row];
-                                        let text =
-                                            RichText::new(debug_instr.debug_string()).monospace();
-                                        if debug_instr.opc_addr == nes.cpu.pc {
-                                            ui.label(text.color(Color32::RED));
+                                        let addr = debug_instr.opc_addr;
+                                        let mapped = debug_instr.is_mapped(nes);
+                                        let mut text = if addr == nes.cpu.pc && mapped {
+                                            let line = debug_instr
+                                                .debug_string_with_values(nes, &self.labels);
+                                            RichText::new(line).monospace().color(Color32::RED)
                                         } else {
-                                            ui.label(text);
+                                            let line = debug_instr.debug_string(nes, &self.labels);
+                                            RichText::new(line).monospace()
+                                        };
+                                        if self.debugger_cursor == Some(addr) && mapped {
+                                            text = text.background_color(Color32::DARK_GRAY);
+                                        }
+                                        let label = egui::Label::new(text).sense(Sense::click());
+                                        // Lines in a bank that isn't mapped in can't be run to
+                                        if ui.add(label).clicked() && mapped {
+                                            self.debugger_cursor = Some(addr);
+                                            self.label_editor.address = format!("${addr:04X}");
                                         }
                                     }
                                 }
@@ -149,332 +420,492 @@ impl App {
         )
     }
 
-    pub fn define_controller_config(&mut self, ctx: &egui::Context) {
-        ctx.show_viewport_immediate(
-            ViewportId::from_hash_of("controller"),
-            ViewportBuilder::default().with_inner_size([500.0, 500.0]),
-            |ctx, _class| {
-                egui::CentralPanel::default().show(ctx, |ui| {
-                    egui::Grid::new("cool-grid").show(ui, |ui| {
-                        if ctx.input(|ui| ui.focused) {
-                            self.get_pressed_input(ctx);
+    fn define_disassembly_view_select(&mut self, ui: &mut egui::Ui) {
+        let Some(nes) = self.emulator.nes.as_ref() else {
+            return;
+        };
+        let current = self.emulator.disassembly_view();
+        let mut view = current;
+        egui::ComboBox::from_id_source("disassembly_view")
+            .selected_text(view.label())
+            .show_ui(ui, |ui| {
+                let mapped = DisassemblyView::Mapped;
+                ui.selectable_value(&mut view, mapped, mapped.label());
+                for bank in 0..DisassemblyView::bank_count(nes) {
+                    let bank = DisassemblyView::Bank(bank);
+                    ui.selectable_value(&mut view, bank, bank.label());
+                }
+            });
+        if view != current {
+            self.emulator.set_disassembly_view(view);
+            self.scroll_debugger_to_pc = true;
+        }
+    }
+
+    fn define_run_controls(&mut self, ui: &mut egui::Ui) {
+        let mut target = None;
+        ui.add_enabled_ui(self.is_paused && self.emulator.game_loaded(), |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Step Over").clicked() {
+                    target = Some(RunTarget::StepOver);
+                }
+                if ui.button("Step Out").clicked() {
+                    target = Some(RunTarget::StepOut);
+                }
+                let run_to_cursor = ui.add_enabled(
+                    self.debugger_cursor.is_some(),
+                    egui::Button::new("Run to Cursor"),
+                );
+                if run_to_cursor.clicked() {
+                    target = self.debugger_cursor.map(RunTarget::Address);
+                }
+            });
+            ui.horizontal(|ui| {
+                let step_back = ui.button("Step Back");
+                let reverse_continue = ui.button("Reverse Continue");
+                let stepped_back = if step_back.clicked() {
+                    Some(self.emulator.step_back())
+                } else if reverse_continue.clicked() {
+                    Some(self.emulator.reverse_continue())
+                } else {
+                    None
+                };
+                match stepped_back {
+                    Some(true) => self.scroll_debugger_to_pc = true,
+                    Some(false) => {
+                        let message = "Nothing to go back to in the rewind history".to_owned();
+                        self.status_message = Some((message, ui.input(|i| i.time)));
+                    }
+                    None => {}
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Run to next");
+                if ui.button("Frame").clicked() {
+                    target = Some(RunTarget::NextFrame);
+                }
+                if ui.button("Scanline").clicked() {
+                    target = Some(RunTarget::NextScanline);
+                }
+                if ui.button("NMI").clicked() {
+                    target = Some(RunTarget::NextNmi);
+                }
+            });
+        });
+        if let Some(target) = target {
+            self.emulator.run_until(target);
+            self.is_paused = false;
+        }
+    }
+
+    fn define_breakpoints_panel(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Breakpoints")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.add_enabled_ui(self.emulator.game_loaded(), |ui| {
+                    self.define_breakpoint_editor(ui);
+                });
+
+                let Some(nes) = self.emulator.nes.as_mut() else {
+                    return;
+                };
+                let label_names: Vec<Option<String>> = nes
+                    .breakpoints
+                    .list
+                    .iter()
+                    .map(|breakpoint| {
+                        let label = self.labels.get(nes, breakpoint.kind.cpu_address()?)?;
+                        Some(label.name.clone()).filter(|name| !name.is_empty())
+                    })
+                    .collect();
+                let breakpoints = &mut nes.breakpoints;
+                let mut removed = None;
+                for (index, breakpoint) in breakpoints.list.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut breakpoint.enabled, "");
+                        let mut description = breakpoint.kind.to_string();
+                        if let Some(name) = &label_names[index] {
+                            description += &format!(" ({name})");
+                        }
+                        if let Some(condition) = &breakpoint.condition {
+                            description += &format!(" if {condition}");
+                        }
+                        if breakpoint.break_on_hit > 1 {
+                            description += &format!(" from hit {}", breakpoint.break_on_hit);
                         }
+                        if let Some(message) = &breakpoint.log_message {
+                            description += &format!(" log \"{message}\"");
+                        }
+                        description += &format!(" ({} hits)", breakpoint.hits);
+                        let text = RichText::new(description).monospace();
+                        if self.last_breakpoint_hit == Some(index) {
+                            ui.label(text.color(Color32::RED));
+                        } else {
+                            ui.label(text);
+                        }
+                        if ui.small_button("Delete").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = removed {
+                    breakpoints.remove(index);
+                    self.last_breakpoint_hit = None;
+                }
 
-                        let maybe_input = self.held_input.iter().next().copied();
+                if breakpoints.log.is_empty() {
+                    return;
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Log");
+                    if ui.small_button("Clear").clicked() {
+                        breakpoints.log.clear();
+                    }
+                });
+                let log = &breakpoints.log;
+                egui::ScrollArea::both()
+                    .id_source("breakpoint_log")
+                    .max_height(150.0)
+                    .stick_to_bottom(true)
+                    .show_rows(ui, 10.0, log.len(), |ui, row_range| {
+                        for row in row_range {
+                            ui.label(RichText::new(&log[row]).monospace());
+                        }
+                    });
+            });
+    }
 
-                        ui.label("");
-                        ui.image(include_image!("../resources/keyboard-line.svg"));
-                        ui.image(include_image!("../resources/gamepad-line.svg"));
-                        ui.end_row();
+    fn define_labels_panel(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(format!("Labels ({})", self.labels.len())).show(ui, |ui| {
+            let Some(nes) = self.emulator.nes.as_ref() else {
+                return;
+            };
+            let time = ui.input(|i| i.time);
+            let mut changed = false;
 
-                        ui.label("UP:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.up),
-                            "con1-up-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .up
-                                }),
-                                "con1-up-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
-                        ui.end_row();
+            let editor = &mut self.label_editor;
+            ui.horizontal(|ui| {
+                if ui.button("Import...").clicked() {
+                    let file = rfd::FileDialog::new()
+                        .add_filter(
+                            "ca65 debug info, FCEUX or Mesen labels",
+                            &["dbg", "nl", "mlb"],
+                        )
+                        .pick_file();
+                    if let Some(path) = file {
+                        let message = match self.labels.import(&path) {
+                            Ok(count) => {
+                                changed = true;
+                                format!("Imported {count} labels from {}", path.display())
+                            }
+                            Err(err) => format!("Couldn't import {}: {err}", path.display()),
+                        };
+                        self.status_message = Some((message, time));
+                    }
+                }
+                ui.add(
+                    egui::TextEdit::singleline(&mut editor.address)
+                        .hint_text("$8000")
+                        .desired_width(50.0),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut editor.name)
+                        .hint_text("name")
+                        .desired_width(100.0),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut editor.comment)
+                        .hint_text("comment")
+                        .desired_width(120.0),
+                );
+                if ui.button("Add").clicked() {
+                    match editor.label(nes) {
+                        Ok((addr, label)) => {
+                            editor.error = None;
+                            self.labels.insert(addr, label);
+                            changed = true;
+                        }
+                        Err(err) => editor.error = Some(err),
+                    }
+                }
+            });
+            if let Some(err) = editor.error.as_ref() {
+                ui.colored_label(Color32::RED, err);
+            }
 
-                        ui.label("DOWN:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.down),
-                            "con1-down-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .down
-                                }),
-                                "con1-down-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
-                        ui.end_row();
+            let mut removed = None;
+            egui::ScrollArea::vertical()
+                .id_source("labels")
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for (&addr, label) in self.labels.iter() {
+                        ui.horizontal(|ui| {
+                            let addr_text = match addr {
+                                LabelAddress::Cpu(addr) => format!("${addr:04X}"),
+                                LabelAddress::PrgRom(offset) => format!("PRG ${offset:05X}"),
+                            };
+                            let mut text = format!("{addr_text:<10} {}", label.name);
+                            if !label.comment.is_empty() {
+                                text += &format!(" ; {}", label.comment);
+                            }
+                            ui.label(RichText::new(text).monospace());
+                            if ui.small_button("Delete").clicked() {
+                                removed = Some(addr);
+                            }
+                        });
+                    }
+                });
+            if let Some(addr) = removed {
+                self.labels.remove(addr);
+                changed = true;
+            }
+            if changed {
+                self.save_labels(time);
+            }
+        });
+    }
 
-                        ui.label("LEFT:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.left),
-                            "con1-left-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .left
-                                }),
-                                "con1-left-gamepad",
-                                InputType::Controller,
-                            ),
+    fn define_breakpoint_editor(&mut self, ui: &mut egui::Ui) {
+        let editor = &mut self.breakpoint_editor;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("breakpoint_type")
+                .selected_text(editor.breakpoint_type.label())
+                .show_ui(ui, |ui| {
+                    for breakpoint_type in BreakpointType::ALL {
+                        ui.selectable_value(
+                            &mut editor.breakpoint_type,
+                            breakpoint_type,
+                            breakpoint_type.label(),
                         );
-                        ui.end_row();
+                    }
+                });
+            if editor.breakpoint_type.has_address() {
+                let hint = match editor.breakpoint_type {
+                    BreakpointType::Scanline => "241",
+                    BreakpointType::PpuRegisterRead | BreakpointType::PpuRegisterWrite => "$2002",
+                    _ => "$8000",
+                };
+                ui.add(
+                    egui::TextEdit::singleline(&mut editor.start)
+                        .hint_text(hint)
+                        .desired_width(50.0),
+                );
+            }
+            if editor.breakpoint_type.has_range() {
+                ui.label("to");
+                ui.add(
+                    egui::TextEdit::singleline(&mut editor.end)
+                        .hint_text("(optional)")
+                        .desired_width(70.0),
+                );
+            }
+            if ui.button("Add").clicked() {
+                if let Some(nes) = self.emulator.nes.as_mut() {
+                    match editor.breakpoint(&self.labels, nes) {
+                        Ok(breakpoint) => {
+                            editor.error = None;
+                            nes.breakpoints.add(breakpoint);
+                        }
+                        Err(err) => editor.error = Some(err),
+                    }
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("If");
+            ui.add(
+                egui::TextEdit::singleline(&mut editor.condition)
+                    .hint_text("A == $20 && [$0300] > 5")
+                    .desired_width(180.0),
+            );
+            ui.label("from hit");
+            ui.add(
+                egui::TextEdit::singleline(&mut editor.break_on_hit)
+                    .hint_text("1")
+                    .desired_width(40.0),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Log");
+            ui.add(
+                egui::TextEdit::singleline(&mut editor.log_message)
+                    .hint_text("A is {A} (pauses instead if empty)")
+                    .desired_width(260.0),
+            );
+        });
+        if let Some(err) = editor.error.as_ref() {
+            ui.colored_label(Color32::RED, err);
+        }
+    }
 
-                        ui.label("RIGHT:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.right),
-                            "con1-right-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .right
-                                }),
-                                "con1-right-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
-                        ui.end_row();
+    fn define_code_data_log_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(self.emulator.game_loaded(), |ui| {
+                let Some(cdl) = self.emulator.code_data_log() else {
+                    if ui.button("Start Code/Data Logger").clicked() {
+                        self.emulator.start_code_data_log();
+                    }
+                    return;
+                };
+                let coverage = cdl.coverage();
+                if ui.button("Save CDL...").clicked() {
+                    let file_name = self
+                        .rom_path
+                        .as_ref()
+                        .and_then(|path| path.file_stem())
+                        .map_or("game".into(), |stem| stem.to_string_lossy());
+                    let file = rfd::FileDialog::new()
+                        .add_filter("Code/Data Log", &["cdl"])
+                        .set_file_name(format!("{file_name}.cdl"))
+                        .save_file();
+                    if let Some(path) = file {
+                        let message = match cdl.save(&path) {
+                            Ok(()) => format!("Saved {}", path.display()),
+                            Err(err) => format!("Couldn't write {}: {err}", path.display()),
+                        };
+                        self.status_message = Some((message, ui.input(|i| i.time)));
+                    }
+                }
+                if ui.button("Clear").clicked() {
+                    if let Some(cdl) = self.emulator.code_data_log_mut() {
+                        cdl.clear();
+                    }
+                }
+                if ui.button("Stop").clicked() {
+                    self.emulator.stop_code_data_log();
+                }
+                let mut text = format!(
+                    "Code {:.1}%  Data {:.1}%",
+                    coverage.code * 100.0,
+                    coverage.data * 100.0
+                );
+                if let Some(chr) = coverage.chr {
+                    text += &format!("  CHR {:.1}%", chr * 100.0);
+                }
+                ui.label(text);
+            });
+        });
+    }
 
-                        ui.label("B:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.b),
-                            "con1-b-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .b
-                                }),
-                                "con1-b-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
-                        ui.end_row();
+    fn define_cpu_trace_controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(self.emulator.game_loaded(), |ui| {
+                if self.emulator.tracer().is_some() {
+                    if ui.button("Stop Trace").clicked() {
+                        self.emulator.stop_trace();
+                    }
+                    return;
+                }
+                if ui.button("Trace to File...").clicked() {
+                    let file = rfd::FileDialog::new()
+                        .set_file_name("trace.log")
+                        .save_file();
+                    if let Some(path) = file {
+                        match CpuTracer::to_file(&path) {
+                            Ok(tracer) => self.emulator.start_trace(tracer),
+                            Err(err) => {
+                                let message = format!("Couldn't create {}: {err}", path.display());
+                                self.status_message = Some((message, ui.input(|i| i.time)));
+                            }
+                        }
+                    }
+                }
+                if ui.button("Trace to Memory").clicked() {
+                    self.emulator
+                        .start_trace(CpuTracer::in_memory(TRACE_MEMORY_LINES));
+                }
+            });
+        });
 
-                        ui.label("A:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.a),
-                            "con1-a-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .a
-                                }),
-                                "con1-a-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
-                        ui.end_row();
+        let Some(tracer) = self.emulator.tracer() else {
+            return;
+        };
+        if let Some(err) = tracer.error() {
+            ui.colored_label(Color32::RED, format!("Trace stopped: {err}"));
+        } else if tracer.is_file() {
+            ui.label("Tracing to file");
+        } else {
+            let lines = tracer.lines();
+            egui::ScrollArea::both()
+                .id_source("cpu_trace")
+                .max_height(200.0)
+                .stick_to_bottom(true)
+                .show_rows(ui, 10.0, lines.len(), |ui, row_range| {
+                    for row in row_range {
+                        ui.label(RichText::new(&lines[row]).monospace());
+                    }
+                });
+        }
+    }
 
-                        ui.label("SELECT:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.select),
-                            "con1-select-key",
-                            InputType::Keyboard,
-                        ));
-
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .select
-                                }),
-                                "con1-select-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
+    pub fn define_controller_config(&mut self, ctx: &egui::Context) {
+        ctx.show_viewport_immediate(
+            ViewportId::from_hash_of("controller"),
+            ViewportBuilder::default().with_inner_size([600.0, 700.0]),
+            |ctx, _class| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    egui::Grid::new("cool-grid").show(ui, |ui| {
+                        if ctx.input(|ui| ui.focused) {
+                            self.get_pressed_input(ctx);
+                        }
 
-                        ui.end_row();
+                        let maybe_input = self.held_input.iter().next().copied();
 
-                        ui.label("START:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.start),
-                            "con1-start-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .start
-                                }),
-                                "con1-start-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
+                        ui.label("");
+                        ui.label("Player 1");
+                        ui.label("");
+                        ui.label("Player 2");
                         ui.end_row();
 
-                        ui.separator();
-                        ui.separator();
-                        ui.separator();
+                        ui.label("");
+                        for _ in 0..2 {
+                            ui.image(include_image!("../resources/keyboard-line.svg"));
+                            ui.image(include_image!("../resources/gamepad-line.svg"));
+                        }
                         ui.end_row();
 
-                        ui.label("Pause:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.pause),
-                            "pause-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .pause
-                                }),
-                                "pause-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
-                        ui.end_row();
+                        // Buttons on the controllers themselves
+                        let buttons: [(&str, &str, InputField); 8] = [
+                            ("UP:", "up", |m| &mut m.up),
+                            ("DOWN:", "down", |m| &mut m.down),
+                            ("LEFT:", "left", |m| &mut m.left),
+                            ("RIGHT:", "right", |m| &mut m.right),
+                            ("B:", "b", |m| &mut m.b),
+                            ("A:", "a", |m| &mut m.a),
+                            ("SELECT:", "select", |m| &mut m.select),
+                            ("START:", "start", |m| &mut m.start),
+                        ];
+                        for (label, id, field) in buttons {
+                            self.define_input_mapping_row(ui, maybe_input, label, id, 2, field);
+                        }
 
-                        ui.label("Rewind:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.rewind),
-                            "rewind-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .rewind
-                                }),
-                                "rewind-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
+                        for _ in 0..5 {
+                            ui.separator();
+                        }
                         ui.end_row();
 
-                        ui.label("Fast forward:");
-                        ui.add(InputSelect::new(
-                            maybe_input,
-                            Some(&mut self.keyboard_input_mapping.0.fast_forward),
-                            "fast-forward-key",
-                            InputType::Keyboard,
-                        ));
-                        ui.add_enabled(
-                            self.selected_controllers.0.is_some(),
-                            InputSelect::new(
-                                maybe_input,
-                                self.selected_controllers.0.map(|id| {
-                                    &mut self
-                                        .controllers_input_mapping
-                                        .get_mut(&id)
-                                        .unwrap()
-                                        .input_mapping
-                                        .fast_forward
-                                }),
-                                "fast-forward-gamepad",
-                                InputType::Controller,
-                            ),
-                        );
-                        ui.end_row();
+                        // Emulator controls, these are only read from player 1's mappings
+                        let controls: [(&str, &str, InputField); 8] = [
+                            ("Pause:", "pause", |m| &mut m.pause),
+                            ("Rewind:", "rewind", |m| &mut m.rewind),
+                            ("Fast forward:", "fast-forward", |m| &mut m.fast_forward),
+                            ("Save state:", "save-state", |m| &mut m.save_state),
+                            ("Load state:", "load-state", |m| &mut m.load_state),
+                            ("Next save slot:", "next-save-slot", |m| {
+                                &mut m.next_save_slot
+                            }),
+                            ("Reset:", "reset", |m| &mut m.reset),
+                            ("Power cycle:", "power-cycle", |m| &mut m.power_cycle),
+                        ];
+                        for (label, id, field) in controls {
+                            self.define_input_mapping_row(ui, maybe_input, label, id, 1, field);
+                        }
 
                         ui.label("");
-                        ui.label("");
-
-                        egui::ComboBox::from_id_source("controller_select")
-                            .selected_text(self.selected_controllers.0.map_or("None", |con| {
-                                self.controllers_input_mapping
-                                    .get(&con)
-                                    .unwrap()
-                                    .name
-                                    .as_str()
-                            }))
-                            .show_ui(ui, |ui| {
-                                ui.selectable_value(&mut self.selected_controllers.0, None, "None");
-                                for (uuid, controller_config) in
-                                    self.controllers_input_mapping.iter()
-                                {
-                                    ui.horizontal(|ui| {
-                                        ui.selectable_value(
-                                            &mut self.selected_controllers.0,
-                                            Some(*uuid),
-                                            &controller_config.name,
-                                        );
-                                    });
-                                }
-                            });
-
+                        for player in 0..2 {
+                            ui.label("");
+                            self.define_controller_select(ui, player);
+                        }
                         ui.end_row();
                     });
                 });
@@ -485,4 +916,113 @@ impl App {
             },
         )
     }
+
+    // A keyboard and gamepad input selector for each of the first `players` players
+    fn define_input_mapping_row(
+        &mut self,
+        ui: &mut egui::Ui,
+        maybe_input: Option<Input>,
+        label: &str,
+        id: &str,
+        players: usize,
+        field: InputField,
+    ) {
+        ui.label(label);
+        for player in 0..players {
+            let (keyboard_mapping, selected_controller) = match player {
+                0 => (
+                    &mut self.keyboard_input_mapping.0,
+                    self.selected_controllers.0,
+                ),
+                _ => (
+                    &mut self.keyboard_input_mapping.1,
+                    self.selected_controllers.1,
+                ),
+            };
+            ui.add(InputSelect::new(
+                maybe_input,
+                Some(field(keyboard_mapping)),
+                format!("con{}-{id}-key", player + 1),
+                InputType::Keyboard,
+            ));
+            ui.add_enabled(
+                selected_controller.is_some(),
+                InputSelect::new(
+                    maybe_input,
+                    selected_controller.map(|uuid| {
+                        field(
+                            &mut self
+                                .controllers_input_mapping
+                                .get_mut(&uuid)
+                                .unwrap()
+                                .input_mapping,
+                        )
+                    }),
+                    format!("con{}-{id}-gamepad", player + 1),
+                    InputType::Controller,
+                ),
+            );
+        }
+        ui.end_row();
+    }
+
+    fn define_controller_select(&mut self, ui: &mut egui::Ui, player: usize) {
+        let selected = match player {
+            0 => &mut self.selected_controllers.0,
+            _ => &mut self.selected_controllers.1,
+        };
+        egui::ComboBox::from_id_source(format!("controller_select_{player}"))
+            .selected_text(selected.map_or("None", |con| {
+                self.controllers_input_mapping
+                    .get(&con)
+                    .unwrap()
+                    .name
+                    .as_str()
+            }))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(selected, None, "None");
+                for (uuid, controller_config) in self.controllers_input_mapping.iter() {
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(selected, Some(*uuid), &controller_config.name);
+                    });
+                }
+            });
+    }
+
+    // Asks where to save an RGBA image, suggesting "<rom name>-<suffix>.png"
+    fn export_png(&mut self, ui: &egui::Ui, suffix: &str, pixels: &[u8], size: [usize; 2]) {
+        let file_name = self
+            .rom_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map_or("game".into(), |stem| stem.to_string_lossy());
+        let file = rfd::FileDialog::new()
+            .add_filter("PNG image", &["png"])
+            .set_file_name(format!("{file_name}-{suffix}.png"))
+            .save_file();
+        if let Some(path) = file {
+            let [width, height] = size;
+            let result = image::save_buffer(
+                &path,
+                pixels,
+                width as u32,
+                height as u32,
+                image::ColorType::Rgba8,
+            );
+            let message = match result {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(err) => format!("Couldn't write {}: {err}", path.display()),
+            };
+            self.status_message = Some((message, ui.input(|i| i.time)));
+        }
+    }
+}
+
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..60 => "Just now".to_owned(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
 }
@@ -0,0 +1,148 @@
+use crate::app::App;
+use crate::nes::ppu::debug_view::{self, PATTERN_TABLES_HEIGHT, PATTERN_TABLES_WIDTH};
+use eframe::egui;
+use eframe::egui::load::SizedTexture;
+use eframe::egui::{
+    vec2, Color32, ColorImage, Rect, Sense, Stroke, TextureHandle, TextureOptions, ViewportBuilder,
+    ViewportId,
+};
+
+pub struct ChrViewer {
+    texture: Option<TextureHandle>,
+    // None for the pattern tables as currently mapped, otherwise an 8 KB page of CHR
+    page: Option<usize>,
+    // None for greyscale, otherwise 0-3 for background palettes and 4-7 for sprite palettes
+    palette: Option<u8>,
+}
+
+impl Default for ChrViewer {
+    fn default() -> Self {
+        ChrViewer {
+            texture: None,
+            page: None,
+            palette: Some(0),
+        }
+    }
+}
+
+fn palette_name(palette: Option<u8>) -> String {
+    match palette {
+        Some(palette @ 0..=3) => format!("Background {palette}"),
+        Some(palette) => format!("Sprite {}", palette - 4),
+        None => "Greyscale".to_owned(),
+    }
+}
+
+impl App {
+    pub fn define_chr_viewer(&mut self, ctx: &egui::Context) {
+        ctx.show_viewport_immediate(
+            ViewportId::from_hash_of("chr_viewer"),
+            ViewportBuilder::default()
+                .with_title("CHR Viewer")
+                .with_inner_size([540.0, 380.0]),
+            |ctx, _class| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let Some(nes) = self.emulator.nes.as_ref() else {
+                        ui.label("No game loaded");
+                        return;
+                    };
+                    let viewer = &mut self.chr_viewer;
+                    let pages = debug_view::chr_pages(nes);
+                    viewer.page = viewer.page.filter(|&page| page < pages);
+
+                    ui.horizontal(|ui| {
+                        let page_name = |page: Option<usize>| match page {
+                            Some(page) => format!("CHR ${:05X}", page * 0x2000),
+                            None => "PPU $0000-$1FFF".to_owned(),
+                        };
+                        egui::ComboBox::from_id_source("chr_page")
+                            .selected_text(page_name(viewer.page))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut viewer.page, None, page_name(None));
+                                for page in (0..pages).map(Some) {
+                                    ui.selectable_value(&mut viewer.page, page, page_name(page));
+                                }
+                            });
+                        egui::ComboBox::from_id_source("chr_palette")
+                            .selected_text(palette_name(viewer.palette))
+                            .show_ui(ui, |ui| {
+                                for palette in (0..8).map(Some).chain([None]) {
+                                    let name = palette_name(palette);
+                                    ui.selectable_value(&mut viewer.palette, palette, name);
+                                }
+                            });
+                    });
+
+                    let pixels =
+                        debug_view::render_pattern_tables(nes, viewer.page, viewer.palette);
+                    let image = ColorImage::from_rgba_unmultiplied(
+                        [PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT],
+                        &pixels,
+                    );
+                    let texture = match viewer.texture.as_mut() {
+                        Some(texture) => {
+                            texture.set(image, TextureOptions::NEAREST);
+                            texture
+                        }
+                        None => viewer.texture.insert(ctx.load_texture(
+                            "pattern_tables",
+                            image,
+                            TextureOptions::NEAREST,
+                        )),
+                    };
+                    let response = ui.add(
+                        egui::Image::from_texture(SizedTexture::from_handle(texture))
+                            .fit_to_exact_size(vec2(512.0, 256.0))
+                            .sense(Sense::hover()),
+                    );
+
+                    let rect = response.rect;
+                    let scale = rect.width() / PATTERN_TABLES_WIDTH as f32;
+                    match response.hover_pos() {
+                        Some(pos) => {
+                            let offset = (pos - rect.min) / scale;
+                            let tile_x = (offset.x as usize / 8).min(31);
+                            let tile_y = (offset.y as usize / 8).min(15);
+                            let tile_rect = Rect::from_min_size(
+                                rect.min + vec2(tile_x as f32, tile_y as f32) * 8.0 * scale,
+                                vec2(8.0, 8.0) * scale,
+                            );
+                            ui.painter_at(rect).rect_stroke(
+                                tile_rect,
+                                0.0,
+                                Stroke::new(1.0, Color32::RED),
+                            );
+                            let table = tile_x / 16;
+                            let tile = tile_y * 16 + tile_x % 16;
+                            let addr = table * 0x1000 + tile * 16;
+                            let location = match viewer.page {
+                                Some(page) => format!("CHR ${:05X}", page * 0x2000 + addr),
+                                None => format!(
+                                    "PPU ${addr:04X}, CHR ${:05X}",
+                                    nes.cart.chr_offset(addr as u16)
+                                ),
+                            };
+                            ui.monospace(format!("Tile ${tile:02X} at {location}"));
+                        }
+                        None => {
+                            ui.monospace("Hover over a tile for details");
+                        }
+                    }
+
+                    if ui.button("Export PNG...").clicked() {
+                        self.export_png(
+                            ui,
+                            "chr",
+                            &pixels,
+                            [PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT],
+                        );
+                    }
+                });
+
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.show_chr_viewer = false;
+                }
+            },
+        )
+    }
+}
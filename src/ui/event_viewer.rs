@@ -0,0 +1,178 @@
+use crate::app::App;
+use crate::nes::events::{Event, EventKind};
+use eframe::egui;
+use eframe::egui::{vec2, Color32, Rect, Sense, Stroke, ViewportBuilder, ViewportId};
+use std::collections::HashSet;
+
+// Dots per scanline and scanlines per frame, including the pre-render scanline
+const DOTS: usize = 341;
+const SCANLINES: usize = 262;
+const SCALE: f32 = 2.0;
+// How close (in pixels) the mouse has to be to an event to show its details
+const HOVER_DISTANCE: f32 = 4.0;
+
+const PPU_REGISTER_NAMES: [&str; 8] = [
+    "PPUCTRL",
+    "PPUMASK",
+    "PPUSTATUS",
+    "OAMADDR",
+    "OAMDATA",
+    "PPUSCROLL",
+    "PPUADDR",
+    "PPUDATA",
+];
+
+#[derive(Default)]
+pub struct EventViewer {
+    hidden: HashSet<EventKind>,
+}
+
+fn colour(kind: EventKind) -> Color32 {
+    match kind {
+        EventKind::PpuRead => Color32::from_rgb(80, 170, 255),
+        EventKind::PpuWrite => Color32::from_rgb(255, 110, 80),
+        EventKind::OamDma => Color32::from_rgb(200, 120, 255),
+        EventKind::MapperWrite => Color32::from_rgb(255, 220, 80),
+        EventKind::MapperIrq => Color32::from_rgb(80, 230, 120),
+        EventKind::Nmi => Color32::WHITE,
+    }
+}
+
+fn describe(event: &Event) -> String {
+    let register = PPU_REGISTER_NAMES[(event.addr & 7) as usize];
+    let what = match event.kind {
+        EventKind::PpuRead => format!(
+            "Read ${:04X} ({register}) = ${:02X}",
+            event.addr, event.value
+        ),
+        EventKind::PpuWrite => format!(
+            "Write ${:04X} ({register}) = ${:02X}",
+            event.addr, event.value
+        ),
+        EventKind::OamDma => format!("Write $4014 (OAMDMA) = ${:02X}", event.value),
+        EventKind::MapperWrite => format!("Write ${:04X} = ${:02X}", event.addr, event.value),
+        EventKind::MapperIrq => "Mapper IRQ".to_owned(),
+        EventKind::Nmi => "NMI".to_owned(),
+    };
+    format!(
+        "{what}\nPC ${:04X}\nScanline {}, dot {}\nCPU cycle {}",
+        event.pc, event.scanline, event.cycle, event.cpu_cycle
+    )
+}
+
+impl App {
+    pub fn define_event_viewer(&mut self, ctx: &egui::Context) {
+        if self.emulator.game_loaded() && self.emulator.event_log().is_none() {
+            self.emulator.start_event_log();
+        }
+        ctx.show_viewport_immediate(
+            ViewportId::from_hash_of("event_viewer"),
+            ViewportBuilder::default()
+                .with_title("PPU Event Viewer")
+                .with_inner_size([720.0, 680.0]),
+            |ctx, _class| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let (Some(nes), Some(log)) =
+                        (self.emulator.nes.as_ref(), self.emulator.event_log())
+                    else {
+                        ui.label("No game loaded");
+                        return;
+                    };
+                    let viewer = &mut self.event_viewer;
+
+                    ui.horizontal_wrapped(|ui| {
+                        for kind in EventKind::ALL {
+                            let mut shown = !viewer.hidden.contains(&kind);
+                            let text = egui::RichText::new(kind.label()).color(colour(kind));
+                            if ui.checkbox(&mut shown, text).changed() {
+                                if shown {
+                                    viewer.hidden.remove(&kind);
+                                } else {
+                                    viewer.hidden.insert(kind);
+                                }
+                            }
+                        }
+                    });
+                    let parity = if nes.ppu.odd_frame { "odd" } else { "even" };
+                    ui.label(format!(
+                        "Frame {} ({parity}), scanline {}, dot {}",
+                        nes.ppu.frames, nes.ppu.scanline, nes.ppu.scanline_cycle
+                    ));
+
+                    // This frame up to where the PPU is now, and the last frame after that
+                    let now = (nes.ppu.scanline, nes.ppu.scanline_cycle);
+                    let events: Vec<&Event> = log
+                        .current_frame()
+                        .iter()
+                        .chain(
+                            log.previous_frame()
+                                .iter()
+                                .filter(|event| (event.scanline, event.cycle) > now),
+                        )
+                        .filter(|event| !viewer.hidden.contains(&event.kind))
+                        .collect();
+
+                    let (response, painter) = ui.allocate_painter(
+                        vec2(DOTS as f32, SCANLINES as f32) * SCALE,
+                        Sense::hover(),
+                    );
+                    let rect = response.rect;
+                    // The pre-render scanline is at the top
+                    let to_screen = |cycle: i32, scanline: i32| {
+                        rect.min + vec2(cycle as f32 + 0.5, (scanline + 1) as f32 + 0.5) * SCALE
+                    };
+
+                    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+                    let visible = Rect::from_min_max(
+                        rect.min + vec2(1.0, 1.0) * SCALE,
+                        rect.min + vec2(257.0, 241.0) * SCALE,
+                    );
+                    painter.rect_filled(visible, 0.0, Color32::from_gray(45));
+                    let vblank = Rect::from_min_max(rect.min + vec2(0.0, 242.0) * SCALE, rect.max);
+                    painter.rect_filled(vblank, 0.0, Color32::from_gray(30));
+                    let y = to_screen(0, now.0).y;
+                    painter.hline(
+                        rect.x_range(),
+                        y,
+                        Stroke::new(1.0, Color32::from_white_alpha(60)),
+                    );
+                    painter.circle_stroke(
+                        to_screen(now.1, now.0),
+                        SCALE * 2.0,
+                        Stroke::new(1.0, Color32::WHITE),
+                    );
+
+                    for event in &events {
+                        let centre = to_screen(event.cycle, event.scanline);
+                        painter.circle_filled(centre, SCALE, colour(event.kind));
+                    }
+
+                    if let Some(pos) = response.hover_pos() {
+                        let nearest = events
+                            .iter()
+                            .map(|event| {
+                                let centre = to_screen(event.cycle, event.scanline);
+                                (event, centre.distance(pos))
+                            })
+                            .filter(|(_, distance)| *distance <= HOVER_DISTANCE)
+                            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+                        let dot = ((pos.x - rect.min.x) / SCALE) as i32;
+                        let scanline = ((pos.y - rect.min.y) / SCALE) as i32 - 1;
+                        let text = match nearest {
+                            Some((event, _)) => describe(event),
+                            None => format!("Scanline {scanline}, dot {dot}"),
+                        };
+                        response.on_hover_ui_at_pointer(|ui| {
+                            ui.monospace(text);
+                        });
+                    }
+                    ui.label(format!("{} events", events.len()));
+                });
+
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.show_event_viewer = false;
+                }
+            },
+        )
+    }
+}
@@ -0,0 +1,271 @@
+use crate::app::App;
+use crate::labels::{Label, LabelAddress, Labels};
+use crate::nes::memory_region::MemoryRegion;
+use crate::nes::Nes;
+use eframe::egui;
+use eframe::egui::{Color32, RichText, Sense, ViewportBuilder, ViewportId};
+
+const BYTES_PER_ROW: usize = 16;
+// How long a byte stays highlighted after it changes (in frames)
+const CHANGE_HIGHLIGHT_FRAMES: u64 = 60;
+
+#[derive(Default)]
+pub struct MemoryViewer {
+    pub region: MemoryRegion,
+    goto: String,
+    search: String,
+    value: String,
+    selected: Option<usize>,
+    scroll_to_selected: bool,
+    error: Option<String>,
+    // The region's contents last time it was shown, and the frame each byte last changed on
+    previous: Vec<u8>,
+    changed_on_frame: Vec<Option<u64>>,
+}
+
+impl MemoryViewer {
+    // Compares the region against what it was last time to find the bytes that have changed
+    fn update(&mut self, nes: &mut Nes) {
+        let len = self.region.len(nes);
+        let current: Vec<u8> = (0..len)
+            .map(|offset| self.region.peek(nes, offset))
+            .collect();
+        if self.previous.len() != len {
+            self.changed_on_frame = vec![None; len];
+            self.selected = self.selected.filter(|&offset| offset < len);
+        } else {
+            let frame = nes.ppu.frames;
+            for (offset, (old, new)) in self.previous.iter().zip(&current).enumerate() {
+                if old != new {
+                    self.changed_on_frame[offset] = Some(frame);
+                }
+            }
+        }
+        self.previous = current;
+    }
+
+    fn recently_changed(&self, nes: &Nes, offset: usize) -> bool {
+        self.changed_on_frame[offset]
+            .is_some_and(|frame| nes.ppu.frames.saturating_sub(frame) < CHANGE_HIGHLIGHT_FRAMES)
+    }
+
+    fn select(&mut self, offset: usize) {
+        self.selected = Some(offset);
+        self.value = format!("{:02X}", self.previous[offset]);
+        self.scroll_to_selected = true;
+    }
+
+    // Hex, or a label name in CPU memory
+    fn goto(&mut self, nes: &Nes, labels: &Labels) -> Result<(), String> {
+        let text = self.goto.trim();
+        let label_addr = match self.region {
+            MemoryRegion::Cpu => labels.address_of(nes, text).map(usize::from),
+            _ => None,
+        };
+        let offset = match label_addr {
+            Some(addr) => addr,
+            None => usize::from_str_radix(text.strip_prefix('$').unwrap_or(text), 16)
+                .map_err(|_| format!("\"{text}\" isn't a hex address or label"))?,
+        };
+        if offset >= self.previous.len() {
+            return Err(format!(
+                "${offset:04X} is past the end of {}",
+                self.region.label()
+            ));
+        }
+        self.select(offset);
+        Ok(())
+    }
+
+    // Finds the next occurrence of a sequence of hex bytes after the selected byte, wrapping around
+    fn search(&mut self) -> Result<(), String> {
+        let needle = self
+            .search
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| "Search for hex bytes separated by spaces, e.g. \"A9 00\"".to_owned())?;
+        if needle.is_empty() || needle.len() > self.previous.len() {
+            return Err("Nothing to search for".to_owned());
+        }
+        let start = self.selected.map_or(0, |offset| offset + 1);
+        let count = self.previous.len() - needle.len() + 1;
+        let found = (0..count)
+            .map(|i| (start + i) % count)
+            .find(|&offset| self.previous[offset..].starts_with(&needle));
+        match found {
+            Some(offset) => {
+                self.select(offset);
+                Ok(())
+            }
+            None => Err("Not found".to_owned()),
+        }
+    }
+
+    fn set_selected(&mut self, nes: &mut Nes) -> Result<(), String> {
+        let Some(offset) = self.selected else {
+            return Err("Click a byte to change it".to_owned());
+        };
+        let text = self.value.trim();
+        let val = u8::from_str_radix(text.strip_prefix('$').unwrap_or(text), 16)
+            .map_err(|_| format!("\"{text}\" isn't a hex byte"))?;
+        if !self.region.poke(nes, offset, val) {
+            return Err(format!("${offset:04X} can't be changed from here"));
+        }
+        Ok(())
+    }
+
+    fn label<'a>(&self, nes: &Nes, labels: &'a Labels, offset: usize) -> Option<&'a Label> {
+        match self.region {
+            MemoryRegion::Cpu | MemoryRegion::Wram => labels.get(nes, offset as u16),
+            MemoryRegion::PrgRom => labels.get_exact(LabelAddress::PrgRom(offset)),
+            MemoryRegion::PrgRam => labels.get_exact(LabelAddress::Cpu(0x6000 + offset as u16)),
+            _ => None,
+        }
+    }
+}
+
+impl App {
+    pub fn define_memory_viewer(&mut self, ctx: &egui::Context) {
+        ctx.show_viewport_immediate(
+            ViewportId::from_hash_of("memory_viewer"),
+            ViewportBuilder::default()
+                .with_title("Memory Viewer")
+                .with_inner_size([620.0, 600.0]),
+            |ctx, _class| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let Some(nes) = self.emulator.nes.as_mut() else {
+                        ui.label("No game loaded");
+                        return;
+                    };
+                    let viewer = &mut self.memory_viewer;
+                    viewer.update(nes);
+
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_source("memory_region")
+                            .selected_text(viewer.region.label())
+                            .show_ui(ui, |ui| {
+                                for region in MemoryRegion::ALL {
+                                    ui.selectable_value(&mut viewer.region, region, region.label());
+                                }
+                            });
+                        ui.label(format!("{} bytes", viewer.previous.len()));
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Go to:");
+                        let goto = ui
+                            .add(egui::TextEdit::singleline(&mut viewer.goto).desired_width(120.0));
+                        let entered =
+                            goto.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if ui.button("Go").clicked() || entered {
+                            viewer.error = viewer.goto(nes, &self.labels).err();
+                        }
+                        ui.label("Find:");
+                        let search = ui.add(
+                            egui::TextEdit::singleline(&mut viewer.search).desired_width(120.0),
+                        );
+                        let entered =
+                            search.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if ui.button("Find Next").clicked() || entered {
+                            viewer.error = viewer.search().err();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        match viewer.selected {
+                            Some(offset) => {
+                                let mut text = format!("${offset:04X}");
+                                if let Some(label) = viewer.label(nes, &self.labels, offset) {
+                                    text += &format!(" ({})", label.name);
+                                }
+                                ui.label(text);
+                            }
+                            None => {
+                                ui.label("Nothing selected");
+                            }
+                        }
+                        ui.label("Value:");
+                        let value = ui
+                            .add(egui::TextEdit::singleline(&mut viewer.value).desired_width(30.0));
+                        let entered =
+                            value.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        if ui.button("Set").clicked() || entered {
+                            viewer.error = viewer.set_selected(nes).err();
+                        }
+                    });
+                    if let Some(error) = &viewer.error {
+                        ui.colored_label(Color32::RED, error);
+                    }
+                    ui.separator();
+
+                    let len = viewer.previous.len();
+                    // Wide enough for the last offset in the region
+                    let address_digits = format!("{:X}", len.saturating_sub(1)).len().max(4);
+                    let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                    let mut scroll_area = egui::ScrollArea::vertical().auto_shrink(false);
+                    if viewer.scroll_to_selected {
+                        viewer.scroll_to_selected = false;
+                        if let Some(offset) = viewer.selected {
+                            let row = offset / BYTES_PER_ROW;
+                            scroll_area = scroll_area.vertical_scroll_offset(
+                                (row_height + ui.spacing().item_spacing.y) * row as f32
+                                    - ui.available_height() / 2.5,
+                            );
+                        }
+                    }
+                    let rows = len.div_ceil(BYTES_PER_ROW);
+                    scroll_area.show_rows(ui, row_height, rows, |ui, row_range| {
+                        for row in row_range {
+                            ui.horizontal(|ui| {
+                                ui.spacing_mut().item_spacing.x = 6.0;
+                                let start = row * BYTES_PER_ROW;
+                                let end = (start + BYTES_PER_ROW).min(len);
+                                ui.label(
+                                    RichText::new(format!("{start:0address_digits$X}"))
+                                        .monospace()
+                                        .weak(),
+                                );
+                                for offset in start..end {
+                                    let mut text =
+                                        RichText::new(format!("{:02X}", viewer.previous[offset]))
+                                            .monospace();
+                                    if viewer.recently_changed(nes, offset) {
+                                        text = text.color(Color32::RED);
+                                    }
+                                    if viewer.selected == Some(offset) {
+                                        text = text.background_color(Color32::DARK_GRAY);
+                                    }
+                                    let mut byte =
+                                        ui.add(egui::Label::new(text).sense(Sense::click()));
+                                    if let Some(label) = viewer.label(nes, &self.labels, offset) {
+                                        byte = byte.on_hover_text(&label.name);
+                                    }
+                                    if byte.clicked() {
+                                        viewer.selected = Some(offset);
+                                        viewer.value = format!("{:02X}", viewer.previous[offset]);
+                                    }
+                                }
+                                let ascii: String = viewer.previous[start..end]
+                                    .iter()
+                                    .map(|&byte| {
+                                        if byte.is_ascii_graphic() || byte == b' ' {
+                                            byte as char
+                                        } else {
+                                            '.'
+                                        }
+                                    })
+                                    .collect();
+                                ui.label(RichText::new(ascii).monospace().weak());
+                            });
+                        }
+                    });
+                });
+
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.show_memory_viewer = false;
+                }
+            },
+        )
+    }
+}
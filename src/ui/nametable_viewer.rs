@@ -0,0 +1,172 @@
+use crate::app::App;
+use crate::nes::ppu::debug_view::{self, NAMETABLES_HEIGHT, NAMETABLES_WIDTH};
+use crate::nes::Nes;
+use eframe::egui;
+use eframe::egui::load::SizedTexture;
+use eframe::egui::{
+    vec2, Color32, ColorImage, Rect, Sense, Stroke, TextureHandle, TextureOptions, ViewportBuilder,
+    ViewportId,
+};
+
+pub struct NametableViewer {
+    texture: Option<TextureHandle>,
+    show_scroll: bool,
+}
+
+impl Default for NametableViewer {
+    fn default() -> Self {
+        NametableViewer {
+            texture: None,
+            show_scroll: true,
+        }
+    }
+}
+
+// A run of scanlines drawn from one continuous part of the nametables
+struct ScrollRegion {
+    first_scanline: usize,
+    lines: usize,
+    x: usize,
+    y: usize,
+}
+
+// Splits the frame wherever the scroll position jumps, e.g. under a status bar
+fn scroll_regions(nes: &Nes) -> Vec<ScrollRegion> {
+    let mut regions: Vec<ScrollRegion> = Vec::new();
+    for (scanline, &(v, fine_x)) in nes.ppu.scanline_scroll.iter().enumerate() {
+        let (x, y) = debug_view::scroll_position(v, fine_x);
+        match regions.last_mut() {
+            Some(region) if region.x == x && region.y + region.lines == y => region.lines += 1,
+            _ => regions.push(ScrollRegion {
+                first_scanline: scanline,
+                lines: 1,
+                x,
+                y,
+            }),
+        }
+    }
+    regions
+}
+
+impl App {
+    pub fn define_nametable_viewer(&mut self, ctx: &egui::Context) {
+        ctx.show_viewport_immediate(
+            ViewportId::from_hash_of("nametable_viewer"),
+            ViewportBuilder::default()
+                .with_title("Nametable Viewer")
+                .with_inner_size([540.0, 680.0]),
+            |ctx, _class| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let Some(nes) = self.emulator.nes.as_ref() else {
+                        ui.label("No game loaded");
+                        return;
+                    };
+                    let viewer = &mut self.nametable_viewer;
+
+                    let image = ColorImage::from_rgba_unmultiplied(
+                        [NAMETABLES_WIDTH, NAMETABLES_HEIGHT],
+                        &debug_view::render_nametables(nes),
+                    );
+                    let texture = match viewer.texture.as_mut() {
+                        Some(texture) => {
+                            texture.set(image, TextureOptions::NEAREST);
+                            texture
+                        }
+                        None => viewer.texture.insert(ctx.load_texture(
+                            "nametables",
+                            image,
+                            TextureOptions::NEAREST,
+                        )),
+                    };
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut viewer.show_scroll, "Show scroll");
+                        ui.label(format!(
+                            "v: ${:04X}  t: ${:04X}  x: {}  Mirroring: {:?}",
+                            nes.ppu.v,
+                            nes.ppu.t,
+                            nes.ppu.x,
+                            nes.cart.mirroring()
+                        ));
+                    });
+
+                    let response = ui.add(
+                        egui::Image::from_texture(SizedTexture::from_handle(texture))
+                            .shrink_to_fit()
+                            .sense(Sense::hover()),
+                    );
+                    let rect = response.rect;
+                    let scale = rect.width() / NAMETABLES_WIDTH as f32;
+                    let to_screen =
+                        |x: usize, y: usize| rect.min + vec2(x as f32 * scale, y as f32 * scale);
+                    let painter = ui.painter_at(rect);
+
+                    let regions = scroll_regions(nes);
+                    if viewer.show_scroll {
+                        let fill = Color32::from_white_alpha(24);
+                        let stroke = Stroke::new(1.0, Color32::WHITE);
+                        for region in &regions {
+                            // The screen is 256 pixels wide, so it can wrap around horizontally
+                            let right = region.x + 256;
+                            let spans = if right > NAMETABLES_WIDTH {
+                                vec![(region.x, NAMETABLES_WIDTH), (0, right - NAMETABLES_WIDTH)]
+                            } else {
+                                vec![(region.x, right)]
+                            };
+                            for (left, right) in spans {
+                                let bottom = region.y + region.lines;
+                                let screen_rect = Rect::from_min_max(
+                                    to_screen(left, region.y),
+                                    to_screen(right, bottom),
+                                );
+                                painter.rect(screen_rect, 0.0, fill, stroke);
+                            }
+                        }
+                    }
+
+                    match response.hover_pos() {
+                        Some(pos) => {
+                            let offset = (pos - rect.min) / scale;
+                            let tile_x = (offset.x as usize / 8).min(NAMETABLES_WIDTH / 8 - 1);
+                            let tile_y = (offset.y as usize / 8).min(NAMETABLES_HEIGHT / 8 - 1);
+                            let tile = debug_view::nametable_tile(nes, tile_x, tile_y);
+                            painter.rect_stroke(
+                                Rect::from_min_max(
+                                    to_screen(tile_x * 8, tile_y * 8),
+                                    to_screen(tile_x * 8 + 8, tile_y * 8 + 8),
+                                ),
+                                0.0,
+                                Stroke::new(1.0, Color32::RED),
+                            );
+                            ui.monospace(format!(
+                                "Tile ${:02X} at ${:04X}\n\
+                                 Attribute ${:02X} at ${:04X}, palette {}",
+                                tile.tile, tile.ppu_addr, tile.attr, tile.attr_addr, tile.palette
+                            ));
+                        }
+                        None => {
+                            ui.monospace("Hover over a tile for details\n");
+                        }
+                    }
+
+                    ui.separator();
+                    ui.label("Scroll at the start of each part of the frame:");
+                    // Raster effects can change the scroll on every scanline
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for region in &regions {
+                            let last_scanline = region.first_scanline + region.lines - 1;
+                            ui.monospace(format!(
+                                "Scanlines {:>3}-{:<3}  X: {:>3}  Y: {:>3}",
+                                region.first_scanline, last_scanline, region.x, region.y
+                            ));
+                        }
+                    });
+                });
+
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.show_nametable_viewer = false;
+                }
+            },
+        )
+    }
+}
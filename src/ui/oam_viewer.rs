@@ -0,0 +1,172 @@
+use crate::app::App;
+use crate::nes::ppu::debug_view::{self, SPRITES_HEIGHT, SPRITES_WIDTH};
+use eframe::egui;
+use eframe::egui::load::SizedTexture;
+use eframe::egui::{
+    pos2, vec2, Color32, ColorImage, Rect, RichText, Sense, TextureHandle, TextureOptions,
+    ViewportBuilder, ViewportId,
+};
+
+const PREVIEW_SCALE: f32 = 2.0;
+
+#[derive(Default)]
+pub struct OamViewer {
+    texture: Option<TextureHandle>,
+    // Only list scanlines that had sprites on them
+    hide_empty_scanlines: bool,
+}
+
+impl App {
+    pub fn define_oam_viewer(&mut self, ctx: &egui::Context) {
+        ctx.show_viewport_immediate(
+            ViewportId::from_hash_of("oam_viewer"),
+            ViewportBuilder::default()
+                .with_title("OAM Viewer")
+                .with_inner_size([760.0, 640.0]),
+            |ctx, _class| {
+                self.highlighted_sprite = None;
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let Some(nes) = self.emulator.nes.as_ref() else {
+                        ui.label("No game loaded");
+                        return;
+                    };
+                    let viewer = &mut self.oam_viewer;
+
+                    let image = ColorImage::from_rgba_unmultiplied(
+                        [SPRITES_WIDTH, SPRITES_HEIGHT],
+                        &debug_view::render_sprites(nes),
+                    );
+                    let texture = match viewer.texture.as_mut() {
+                        Some(texture) => {
+                            texture.set(image, TextureOptions::NEAREST);
+                            texture
+                        }
+                        None => viewer.texture.insert(ctx.load_texture(
+                            "sprites",
+                            image,
+                            TextureOptions::NEAREST,
+                        )),
+                    };
+                    let height = debug_view::sprite_height(nes);
+
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "8x{height} sprites, OAMADDR ${:02X}",
+                            nes.ppu.oam_addr
+                        ));
+                        match nes.ppu.sprite_zero_hit_at {
+                            Some((scanline, cycle)) => ui.label(format!(
+                                "Sprite 0 hit on scanline {scanline}, cycle {cycle}"
+                            )),
+                            None => ui.label("No sprite 0 hit this frame"),
+                        };
+                    });
+                    ui.separator();
+
+                    ui.columns(2, |columns| {
+                        egui::ScrollArea::vertical().id_source("sprite_list").show(
+                            &mut columns[0],
+                            |ui| {
+                                egui::Grid::new("sprite_grid").striped(true).show(ui, |ui| {
+                                    for heading in ["#", "", "X", "Y", "Tile", "Pal", "Flip", ""] {
+                                        ui.strong(heading);
+                                    }
+                                    ui.end_row();
+
+                                    for index in 0..64 {
+                                        let sprite = debug_view::sprite(nes, index);
+                                        let (cell_x, cell_y) = ((index % 8) * 8, (index / 8) * 16);
+                                        let uv = Rect::from_min_size(
+                                            pos2(
+                                                cell_x as f32 / SPRITES_WIDTH as f32,
+                                                cell_y as f32 / SPRITES_HEIGHT as f32,
+                                            ),
+                                            vec2(
+                                                8.0 / SPRITES_WIDTH as f32,
+                                                height as f32 / SPRITES_HEIGHT as f32,
+                                            ),
+                                        );
+                                        let flip = match (
+                                            sprite.flip_horizontal(),
+                                            sprite.flip_vertical(),
+                                        ) {
+                                            (false, false) => "",
+                                            (true, false) => "H",
+                                            (false, true) => "V",
+                                            (true, true) => "HV",
+                                        };
+                                        let priority = if sprite.behind_background() {
+                                            "Behind"
+                                        } else {
+                                            "Front"
+                                        };
+
+                                        let mut row = ui.monospace(format!("{index:02}"));
+                                        row |= ui.add(
+                                            egui::Image::from_texture(SizedTexture::from_handle(
+                                                texture,
+                                            ))
+                                            .uv(uv)
+                                            .bg_fill(Color32::DARK_GRAY)
+                                            .fit_to_exact_size(
+                                                vec2(8.0, height as f32) * PREVIEW_SCALE,
+                                            )
+                                            .sense(Sense::hover()),
+                                        );
+                                        row |= ui.monospace(format!("{:3}", sprite.x));
+                                        row |= ui.monospace(format!("{:3}", sprite.y));
+                                        row |= ui.monospace(format!("${:02X}", sprite.tile));
+                                        row |= ui.monospace(format!("{}", sprite.palette()));
+                                        row |= ui.monospace(flip);
+                                        row |= ui.monospace(priority);
+                                        ui.end_row();
+                                        if row.hovered() {
+                                            self.highlighted_sprite = Some(index);
+                                        }
+                                    }
+                                });
+                            },
+                        );
+
+                        let ui = &mut columns[1];
+                        ui.horizontal(|ui| {
+                            ui.strong("Sprite evaluation");
+                            ui.checkbox(&mut viewer.hide_empty_scanlines, "Hide empty scanlines");
+                        });
+                        ui.label("Sprites found on each scanline are drawn on the one after.");
+                        egui::ScrollArea::vertical()
+                            .id_source("scanline_list")
+                            .show(ui, |ui| {
+                                for (scanline, found) in nes.ppu.scanline_sprites.iter().enumerate()
+                                {
+                                    if viewer.hide_empty_scanlines && found.count == 0 {
+                                        continue;
+                                    }
+                                    let sprites = found.sprites[..found.count as usize]
+                                        .iter()
+                                        .map(|index| format!("{index:02}"))
+                                        .collect::<Vec<String>>()
+                                        .join(" ");
+                                    let mut text = format!("{scanline:3}: {sprites}");
+                                    if found.overflow {
+                                        text += "  overflow";
+                                    }
+                                    let text = RichText::new(text).monospace();
+                                    if found.overflow {
+                                        ui.label(text.color(Color32::RED));
+                                    } else {
+                                        ui.label(text);
+                                    }
+                                }
+                            });
+                    });
+                });
+
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.show_oam_viewer = false;
+                    self.highlighted_sprite = None;
+                }
+            },
+        )
+    }
+}
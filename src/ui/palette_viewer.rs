@@ -0,0 +1,65 @@
+use crate::app::App;
+use crate::nes::ppu::{debug_view, peek_vram};
+use eframe::egui;
+use eframe::egui::{vec2, Color32, Sense, ViewportBuilder, ViewportId};
+
+const SWATCH_SIZE: f32 = 24.0;
+
+impl App {
+    pub fn define_palette_viewer(&mut self, ctx: &egui::Context) {
+        ctx.show_viewport_immediate(
+            ViewportId::from_hash_of("palette_viewer"),
+            ViewportBuilder::default()
+                .with_title("Palette Viewer")
+                .with_inner_size([620.0, 330.0]),
+            |ctx, _class| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let Some(nes) = self.emulator.nes.as_ref() else {
+                        ui.label("No game loaded");
+                        return;
+                    };
+
+                    egui::Grid::new("palette_grid").show(ui, |ui| {
+                        for palette in 0..8u16 {
+                            if palette < 4 {
+                                ui.label(format!("Background {palette}"));
+                            } else {
+                                ui.label(format!("Sprite {}", palette - 4));
+                            }
+                            for entry in 0..4 {
+                                let addr = 0x3F00 + palette * 4 + entry;
+                                let (r, g, b) = debug_view::colour(nes, addr);
+                                let (swatch, painter) = ui.allocate_painter(
+                                    vec2(SWATCH_SIZE, SWATCH_SIZE),
+                                    Sense::hover(),
+                                );
+                                painter.rect_filled(swatch.rect, 2.0, Color32::from_rgb(r, g, b));
+                                // 0x3F10, 0x3F14 etc. are mirrors of 0x3F00, 0x3F04 etc.
+                                ui.monospace(format!(
+                                    "${addr:04X}: ${:02X}\n#{r:02X}{g:02X}{b:02X}",
+                                    peek_vram(addr, nes) & 0b0011_1111
+                                ));
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.separator();
+                    if ui.button("Export PNG...").clicked() {
+                        let pixels = debug_view::render_palettes(nes);
+                        self.export_png(
+                            ui,
+                            "palette",
+                            &pixels,
+                            [debug_view::PALETTES_WIDTH, debug_view::PALETTES_HEIGHT],
+                        );
+                    }
+                });
+
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.show_palette_viewer = false;
+                }
+            },
+        )
+    }
+}
@@ -2,6 +2,7 @@ use eframe::egui;
 use eframe::egui::{Color32, FontId, Response, Ui, Vec2, Widget};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use std::hash::Hash;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
 pub enum Input {
@@ -43,7 +44,7 @@ impl Display for Input {
 pub struct InputSelect<'a> {
     pub pressed_input: Option<Input>,
     pub stored_input: Option<&'a mut Input>,
-    pub unique_id: &'static str,
+    pub unique_id: egui::Id,
     pub input_type: InputType,
 }
 
@@ -51,13 +52,13 @@ impl<'a> InputSelect<'a> {
     pub fn new(
         pressed_input: Option<Input>,
         stored_input: Option<&'a mut Input>,
-        unique_id: &'static str,
+        unique_id: impl Hash,
         input_type: InputType,
     ) -> Self {
         InputSelect {
             pressed_input,
             stored_input,
-            unique_id,
+            unique_id: egui::Id::new(unique_id),
             input_type,
         }
     }
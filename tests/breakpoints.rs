@@ -0,0 +1,39 @@
+mod common;
+
+use nes_emu_egui::emulator::{Emulator, FrameInput};
+use nes_emu_egui::nes::breakpoints::{Breakpoint, BreakpointKind};
+
+// Runs `program` from $8000 for a frame with a read breakpoint on start-end, and returns how many
+// times it was hit
+fn read_hits(program: &[u8], start: u16, end: u16) -> u64 {
+    let mut emulator = Emulator::new(None, None);
+    emulator.load_game(common::nrom(program)).unwrap();
+    let breakpoints = emulator.breakpoints_mut().unwrap();
+    breakpoints
+        .list
+        .push(Breakpoint::new(BreakpointKind::CpuRead { start, end }));
+    emulator.run_frame(FrameInput::default());
+    emulator.breakpoints().unwrap().list[0].hits
+}
+
+#[test]
+fn running_code_is_not_reading_it() {
+    // NOP, JMP $8000
+    let program = [0xEA, 0x4C, 0x00, 0x80];
+    assert_eq!(read_hits(&program, 0x8000, 0x80FF), 0);
+}
+
+#[test]
+fn reading_data_hits() {
+    // LDA $8010, JMP $8000
+    let program = [0xAD, 0x10, 0x80, 0x4C, 0x00, 0x80];
+    assert_eq!(read_hits(&program, 0x8010, 0x8010), 1);
+}
+
+#[test]
+fn dummy_reads_are_not_reads() {
+    // LDX #$20, then LDA $80F0,X crosses a page, so it reads $8010 before the real read at $8110
+    let program = [0xA2, 0x20, 0xBD, 0xF0, 0x80, 0x4C, 0x02, 0x80];
+    assert_eq!(read_hits(&program, 0x8010, 0x8010), 0);
+    assert_eq!(read_hits(&program, 0x8110, 0x8110), 1);
+}
@@ -0,0 +1,33 @@
+// Shared by the integration tests, each test binary only uses some of it
+#![allow(dead_code)]
+
+use nes_emu_egui::nes::cartridge::cartridge_def::RomConfig;
+use nes_emu_egui::setup::get_rom_from_bytes;
+
+pub const INES_HEADER_SIZE: usize = 16;
+
+// A ROM image with header bytes 4-15 as given, followed by zeroed PRG and CHR ROM of the sizes in
+// bytes 4 (16KB units) and 5 (8KB units)
+pub fn rom_image(header: [u8; 12]) -> Vec<u8> {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&header);
+    rom.resize(
+        INES_HEADER_SIZE + header[0] as usize * 0x4000 + header[1] as usize * 0x2000,
+        0,
+    );
+    rom
+}
+
+pub fn load(rom: &[u8]) -> RomConfig {
+    get_rom_from_bytes(rom).unwrap_or_else(|err| panic!("{err}"))
+}
+
+// NROM with 16KB of PRG ROM, running `program` from $8000. It has to loop by itself.
+pub fn nrom(program: &[u8]) -> RomConfig {
+    let mut rom = rom_image([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let prg_rom = &mut rom[INES_HEADER_SIZE..INES_HEADER_SIZE + 0x4000];
+    prg_rom[..program.len()].copy_from_slice(program);
+    // Reset vector
+    prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+    load(&rom)
+}
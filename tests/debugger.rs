@@ -0,0 +1,139 @@
+mod common;
+
+use common::INES_HEADER_SIZE;
+use nes_emu_egui::emulator::Emulator;
+use nes_emu_egui::nes::breakpoints::{Breakpoint, BreakpointKind};
+use nes_emu_egui::nes::controller::NesButtonState;
+use nes_emu_egui::nes::cpu::debugger::{DisassemblyView, InstrBytes};
+
+// NROM with 16KB of PRG ROM, which is mirrored so both 8KB banks are always mapped twice
+fn nrom_emulator(prg_rom: impl FnOnce(&mut [u8])) -> Emulator {
+    let mut rom = common::rom_image([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    prg_rom(&mut rom[INES_HEADER_SIZE..INES_HEADER_SIZE + 0x4000]);
+    let mut emulator = Emulator::new(None, None);
+    emulator.load_game(common::load(&rom)).unwrap();
+    emulator
+}
+
+#[test]
+fn jumping_into_an_instruction_at_the_end_of_memory() {
+    let mut emulator = nrom_emulator(|prg_rom| {
+        // JMP $FFFE, into the middle of the STA $xxxx the IRQ vector points at ($FFFD)
+        prg_rom[0x0D00..0x0D03].copy_from_slice(&[0x4C, 0xFE, 0xFF]);
+        // Reset vector $8D00, IRQ vector $FFFD
+        prg_rom[0x3FFC..0x4000].copy_from_slice(&[0x00, 0x8D, 0xFD, 0xFF]);
+    });
+    // The STA has to be forgotten to make way for the CPU's position, without overflowing
+    emulator.run_one_cpu_instruction();
+    assert_eq!(emulator.nes.as_ref().unwrap().cpu.pc, 0xFFFE);
+}
+
+#[test]
+fn banks_are_listed_where_they_are_mapped() {
+    let mut emulator = nrom_emulator(|prg_rom| {
+        // JMP $8000
+        prg_rom[..3].copy_from_slice(&[0x4C, 0x00, 0x80]);
+        prg_rom[0x3FFC..0x3FFE].copy_from_slice(&[0x00, 0x80]);
+    });
+    let nes = emulator.nes.as_ref().unwrap();
+    assert_eq!(DisassemblyView::bank_count(nes), 2);
+
+    emulator.set_disassembly_view(DisassemblyView::Bank(0));
+    let lines = &emulator.instruction_cache;
+    assert_eq!(lines[0].opc_addr, 0x8000);
+    assert!(matches!(lines[0].bytes, InstrBytes::I3(0x4C, 0x00, 0x80)));
+    assert_eq!(lines.last().unwrap().opc_addr, 0x9FFF);
+
+    emulator.set_disassembly_view(DisassemblyView::Bank(1));
+    let lines = &emulator.instruction_cache;
+    assert_eq!(lines[0].opc_addr, 0xA000);
+    assert_eq!(lines[0].prg_rom_offset, 0x2000);
+    assert_eq!(lines.len(), 0x2000);
+
+    // Banks past the end of PRG ROM have nothing in them
+    emulator.set_disassembly_view(DisassemblyView::Bank(2));
+    assert!(emulator.instruction_cache.is_empty());
+}
+
+// Reads controller 1 over and over, and counts at $10 while A is held
+const COUNT_A_PROGRAM: [u8; 22] = [
+    0xA9, 0x01, // LDA #$01
+    0x8D, 0x16, 0x40, // STA $4016
+    0xA9, 0x00, // LDA #$00
+    0x8D, 0x16, 0x40, // STA $4016
+    0xAD, 0x16, 0x40, // LDA $4016
+    0x29, 0x01, // AND #$01
+    0xF0, 0xEF, // BEQ $8000
+    0xE6, 0x10, // INC $10 ($8011)
+    0x4C, 0x00, 0x80, // JMP $8000
+];
+const INC_ADDR: u16 = 0x8011;
+
+fn buttons(a: bool) -> NesButtonState {
+    NesButtonState {
+        a,
+        ..Default::default()
+    }
+}
+
+// Runs frames through update the way the app does, holding A on the frames in `a_frames`, then
+// pauses and steps partway into the next frame before pressing A
+fn record(emulator: &mut Emulator, frames: u32, a_frames: std::ops::Range<u32>) {
+    for frame in 0..frames {
+        emulator.update_controller(1, buttons(a_frames.contains(&frame)));
+        emulator.update((frame + 1) as f64 / 60.0 + 0.001);
+    }
+    emulator.get_set_pause(Some(true));
+    for _ in 0..300 {
+        emulator.run_one_cpu_instruction();
+    }
+    emulator.update_controller(1, buttons(true));
+}
+
+fn instruction_count(emulator: &Emulator) -> u64 {
+    emulator.nes.as_ref().unwrap().cpu.instruction_count
+}
+
+#[test]
+fn stepping_back_replays_recorded_input() {
+    let mut emulator = Emulator::new(None, None);
+    emulator.load_game(common::nrom(&COUNT_A_PROGRAM)).unwrap();
+    record(&mut emulator, 8, 2..4);
+    for _ in 0..300 {
+        emulator.run_one_cpu_instruction();
+    }
+    // Carry on running with A released, then look back through the history
+    emulator.get_set_pause(Some(false));
+    for frame in 8..12 {
+        emulator.update_controller(1, buttons(false));
+        emulator.update((frame + 1) as f64 / 60.0 + 0.001);
+    }
+    emulator.get_set_pause(Some(true));
+    emulator.scrub_by(-100.0);
+    emulator.scrub_by(100.0);
+
+    // The last INC is from when A was pressed while stepping
+    let breakpoints = emulator.breakpoints_mut().unwrap();
+    breakpoints
+        .list
+        .push(Breakpoint::new(BreakpointKind::Execute(INC_ADDR)));
+    assert!(emulator.reverse_continue());
+    let hit = instruction_count(&emulator);
+    let hit_hash = emulator.state_hash();
+    assert_eq!(emulator.nes.as_ref().unwrap().cpu.pc, INC_ADDR);
+    assert!(emulator.step_back());
+    assert_eq!(instruction_count(&emulator), hit - 1);
+    let before_hit_hash = emulator.state_hash();
+
+    // The same input without any rewinding, run straight to the same instructions
+    let mut straight = Emulator::new(None, None);
+    straight.load_game(common::nrom(&COUNT_A_PROGRAM)).unwrap();
+    record(&mut straight, 8, 2..4);
+    assert!(instruction_count(&straight) < hit - 1);
+    while instruction_count(&straight) < hit - 1 {
+        straight.run_one_cpu_instruction();
+    }
+    assert_eq!(straight.state_hash(), before_hit_hash);
+    straight.run_one_cpu_instruction();
+    assert_eq!(straight.state_hash(), hit_hash);
+}
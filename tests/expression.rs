@@ -0,0 +1,82 @@
+mod common;
+
+use nes_emu_egui::emulator::Emulator;
+use nes_emu_egui::nes::breakpoints::expression::{Expression, LogMessage};
+use nes_emu_egui::nes::Nes;
+
+// Freshly powered on, nothing has run yet so PC is still at the reset vector ($8000)
+fn with_nes(f: impl FnOnce(&mut Nes)) {
+    let mut emulator = Emulator::new(None, None);
+    emulator
+        .load_game(common::nrom(&[0x4C, 0x00, 0x80]))
+        .unwrap();
+    f(emulator.nes.as_mut().unwrap());
+}
+
+fn evaluate(nes: &mut Nes, source: &str) -> i64 {
+    Expression::parse(source)
+        .unwrap_or_else(|err| panic!("{source}: {err}"))
+        .evaluate(nes)
+}
+
+#[test]
+fn operator_precedence() {
+    with_nes(|nes| {
+        assert_eq!(evaluate(nes, "1 + 2 * 3"), 7);
+        assert_eq!(evaluate(nes, "(1 + 2) * 3"), 9);
+        assert_eq!(evaluate(nes, "1 + 2 << 1"), 6);
+        // Comparisons bind tighter than bitwise operators, like C
+        assert_eq!(evaluate(nes, "6 & 3 == 3"), 0);
+        assert_eq!(evaluate(nes, "1 || 0 && 0"), 1);
+        assert_eq!(evaluate(nes, "10 - 4 - 3"), 3);
+        assert_eq!(evaluate(nes, "-2 * 3"), -6);
+        assert_eq!(evaluate(nes, "!0 + ~0"), 0);
+    });
+}
+
+#[test]
+fn percent_is_binary_or_remainder() {
+    with_nes(|nes| {
+        assert_eq!(evaluate(nes, "%101"), 5);
+        assert_eq!(evaluate(nes, "%0010_0000"), 32);
+        assert_eq!(evaluate(nes, "7 % 3"), 1);
+        // After a value it's always the remainder operator
+        assert_eq!(evaluate(nes, "7 %11"), 7);
+        assert_eq!(evaluate(nes, "(%11) % %10"), 1);
+        assert_eq!(evaluate(nes, "5 + %11"), 8);
+    });
+}
+
+#[test]
+fn number_literals() {
+    with_nes(|nes| {
+        assert_eq!(evaluate(nes, "$20"), 0x20);
+        assert_eq!(evaluate(nes, "0x20"), 0x20);
+        assert_eq!(evaluate(nes, "$ff_ff"), 0xFFFF);
+        assert_eq!(evaluate(nes, "1_000"), 1000);
+        assert_eq!(evaluate(nes, "pc == $8000"), 1);
+        assert_eq!(evaluate(nes, "[$8000]"), 0x4C);
+    });
+    assert!(Expression::parse("$").is_err());
+    assert!(Expression::parse("0xZZ").is_err());
+    assert!(Expression::parse("%12").is_err());
+}
+
+#[test]
+fn invalid_expressions_are_errors() {
+    for source in ["", "1 +", "(1", "[1", "1 2", "nope", "é", "a == é", "$é"] {
+        assert!(Expression::parse(source).is_err(), "{source}");
+    }
+    assert!(LogMessage::parse("x {é}").is_err());
+    assert!(LogMessage::parse("x {1").is_err());
+    assert!(LogMessage::parse("{}").is_err());
+}
+
+#[test]
+fn log_message_formatting() {
+    with_nes(|nes| {
+        let message = LogMessage::parse("PC {pc}, opcode {[pc]}, {1 + 2} é").unwrap();
+        assert_eq!(message.format(nes), "PC 8000, opcode 4C, 03 é");
+        assert_eq!(message.to_string(), "PC {pc}, opcode {[pc]}, {1 + 2} é");
+    });
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use nes_emu_egui::emulator::{Emulator, FrameInput, FRAME_HEIGHT, FRAME_WIDTH};
+
+// NROM cartridge that loops forever at the reset vector (JMP $8000)
+fn load_looping_rom(emulator: &mut Emulator) {
+    emulator
+        .load_game(common::nrom(&[0x4C, 0x00, 0x80]))
+        .unwrap();
+}
+
+#[test]
+fn run_frame_returns_video_and_audio() {
+    let mut emulator = Emulator::new(None, None);
+    load_looping_rom(&mut emulator);
+
+    // The first frame starts partway through, after that each frame is a full 1/60th of a second
+    emulator.run_frame(FrameInput::default());
+    let samples_per_frame = emulator.sample_rate() / 60.0;
+    for _ in 0..10 {
+        let output = emulator.run_frame(FrameInput::default());
+        assert_eq!(output.video.len(), FRAME_WIDTH * FRAME_HEIGHT * 4);
+        assert!((output.audio.len() as f32 / samples_per_frame - 1.0).abs() < 0.02);
+    }
+    assert_eq!(emulator.nes.as_ref().unwrap().cpu.pc & 0xFFF0, 0x8000);
+}
+
+#[test]
+fn loading_another_game_starts_from_scratch() {
+    let mut emulator = Emulator::new(None, None);
+    load_looping_rom(&mut emulator);
+    for _ in 0..10 {
+        emulator.run_frame(FrameInput::default());
+    }
+
+    // The new game's CPU starts again from cycle 0
+    emulator
+        .load_game(common::nrom(&[0xEA, 0x4C, 0x00, 0x80]))
+        .unwrap();
+    let output = emulator.run_frame(FrameInput::default());
+    assert_eq!(output.video.len(), FRAME_WIDTH * FRAME_HEIGHT * 4);
+    assert_eq!(emulator.nes.as_ref().unwrap().cpu.pc & 0xFFF0, 0x8000);
+}
@@ -0,0 +1,142 @@
+use nes_emu_egui::labels::{LabelAddress, Labels};
+use std::fs;
+use std::path::PathBuf;
+
+// Writes a label file under the given name and imports it into an empty set of labels
+fn import(test: &str, file_name: &str, text: &str) -> Labels {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("labels_{test}"));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(file_name);
+    fs::write(&path, text).unwrap();
+    let mut labels = Labels::default();
+    let count = labels.import(&path).unwrap_or_else(|err| panic!("{err}"));
+    assert_eq!(count, labels.len());
+    labels
+}
+
+fn name_at(labels: &Labels, addr: LabelAddress) -> &str {
+    match labels.get_exact(addr) {
+        Some(label) => &label.name,
+        None => panic!("no label at {addr:?}"),
+    }
+}
+
+#[test]
+fn nl_ram_labels_are_cpu_addresses() {
+    let labels = import(
+        "nl_ram",
+        "game.nes.ram.nl",
+        "$0010#counter#Frames since reset#\n$0300/10#buffer#\nnot a label\n",
+    );
+    assert_eq!(labels.len(), 2);
+    let counter = labels.get_exact(LabelAddress::Cpu(0x0010)).unwrap();
+    assert_eq!(counter.name, "counter");
+    assert_eq!(counter.comment, "Frames since reset");
+    assert_eq!(name_at(&labels, LabelAddress::Cpu(0x0300)), "buffer");
+}
+
+#[test]
+fn nl_bank_numbers_are_hex() {
+    let labels = import("nl_bank_3", "game.nes.3.nl", "$8123#in_bank_3#\n");
+    assert_eq!(
+        name_at(&labels, LabelAddress::PrgRom(3 * 0x4000 + 0x0123)),
+        "in_bank_3"
+    );
+
+    // Bank 10 is written by FCEUX as "A", either half of the CPU address space means the same bank
+    let labels = import("nl_bank_a", "game.nes.A.nl", "$C456#in_bank_a#\n");
+    assert_eq!(
+        name_at(&labels, LabelAddress::PrgRom(0xA * 0x4000 + 0x0456)),
+        "in_bank_a"
+    );
+    let labels = import("nl_bank_10", "game.nes.10.nl", "$8000#in_bank_16#\n");
+    assert_eq!(
+        name_at(&labels, LabelAddress::PrgRom(0x10 * 0x4000)),
+        "in_bank_16"
+    );
+}
+
+#[test]
+fn mlb_labels_by_memory_type() {
+    let labels = import(
+        "mlb",
+        "game.mlb",
+        "P:4123:reset:Comment with\\na line break\n\
+         R:0010:counter\n\
+         W:0100-0107:save_slot\n\
+         G:2002:PPUSTATUS\n\
+         C:0100:tile\n",
+    );
+    assert_eq!(labels.len(), 4);
+    let reset = labels.get_exact(LabelAddress::PrgRom(0x4123)).unwrap();
+    assert_eq!(reset.name, "reset");
+    assert_eq!(reset.comment, "Comment with\na line break");
+    assert_eq!(name_at(&labels, LabelAddress::Cpu(0x0010)), "counter");
+    assert_eq!(name_at(&labels, LabelAddress::Cpu(0x6100)), "save_slot");
+    assert_eq!(name_at(&labels, LabelAddress::Cpu(0x2002)), "PPUSTATUS");
+}
+
+#[test]
+fn saved_labels_read_back_the_same() {
+    let labels = import(
+        "mlb_saved",
+        "game.mlb",
+        "P:4123:reset:Two\\nlines\nR:0010:counter\nW:0100:save_slot\nG:2002:PPUSTATUS\n",
+    );
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("labels_mlb_saved/saved.mlb");
+    labels.save(&path).unwrap();
+    let mut saved = Labels::default();
+    saved.import(&path).unwrap();
+
+    let pairs = |labels: &Labels| {
+        let pairs = labels
+            .iter()
+            .map(|(addr, label)| (*addr, label.name.clone(), label.comment.clone()));
+        pairs.collect::<Vec<_>>()
+    };
+    assert_eq!(pairs(&saved), pairs(&labels));
+}
+
+// Two PRG ROM segments, one at the start of the file and one in the second 16 KB bank, plus a
+// zero page segment that isn't in the output file at all
+const DBG: &str = r#"version major=2,minor=0
+info csym=0,file=1,lib=0,line=4,mod=1,scope=1,seg=3,span=4,sym=5,type=1
+file id=0,name="main.s",size=100,mtime=0x5F000000,mod=0
+seg id=0,name="ZEROPAGE",start=0x000000,size=0x0010,addrsize=zeropage,type=rw
+seg id=1,name="CODE",start=0x008000,size=0x0100,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+seg id=2,name="BANK1",start=0x00C000,size=0x0100,addrsize=absolute,type=ro,oname="game.nes",ooffs=16400
+sym id=0,name="counter",addrsize=zeropage,scope=0,def=1,val=0x10,seg=0,type=lab
+sym id=1,name="reset",addrsize=absolute,scope=0,def=2,val=0x8000,seg=1,type=lab
+sym id=2,name="nmi, handler",addrsize=absolute,scope=0,def=3,val=0x8042,seg=1,type=lab
+sym id=3,name="far_away",addrsize=absolute,scope=0,def=4,val=0xC123,seg=2,type=lab
+sym id=4,name="SPEED",addrsize=zeropage,scope=0,def=5,val=0x3,type=equ
+"#;
+
+#[test]
+fn dbg_labels_in_prg_rom_skip_the_ines_header() {
+    let labels = import("dbg", "game.dbg", DBG);
+    assert_eq!(labels.len(), 4);
+    assert_eq!(name_at(&labels, LabelAddress::Cpu(0x0010)), "counter");
+    assert_eq!(name_at(&labels, LabelAddress::PrgRom(0x0000)), "reset");
+    assert_eq!(
+        name_at(&labels, LabelAddress::PrgRom(0x0042)),
+        "nmi, handler"
+    );
+    assert_eq!(name_at(&labels, LabelAddress::PrgRom(0x4123)), "far_away");
+}
+
+#[test]
+fn other_files_are_refused() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("labels_refused");
+    fs::create_dir_all(&dir).unwrap();
+    let mut labels = Labels::default();
+    for (file_name, text) in [
+        ("game.dbg", "sym id=0,name=\"x\"\n"),
+        ("game.txt", "$8000#x#\n"),
+    ] {
+        let path = dir.join(file_name);
+        fs::write(&path, text).unwrap();
+        assert!(labels.import(&path).is_err());
+    }
+    assert!(labels.is_empty());
+}
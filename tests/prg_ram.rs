@@ -0,0 +1,134 @@
+mod common;
+
+use nes_emu_egui::emulator::{Emulator, FrameInput};
+use nes_emu_egui::nes::cartridge::{create_cartridge, Cartridge};
+
+const MMC3_WRAM_ENABLE_A001: u16 = 0xA001;
+
+// Builds a 128KB PRG / 128KB CHR MMC3 ROM with the given header bytes 6-15 and loads it
+fn load_mmc3_rom(flags_6: u8, header_tail: [u8; 9]) -> Box<dyn Cartridge> {
+    let mut header = [8, 16, flags_6, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    header[3..].copy_from_slice(&header_tail);
+    let rom_config = common::load(&common::rom_image(header));
+    create_cartridge(rom_config).unwrap_or_else(|err| panic!("{err}"))
+}
+
+// Stores $5A at $6000, reads it back and leaves what it read at $0000. Without PRG RAM the read is
+// open bus, which is $60 from the high byte of the address.
+const READ_BACK_PROGRAM: [u8; 15] = [
+    0xA9, 0x5A, // LDA #$5A
+    0x8D, 0x00, 0x60, // STA $6000
+    0xA9, 0x00, // LDA #$00
+    0xAD, 0x00, 0x60, // LDA $6000
+    0x85, 0x00, // STA $00
+    0x4C, 0x0C, 0xE0, // JMP $E00C
+];
+const OPEN_BUS: u8 = 0x60;
+
+// Runs READ_BACK_PROGRAM on a ROM with the given header bytes 4-15 and returns what it read
+fn read_back_through_cpu(header: [u8; 12]) -> u8 {
+    let mut rom = common::rom_image(header);
+    let prg_rom_end = common::INES_HEADER_SIZE + header[0] as usize * 0x4000;
+    // The last 8KB of PRG ROM, all of the boards tested here have it fixed at $E000
+    let last_bank = &mut rom[prg_rom_end - 0x2000..prg_rom_end];
+    last_bank[..READ_BACK_PROGRAM.len()].copy_from_slice(&READ_BACK_PROGRAM);
+    last_bank[0x1FFC..0x1FFE].copy_from_slice(&[0x00, 0xE0]);
+
+    let mut emulator = Emulator::new(None, None);
+    emulator.load_game(common::load(&rom)).unwrap();
+    emulator.run_frame(FrameInput::default());
+    emulator.nes.as_ref().unwrap().wram[0]
+}
+
+#[test]
+fn ines_mmc3_without_battery_has_prg_ram() {
+    // Shaped like Super Mario Bros. 3: TSROM, 256KB PRG / 128KB CHR, 8KB of PRG RAM but no battery
+    // and nothing in byte 8. It used to crash at startup because the battery bit was used to
+    // decide if PRG RAM existed.
+    assert_eq!(
+        read_back_through_cpu([16, 16, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        0x5A
+    );
+}
+
+#[test]
+fn ines_nrom_test_rom_has_prg_ram() {
+    // Shaped like blargg's NROM test ROMs: 32KB PRG / 8KB CHR and nothing else in the header. They
+    // report their results at $6000.
+    assert_eq!(
+        read_back_through_cpu([2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        0x5A
+    );
+    // Unless byte 10 says there's no PRG RAM
+    assert_eq!(
+        read_back_through_cpu([2, 1, 0, 0, 0, 0, 0x10, 0, 0, 0, 0, 0]),
+        OPEN_BUS
+    );
+}
+
+#[test]
+fn ines_discrete_boards_have_no_prg_ram_by_default() {
+    // UxROM, CNROM and AxROM
+    for flags_6 in [0x20, 0x30, 0x70] {
+        let header = [2, 1, flags_6, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(
+            read_back_through_cpu(header),
+            OPEN_BUS,
+            "flags 6 = {flags_6:02X}"
+        );
+    }
+}
+
+#[test]
+fn ines_prg_ram_from_header() {
+    // NROM with byte 8 asking for 8KB of PRG RAM, and NROM with a battery (Family BASIC)
+    assert_eq!(
+        read_back_through_cpu([1, 1, 0x00, 0, 1, 0, 0, 0, 0, 0, 0, 0]),
+        0x5A
+    );
+    assert_eq!(
+        read_back_through_cpu([1, 1, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        0x5A
+    );
+    // MMC3 with byte 10 bit 4 saying there's no PRG RAM
+    let header = [16, 16, 0x40, 0, 0, 0, 0x10, 0, 0, 0, 0, 0];
+    assert_eq!(read_back_through_cpu(header), OPEN_BUS);
+}
+
+#[test]
+fn nes2_without_prg_ram_is_open_bus() {
+    // NES 2.0 header with all RAM sizes set to 0
+    let mut cart = load_mmc3_rom(0x40, [0x08, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    cart.write_prg_ram(0x6000, 0x12);
+    assert_eq!(cart.read_prg_ram(0x6000), None);
+}
+
+#[test]
+fn nes2_small_prg_ram_is_mirrored() {
+    // NES 2.0 header with 2KB of PRG RAM (64 << 5)
+    let mut cart = load_mmc3_rom(0x40, [0x08, 0, 0, 0x05, 0, 0, 0, 0, 0]);
+
+    cart.write_prg_ram(0x6000, 0x56);
+    assert_eq!(cart.read_prg_ram(0x6800), Some(0x56));
+}
+
+#[test]
+fn mmc3_a001_disables_and_write_protects_prg_ram() {
+    let mut cart = load_mmc3_rom(0x42, [0; 9]);
+    cart.write_prg_ram(0x6000, 0x12);
+
+    // Chip enabled, writes denied
+    cart.write_prg_rom(MMC3_WRAM_ENABLE_A001, 0b1100_0000);
+    cart.write_prg_ram(0x6000, 0x34);
+    assert_eq!(cart.read_prg_ram(0x6000), Some(0x12));
+
+    // Chip disabled, reads fall through to open bus
+    cart.write_prg_rom(MMC3_WRAM_ENABLE_A001, 0b0000_0000);
+    assert_eq!(cart.read_prg_ram(0x6000), None);
+
+    cart.write_prg_rom(MMC3_WRAM_ENABLE_A001, 0b1000_0000);
+    cart.write_prg_ram(0x6000, 0x34);
+    assert_eq!(cart.read_prg_ram(0x6000), Some(0x34));
+    assert_eq!(cart.battery_ram().map(|ram| ram[0]), Some(0x34));
+}
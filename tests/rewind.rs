@@ -0,0 +1,94 @@
+use nes_emu_egui::rewind::RewindBuffer;
+
+// Frames per keyframe in the buffer
+const SEGMENT_LENGTH: usize = 60;
+
+// Mostly the same from one frame to the next, like serialized NES states, but growing and
+// shrinking so deltas have to cope with states longer and shorter than their keyframe
+fn state(frame: usize) -> Vec<u8> {
+    let len = 200 + (frame * 37) % 90;
+    (0..len)
+        .map(|i| match i % 50 {
+            0 => frame as u8,
+            1 => (frame * i) as u8,
+            _ => i as u8,
+        })
+        .collect()
+}
+
+fn buffer_with(frames: usize) -> RewindBuffer {
+    let mut buffer = RewindBuffer::new(usize::MAX, usize::MAX);
+    for frame in 0..frames {
+        buffer.push(state(frame));
+    }
+    buffer
+}
+
+fn assert_holds_first(buffer: &RewindBuffer, frames: usize) {
+    assert_eq!(buffer.len(), frames);
+    for frame in 0..frames {
+        assert_eq!(buffer.get(frame), Some(state(frame)), "frame {frame}");
+    }
+    assert_eq!(buffer.get(frames), None);
+    // Nothing left over from what was dropped
+    assert_eq!(buffer.size(), buffer_with(frames).size());
+}
+
+#[test]
+fn states_round_trip() {
+    assert_holds_first(
+        &buffer_with(SEGMENT_LENGTH * 2 + 10),
+        SEGMENT_LENGTH * 2 + 10,
+    );
+}
+
+#[test]
+fn empty_states_round_trip() {
+    let mut buffer = RewindBuffer::new(usize::MAX, usize::MAX);
+    // An empty keyframe, then a state longer than it and an empty one again
+    buffer.push(Vec::new());
+    buffer.push(state(1));
+    buffer.push(Vec::new());
+    assert_eq!(buffer.get(0), Some(Vec::new()));
+    assert_eq!(buffer.get(1), Some(state(1)));
+    assert_eq!(buffer.get(2), Some(Vec::new()));
+}
+
+#[test]
+fn truncate_within_a_segment() {
+    let mut buffer = buffer_with(SEGMENT_LENGTH * 2 + 10);
+    buffer.truncate(SEGMENT_LENGTH + 25);
+    assert_holds_first(&buffer, SEGMENT_LENGTH + 25);
+
+    // New frames are made against the right keyframe afterwards
+    buffer.push(state(SEGMENT_LENGTH + 25));
+    assert_holds_first(&buffer, SEGMENT_LENGTH + 26);
+}
+
+#[test]
+fn truncate_across_segments() {
+    let mut buffer = buffer_with(SEGMENT_LENGTH * 3);
+    buffer.truncate(SEGMENT_LENGTH - 1);
+    assert_holds_first(&buffer, SEGMENT_LENGTH - 1);
+
+    // Down to exactly a segment boundary, then to nothing
+    let mut buffer = buffer_with(SEGMENT_LENGTH * 3);
+    buffer.truncate(SEGMENT_LENGTH);
+    assert_holds_first(&buffer, SEGMENT_LENGTH);
+    buffer.truncate(0);
+    assert_holds_first(&buffer, 0);
+    assert_eq!(buffer.size(), 0);
+
+    buffer.push(state(0));
+    assert_holds_first(&buffer, 1);
+}
+
+#[test]
+fn limits_drop_whole_segments_from_the_front() {
+    let mut buffer = RewindBuffer::new(SEGMENT_LENGTH * 2, usize::MAX);
+    for frame in 0..SEGMENT_LENGTH * 2 + 1 {
+        buffer.push(state(frame));
+    }
+    assert_eq!(buffer.len(), SEGMENT_LENGTH + 1);
+    assert_eq!(buffer.get(0), Some(state(SEGMENT_LENGTH)));
+}
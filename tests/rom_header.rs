@@ -0,0 +1,19 @@
+use nes_emu_egui::setup::get_rom_from_bytes;
+
+#[test]
+fn nes2_oversized_rom_is_an_error() {
+    // NES 2.0 exponent-multiplier sizes of 2^63 * 7 bytes, too big for a usize
+    let rom = [
+        b'N', b'E', b'S', 0x1A, 0xFF, 0xFF, 0, 0x08, 0, 0xFF, 0, 0, 0, 0, 0, 0,
+    ];
+    let Err(err) = get_rom_from_bytes(&rom) else {
+        panic!("Loaded a ROM with an impossible size");
+    };
+    assert!(err.to_string().contains("file not long enough"), "{err}");
+
+    // 2^63 bytes each of PRG and CHR ROM, which only overflow once they're added together
+    let rom = [
+        b'N', b'E', b'S', 0x1A, 0xFC, 0xFC, 0, 0x08, 0, 0xFF, 0, 0, 0, 0, 0, 0,
+    ];
+    assert!(get_rom_from_bytes(&rom).is_err());
+}
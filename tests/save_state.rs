@@ -0,0 +1,78 @@
+mod common;
+
+use nes_emu_egui::emulator::{Emulator, FrameInput};
+use nes_emu_egui::save_state::{SaveStateError, FORMAT_VERSION};
+
+// Keeps turning the background on, so frames are drawn (in the backdrop colour)
+const SHOW_BACKGROUND_PROGRAM: [u8; 8] = [
+    0xA9, 0x08, // LDA #$08
+    0x8D, 0x01, 0x20, // STA $2001
+    0x4C, 0x00, 0x80, // JMP $8000
+];
+
+fn emulator_with(program: &[u8]) -> Emulator {
+    let mut emulator = Emulator::new(None, None);
+    emulator.load_game(common::nrom(program)).unwrap();
+    emulator
+}
+
+// A state saved a few frames in, and the emulator it was saved from
+fn saved_state() -> (Emulator, Vec<u8>) {
+    let mut emulator = emulator_with(&SHOW_BACKGROUND_PROGRAM);
+    for _ in 0..5 {
+        emulator.run_frame(FrameInput::default());
+    }
+    let state = emulator.save_state().unwrap();
+    (emulator, state)
+}
+
+#[test]
+fn state_round_trips() {
+    let (mut original, state) = saved_state();
+    let saved_hash = original.state_hash();
+
+    // A fresh emulator's frame buffer is all zeroes, it only fills up if the loaded Nes draws to it
+    let mut restored = emulator_with(&SHOW_BACKGROUND_PROGRAM);
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.state_hash(), saved_hash);
+    assert!(restored.nes.as_ref().unwrap().frame.is_some());
+
+    let expected = original.run_frame(FrameInput::default());
+    let output = restored.run_frame(FrameInput::default());
+    assert!(output.video.iter().any(|&byte| byte != 0));
+    assert!(output.video == expected.video);
+    assert_eq!(restored.state_hash(), original.state_hash());
+}
+
+#[test]
+fn state_from_another_rom_is_refused() {
+    let (_, state) = saved_state();
+    let mut other = emulator_with(&[0xEA, 0x4C, 0x00, 0x80]);
+    let hash = other.state_hash();
+
+    let result = other.load_state(&state);
+    assert!(matches!(result, Err(SaveStateError::DifferentRom)));
+    assert_eq!(other.state_hash(), hash);
+}
+
+#[test]
+fn state_from_another_format_version_is_refused() {
+    let (mut emulator, mut state) = saved_state();
+    // The version follows the 8 byte magic number
+    state[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+    let result = emulator.load_state(&state);
+    assert!(matches!(
+        result,
+        Err(SaveStateError::IncompatibleVersion(version)) if version == FORMAT_VERSION + 1
+    ));
+}
+
+#[test]
+fn other_files_are_refused() {
+    let (mut emulator, state) = saved_state();
+    let result = emulator.load_state(&state[..6]);
+    assert!(matches!(result, Err(SaveStateError::NotASaveState)));
+    let result = emulator.load_state(b"NES\x1A not a save state");
+    assert!(matches!(result, Err(SaveStateError::NotASaveState)));
+}
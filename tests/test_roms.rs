@@ -0,0 +1,311 @@
+use image::RgbaImage;
+use nes_emu_egui::emulator::{Emulator, FrameInput, FRAME_HEIGHT, FRAME_WIDTH};
+use nes_emu_egui::nes::{apu, cpu, ppu, Nes};
+use nes_emu_egui::setup::get_rom_from_file;
+use std::path::PathBuf;
+
+/*
+    Runs test ROMs from https://github.com/christopherpow/nes-test-roms and reports a result for
+    each one. The ROMs aren't included in the repo, clone that repository into tests/roms (or point
+    NES_TEST_ROMS at a copy of it) to run them. Individual ROMs that can't be found are skipped, but
+    the test fails if none of them are there.
+
+    The test is ignored by default since it needs the ROMs, and they're slow in debug builds, so
+    run it with:
+    cargo test --release --test test_roms -- --ignored --nocapture
+*/
+
+enum Check {
+    // nestest in automation mode, started at $C000 with no PPU. Error codes end up in $02 and $03.
+    Nestest,
+    // Newer blargg tests report their status at $6000 and write a message from $6004.
+    Blargg,
+    // Older (2005) blargg tests write a result code to $F8 when they finish, 1 means passed.
+    // They also show it on screen.
+    LegacyBlargg,
+    // ROMs that only show their results on screen. The CRC32 of the frame after `frames` frames
+    // has to match `crc`. Until someone with the ROM has checked the screen and pinned it, `crc`
+    // is None and the ROM fails, with the frame saved so it can be looked at.
+    FrameHash { frames: u32, crc: Option<u32> },
+}
+
+const TEST_ROMS: &[(&str, Check)] = &[
+    ("other/nestest.nes", Check::Nestest),
+    // CPU
+    ("cpu_instrs/cpu_instrs.nes", Check::Blargg),
+    ("instr_test-v5/all_instrs.nes", Check::Blargg),
+    ("instr_timing/instr_timing.nes", Check::Blargg),
+    ("cpu_interrupts_v2/cpu_interrupts.nes", Check::Blargg),
+    // PPU
+    ("ppu_vbl_nmi/ppu_vbl_nmi.nes", Check::Blargg),
+    ("oam_read/oam_read.nes", Check::Blargg),
+    (
+        "sprite_hit_tests_2005.10.05/01.basics.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/02.alignment.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/03.corners.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/04.flip.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/05.left_clip.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/06.right_edge.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/07.screen_bottom.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/08.double_height.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/09.timing_basics.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/10.timing_order.nes",
+        Check::LegacyBlargg,
+    ),
+    (
+        "sprite_hit_tests_2005.10.05/11.edge_timing.nes",
+        Check::LegacyBlargg,
+    ),
+    // APU
+    ("apu_test/apu_test.nes", Check::Blargg),
+    // Mappers
+    ("mmc3_test_2/rom_singles/1-clocking.nes", Check::Blargg),
+    ("mmc3_test_2/rom_singles/2-details.nes", Check::Blargg),
+    ("mmc3_test_2/rom_singles/3-A12_clocking.nes", Check::Blargg),
+    (
+        "mmc3_test_2/rom_singles/4-scanline_timing.nes",
+        Check::Blargg,
+    ),
+    ("mmc3_test_2/rom_singles/5-MMC3.nes", Check::Blargg),
+    // Visual
+    (
+        "scanline/scanline.nes",
+        Check::FrameHash {
+            frames: 60,
+            crc: None,
+        },
+    ),
+    (
+        "full_palette/full_palette.nes",
+        Check::FrameHash {
+            frames: 60,
+            crc: None,
+        },
+    ),
+    (
+        "nmi_sync/demo_ntsc.nes",
+        Check::FrameHash {
+            frames: 120,
+            crc: None,
+        },
+    ),
+];
+
+// Emulated time a blargg test gets before it's considered hung
+const BLARGG_TIMEOUT_FRAMES: u32 = 60 * 120;
+// The ROM asks to be reset, and wants at least 100ms to pass before it is
+const BLARGG_RESET_DELAY_FRAMES: u32 = 10;
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const LEGACY_BLARGG_RESULT: usize = 0xF8;
+
+// Last instruction of the automated tests, after this it returns to nowhere
+const NESTEST_END_PC: u16 = 0xC66E;
+const NESTEST_MAX_CYCLES: u64 = 100_000;
+
+enum Outcome {
+    Pass,
+    Fail(String),
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see tests/test_roms.rs"]
+fn test_roms() {
+    let rom_dir = std::env::var_os("NES_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms"));
+    assert!(rom_dir.is_dir(), "No test ROMs at {}", rom_dir.display());
+
+    let mut failures = Vec::new();
+    let mut found = 0;
+    for (name, check) in TEST_ROMS {
+        let path = rom_dir.join(name);
+        if !path.is_file() {
+            println!("SKIP  {name}");
+            continue;
+        }
+        found += 1;
+        let outcome = match get_rom_from_file(&path) {
+            Ok(rom_config) => {
+                let mut emulator = Emulator::new(None, None);
+                match emulator.load_game(rom_config) {
+                    Ok(()) => run_check(&mut emulator, name, check),
+                    Err(err) => Outcome::Fail(err.to_string()),
+                }
+            }
+            Err(err) => Outcome::Fail(err.to_string()),
+        };
+        match outcome {
+            Outcome::Pass => println!("PASS  {name}"),
+            Outcome::Fail(message) => {
+                println!("FAIL  {name}: {message}");
+                failures.push(*name);
+            }
+        }
+    }
+
+    assert!(
+        found > 0,
+        "None of the test ROMs are in {}",
+        rom_dir.display()
+    );
+    assert!(failures.is_empty(), "Failed test ROMs: {failures:?}");
+}
+
+fn run_check(emulator: &mut Emulator, name: &str, check: &Check) -> Outcome {
+    match check {
+        Check::Nestest => run_nestest(emulator.nes.as_mut().unwrap()),
+        Check::Blargg => run_blargg(emulator),
+        Check::LegacyBlargg => run_legacy_blargg(emulator),
+        Check::FrameHash { frames, crc } => run_frame_hash(emulator, name, *frames, *crc),
+    }
+}
+
+fn run_nestest(nes: &mut Nes) -> Outcome {
+    nes.cpu.pc = 0xC000;
+    loop {
+        let instruction_done = cpu::step_cpu(nes);
+        for _ in 0..3 {
+            ppu::step_ppu(nes);
+        }
+        apu::step_apu(nes);
+
+        if instruction_done && nes.cpu.pc == NESTEST_END_PC {
+            break;
+        }
+        if nes.cpu.cycles > NESTEST_MAX_CYCLES {
+            return Outcome::Fail(format!("never reached ${NESTEST_END_PC:04X}"));
+        }
+    }
+    match (nes.wram[0x02], nes.wram[0x03]) {
+        (0, 0) => Outcome::Pass,
+        (official, unofficial) => Outcome::Fail(format!(
+            "official opcode error {official:02X}, unofficial opcode error {unofficial:02X}"
+        )),
+    }
+}
+
+fn run_blargg(emulator: &mut Emulator) -> Outcome {
+    // All of the blargg ROMs listed are NROM, MMC1 or MMC3, which get PRG RAM from an iNES 1.0
+    // header that doesn't mention it. This is here so a ROM on another board says why it failed.
+    if emulator
+        .nes
+        .as_mut()
+        .unwrap()
+        .cart
+        .read_prg_ram(0x6000)
+        .is_none()
+    {
+        return Outcome::Fail("no PRG RAM at $6000 to report the result in".to_owned());
+    }
+    let mut reset_countdown = None;
+    for _ in 0..BLARGG_TIMEOUT_FRAMES {
+        emulator.run_frame(FrameInput::default());
+        let nes = emulator.nes.as_mut().unwrap();
+
+        let signature: Vec<u8> = (0x6001..=0x6003)
+            .map(|addr| peek_prg_ram(nes, addr))
+            .collect();
+        if signature != BLARGG_SIGNATURE {
+            continue;
+        }
+        match peek_prg_ram(nes, 0x6000) {
+            0x80 => {}
+            0x81 => match reset_countdown {
+                Some(0) => {
+                    reset_countdown = None;
+                    emulator.reset();
+                }
+                Some(frames) => reset_countdown = Some(frames - 1),
+                None => reset_countdown = Some(BLARGG_RESET_DELAY_FRAMES),
+            },
+            0 => return Outcome::Pass,
+            code => {
+                let message = blargg_message(nes);
+                return Outcome::Fail(format!("result {code}: {}", message.trim()));
+            }
+        }
+    }
+    Outcome::Fail("timed out".to_owned())
+}
+
+fn run_frame_hash(emulator: &mut Emulator, name: &str, frames: u32, crc: Option<u32>) -> Outcome {
+    let mut video = Vec::new();
+    for _ in 0..frames {
+        video = emulator.run_frame(FrameInput::default()).video;
+    }
+    let frame_crc = crc32fast::hash(&video);
+    if crc == Some(frame_crc) {
+        return Outcome::Pass;
+    }
+    // Saved so it can be checked by eye before pinning its CRC
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name.replace('/', "_") + ".png");
+    let image = RgbaImage::from_raw(FRAME_WIDTH as u32, FRAME_HEIGHT as u32, video)
+        .expect("Frames are FRAME_WIDTH x FRAME_HEIGHT");
+    if let Err(err) = image.save(&path) {
+        println!("Couldn't save {}: {err}", path.display());
+    }
+    match crc {
+        Some(crc) => Outcome::Fail(format!(
+            "frame CRC is {frame_crc:08X}, expected {crc:08X}, see {}",
+            path.display()
+        )),
+        None => Outcome::Fail(format!(
+            "no CRC pinned yet, frame CRC is {frame_crc:08X}, see {}",
+            path.display()
+        )),
+    }
+}
+
+fn run_legacy_blargg(emulator: &mut Emulator) -> Outcome {
+    for _ in 0..BLARGG_TIMEOUT_FRAMES {
+        emulator.run_frame(FrameInput::default());
+        // RAM starts cleared, so anything else means the test has finished
+        match emulator.nes.as_ref().unwrap().wram[LEGACY_BLARGG_RESULT] {
+            0 => {}
+            1 => return Outcome::Pass,
+            code => return Outcome::Fail(format!("result {code}")),
+        }
+    }
+    Outcome::Fail(format!(
+        "never wrote a result to ${LEGACY_BLARGG_RESULT:04X}"
+    ))
+}
+
+fn blargg_message(nes: &mut Nes) -> String {
+    let text: Vec<u8> = (0x6004..=0x7FFF)
+        .map(|addr| peek_prg_ram(nes, addr))
+        .take_while(|&byte| byte != 0)
+        .collect();
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+fn peek_prg_ram(nes: &mut Nes, addr: u16) -> u8 {
+    nes.cart.read_prg_ram(addr).unwrap_or(0)
+}